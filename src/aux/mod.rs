@@ -1,10 +1,19 @@
 //! Auxiliary FST serializing and deserializing functions
-use memmap::Mmap;
-use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::fs::File;
 use std::io::{Seek, Write};
 use anyhow::{Context, Result};
+use crate::indexing::docids::{check_docid_length, DOCID_RECORD_LENGTH};
+
+/// Pads a docid to the fixed-size records of the docids data file, filling it with NULs. Fails if the docid doesn't fit
+pub fn padded_docid(docid: &str) -> Result<[u8; DOCID_RECORD_LENGTH]> {
+    check_docid_length(docid)?;
+
+    let mut doc_id_bytes = [0; DOCID_RECORD_LENGTH];
+    doc_id_bytes[..docid.len()].copy_from_slice(docid.as_bytes());
+
+    Ok(doc_id_bytes)
+}
 
 /// Serializes a value using bincode
 pub fn serialize_value<T>(value: &T) -> Vec<u8>
@@ -31,33 +40,23 @@ pub fn write_buffer_to_binary_file(
     Ok((start_position, end_position))
 }
 
-/// Write a bincode-serializable value to the file, returning its starting and ending positions
-pub fn write_value_to_binary_file<T>(file: &mut File, value: &T) -> Result<(u64, u64)>
-where
-    T: Serialize,
-{
-    let start_position = file.stream_position()
-        .with_context(|| "Failed to read binary file when writing to postings")?;
-
-    let serialized_value = bincode::serialize(value)
-        .with_context(|| "Failed to serialize extraneous value when writing to postings")?;
-    file.write_all(&serialized_value)
-        .with_context(|| "Failed to write serialized value to postings")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let end_position = file.stream_position()
-        .with_context(|| "Failed to read binary file when writing to postings")?;
+    #[test]
+    fn docids_are_padded_with_nuls() {
+        let padded = padded_docid("d1").unwrap();
 
-    Ok((start_position, end_position))
-}
+        assert_eq!(&padded[..3], b"d1\0");
+        assert!(padded[2..].iter().all(|byte| *byte == 0));
+        assert_eq!(padded_docid(&"x".repeat(DOCID_RECORD_LENGTH)).unwrap(), [b'x'; DOCID_RECORD_LENGTH]);
+    }
 
-/// Read a value of type T from a Mmapped file, given its starting and ending position
-pub fn read_value_from_mmap<T>(file: &Mmap, start: u64, end: u64) -> Result<T>
-where
-    T: DeserializeOwned,
-{
-    let slice = &file[start as usize..end as usize];
-    let deserialized_value: T = bincode::deserialize(slice)
-        .with_context(|| "Failed to deserialize extraneous value when writing to postings")?;
+    #[test]
+    fn long_docids_are_rejected() {
+        let docid = "é".repeat(DOCID_RECORD_LENGTH / 2 + 1);
 
-    Ok(deserialized_value)
+        assert_eq!(padded_docid(&docid).unwrap_err().to_string(), format!("docid length exceeded for document {}", docid));
+    }
 }
@@ -0,0 +1,191 @@
+// Manifest of the live segments of an index. Segments are immutable once written, so adding documents to an index
+// means writing a new segment and then atomically replacing the manifest with one that includes it. It also records the
+// analyzer of every field, so that queries can be analyzed like the indexed fields
+
+use crate::index_directory::IndexDirectory;
+use crate::tokenizer::Analyzer;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SegmentInfo {
+    pub name: String,
+    pub n_docs: usize,
+    // Documents of the segment which have been deleted (or replaced in a newer segment)
+    #[serde(default)]
+    pub n_deleted: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Manifest {
+    pub segments: Vec<SegmentInfo>,
+    next_segment_id: u64,
+    // Index key -> Analyzer of the field. Missing for indexes written before the analyzers were configurable
+    #[serde(default)]
+    analyzers: HashMap<String, Analyzer>,
+}
+
+impl Manifest {
+    /// Loads the index manifest, or an empty one if the index has no segments yet
+    pub fn load(index_directory: &IndexDirectory) -> Result<Self> {
+        let manifest_path = index_directory.manifest_path();
+        if !manifest_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let manifest = serde_json::from_reader(io::BufReader::new(File::open(&manifest_path)?))
+            .with_context(|| format!("Failed to read manifest {:?}", manifest_path))?;
+
+        Ok(manifest)
+    }
+
+    /// Reserves the name of a new segment
+    pub fn next_segment_name(&mut self) -> String {
+        let segment_name = format!("segment_{}", self.next_segment_id);
+        self.next_segment_id += 1;
+
+        segment_name
+    }
+
+    pub fn add_segment(&mut self, segment_info: SegmentInfo) {
+        self.segments.push(segment_info);
+    }
+
+    /// Replaces several segments with the one they were merged into, at the position of the first of them. The merged
+    /// segment may be missing if all of their documents were deleted
+    pub fn replace_segments(&mut self, segment_names: &[String], merged_segment_info: Option<SegmentInfo>) {
+        let position = self.segments.iter()
+            .position(|segment_info| segment_names.contains(&segment_info.name))
+            .unwrap_or(self.segments.len());
+
+        self.segments.retain(|segment_info| !segment_names.contains(&segment_info.name));
+        if let Some(merged_segment_info) = merged_segment_info {
+            self.segments.insert(position.min(self.segments.len()), merged_segment_info);
+        }
+    }
+
+    /// Analyzer the field was indexed with
+    pub fn analyzer(&self, index_key: &str) -> Analyzer {
+        self.analyzers.get(index_key).cloned().unwrap_or_default()
+    }
+
+    /// Records the analyzers of the fields. Their analyzers can't change while the index has segments, since the
+    /// tokens of the existing segments wouldn't be the ones queries are analyzed into
+    pub fn set_analyzers(&mut self, analyzers: &HashMap<String, Analyzer>) -> Result<()> {
+        if !self.segments.is_empty() {
+            for (index_key, analyzer) in analyzers {
+                let index_analyzer = self.analyzer(index_key);
+                if index_analyzer != *analyzer {
+                    return Err(anyhow!(format!(
+                        "Field {} was indexed with the analyzer {:?}, and can't be indexed with {:?}",
+                        index_key,
+                        index_analyzer,
+                        analyzer
+                    )));
+                }
+            }
+        }
+
+        self.analyzers.extend(analyzers.iter().map(|(index_key, analyzer)| (index_key.clone(), analyzer.clone())));
+
+        Ok(())
+    }
+
+    pub fn get_segment_mut(&mut self, segment_name: &str) -> Option<&mut SegmentInfo> {
+        self.segments.iter_mut().find(|segment_info| segment_info.name == segment_name)
+    }
+
+    /// Writes the manifest to a temporary file and renames it, so readers never see a partially written one
+    pub fn write(&self, index_directory: &IndexDirectory) -> Result<()> {
+        let manifest_path = index_directory.manifest_path();
+        let tmp_manifest_path = manifest_path.with_extension("json.tmp");
+
+        let manifest_json = serde_json::to_string(self)?;
+        let mut wtr = io::BufWriter::new(File::create(&tmp_manifest_path)?);
+        wtr.write_all(manifest_json.as_bytes())?;
+        wtr.flush()?;
+        drop(wtr);
+
+        fs::rename(&tmp_manifest_path, &manifest_path)
+            .with_context(|| format!("Failed to replace manifest {:?}", manifest_path))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_index::TestIndex;
+
+    fn segment_info(name: &str, n_docs: usize, n_deleted: usize) -> SegmentInfo {
+        SegmentInfo { name: name.to_string(), n_docs, n_deleted }
+    }
+
+    fn segments(manifest: &Manifest) -> Vec<(String, usize, usize)> {
+        manifest.segments.iter()
+            .map(|segment_info| (segment_info.name.clone(), segment_info.n_docs, segment_info.n_deleted))
+            .collect()
+    }
+
+    #[test]
+    fn segments_keep_their_order_across_reloads() {
+        let index = TestIndex::new("manifest_reload");
+        let mut manifest = Manifest::load(&index.directory).unwrap();
+        assert!(manifest.segments.is_empty());
+
+        for (n_docs, n_deleted) in [(3, 0), (5, 2), (1, 1)] {
+            let segment_name = manifest.next_segment_name();
+            manifest.add_segment(segment_info(&segment_name, n_docs, n_deleted));
+        }
+        manifest.write(&index.directory).unwrap();
+
+        let mut reloaded_manifest = Manifest::load(&index.directory).unwrap();
+        let expected_segments = [
+            ("segment_0".to_string(), 3, 0),
+            ("segment_1".to_string(), 5, 2),
+            ("segment_2".to_string(), 1, 1),
+        ];
+        assert_eq!(segments(&reloaded_manifest), expected_segments);
+        // Segment names are never reused, even after a reload
+        assert_eq!(reloaded_manifest.next_segment_name(), "segment_3");
+        assert!(!index.directory.manifest_path().with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn merged_segments_take_the_place_of_the_first_one() {
+        let mut manifest = Manifest::default();
+        for segment_name in ["segment_0", "segment_1", "segment_2", "segment_3"] {
+            manifest.add_segment(segment_info(segment_name, 2, 1));
+        }
+
+        let merged_segment_names = ["segment_2".to_string(), "segment_1".to_string()];
+        manifest.replace_segments(&merged_segment_names, Some(segment_info("segment_4", 2, 0)));
+        let expected_segments = [
+            ("segment_0".to_string(), 2, 1),
+            ("segment_4".to_string(), 2, 0),
+            ("segment_3".to_string(), 2, 1),
+        ];
+        assert_eq!(segments(&manifest), expected_segments);
+
+        // Merging segments with only deleted documents leaves nothing in their place
+        manifest.replace_segments(&["segment_0".to_string(), "segment_3".to_string()], None);
+        assert_eq!(segments(&manifest), [("segment_4".to_string(), 2, 0)]);
+    }
+
+    #[test]
+    fn manifests_of_older_indexes_are_loaded() {
+        let index = TestIndex::new("manifest_older");
+        fs::write(index.directory.manifest_path(), r#"{"segments":[{"name":"segment_0","n_docs":4}],"next_segment_id":1}"#).unwrap();
+
+        let manifest = Manifest::load(&index.directory).unwrap();
+
+        assert_eq!(segments(&manifest), [("segment_0".to_string(), 4, 0)]);
+        assert_eq!(manifest.analyzer("body"), Analyzer::default());
+    }
+}
@@ -0,0 +1,200 @@
+// Location of an index on disk. An index is made of immutable segments, each one stored in its own subdirectory, and
+// a manifest listing the live ones. Every segment file (postings, lengths, stored fields, stats...) is resolved
+// through a SegmentDirectory, so several indexes can live side by side and be opened from any path
+
+mod manifest;
+
+pub use self::manifest::{Manifest, SegmentInfo};
+
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct IndexDirectory {
+    path: PathBuf,
+}
+
+impl IndexDirectory {
+    /// Creates the directory (and its parents) if needed, for writing a new index into it
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create index directory {:?}", path))?;
+
+        Ok(Self { path })
+    }
+
+    /// Opens an already existing index directory
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if !path.is_dir() {
+            return Err(anyhow!(format!("Index directory {:?} does not exist", path)));
+        }
+
+        Ok(Self { path })
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        self.path.join("manifest.json")
+    }
+
+    pub fn segment(&self, segment_name: &str) -> SegmentDirectory {
+        SegmentDirectory { path: self.path.join(segment_name) }
+    }
+
+    /// Creates the directory for a new segment, which won't be visible to readers until added to the manifest
+    pub fn create_segment(&self, segment_name: &str) -> Result<SegmentDirectory> {
+        let segment_directory = self.segment(segment_name);
+        fs::create_dir_all(&segment_directory.path)
+            .with_context(|| format!("Failed to create segment directory {:?}", segment_directory.path))?;
+
+        Ok(segment_directory)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SegmentDirectory {
+    path: PathBuf,
+}
+
+impl SegmentDirectory {
+    pub fn postings_index_path(&self, index_key: &str) -> PathBuf {
+        self.path.join(format!("postings_index_{}.fst", index_key))
+    }
+
+    pub fn postings_data_path(&self, index_key: &str) -> PathBuf {
+        self.path.join(format!("postings_data_{}.bin", index_key))
+    }
+
+    /// FST of the field's tokens with their characters reversed, for leading-wildcard queries
+    pub fn reversed_postings_index_path(&self, index_key: &str) -> PathBuf {
+        self.path.join(format!("reversed_postings_index_{}.fst", index_key))
+    }
+
+    pub fn lengths_index_path(&self, index_key: &str) -> PathBuf {
+        self.path.join(format!("lengths_index_{}.fst", index_key))
+    }
+
+    /// FST of the number of documents containing each token in any field
+    pub fn doc_frequencies_index_path(&self) -> PathBuf {
+        self.path.join("doc_frequencies_index.fst")
+    }
+
+    pub fn docids_index_path(&self) -> PathBuf {
+        self.path.join("docids_index.fst")
+    }
+
+    pub fn docids_data_path(&self) -> PathBuf {
+        self.path.join("docids_data.bin")
+    }
+
+    /// Block index of the stored fields, with the first doc id and data file position of every block
+    pub fn stored_fields_index_path(&self) -> PathBuf {
+        self.path.join("stored_fields_index.bin")
+    }
+
+    pub fn stored_fields_data_path(&self) -> PathBuf {
+        self.path.join("stored_fields_data.bin")
+    }
+
+    pub fn avg_lengths_index_path(&self) -> PathBuf {
+        self.path.join("avg_lengths_index.fst")
+    }
+
+    pub fn stats_path(&self) -> PathBuf {
+        self.path.join("index_stats.json")
+    }
+
+    pub fn deleted_docs_path(&self) -> PathBuf {
+        self.path.join("deleted_docs.json")
+    }
+
+    /// Temporary directory for the sorted runs spilled to disk while indexing with a memory budget
+    pub fn spimi_runs_path(&self) -> PathBuf {
+        self.path.join("spimi_runs")
+    }
+
+    pub fn spimi_run_path(&self, index_key: &str, run: usize) -> PathBuf {
+        self.spimi_runs_path().join(format!("run_{}_{}.bin", run, index_key))
+    }
+
+    /// Removes the segment's files, for segments that were never added to the manifest
+    pub fn remove(&self) -> Result<()> {
+        fs::remove_dir_all(&self.path)
+            .with_context(|| format!("Failed to remove segment directory {:?}", self.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_index::{body_docs, TestIndex};
+
+    #[test]
+    fn directories_are_created_with_their_parents() {
+        let index = TestIndex::new("index_directory_create");
+        let nested_path = index.path().join("nested").join("index");
+
+        let index_directory = IndexDirectory::create(&nested_path).unwrap();
+
+        assert!(nested_path.is_dir());
+        assert_eq!(index_directory.manifest_path(), nested_path.join("manifest.json"));
+        // Creating it again keeps it as it is
+        fs::write(nested_path.join("manifest.json"), "{}").unwrap();
+        IndexDirectory::create(&nested_path).unwrap();
+        assert_eq!(fs::read_to_string(nested_path.join("manifest.json")).unwrap(), "{}");
+    }
+
+    #[test]
+    fn existing_directories_are_opened() {
+        let index = TestIndex::new("index_directory_open");
+        index.indexer(&["body"]).index(body_docs(&[("d1", "rust")])).unwrap();
+
+        let index_directory = IndexDirectory::open(index.path()).unwrap();
+        let manifest = Manifest::load(&index_directory).unwrap();
+
+        assert_eq!(manifest.segments.len(), 1);
+        assert!(index_directory.segment(&manifest.segments[0].name).docids_index_path().exists());
+    }
+
+    #[test]
+    fn missing_directories_are_not_opened() {
+        let index = TestIndex::new("index_directory_missing");
+        let missing_path = index.path().join("missing");
+        let file_path = index.path().join("file");
+        fs::write(&file_path, "").unwrap();
+
+        let missing_error = IndexDirectory::open(&missing_path).unwrap_err();
+        let file_error = IndexDirectory::open(&file_path).unwrap_err();
+
+        assert_eq!(missing_error.to_string(), format!("Index directory {:?} does not exist", missing_path));
+        assert_eq!(file_error.to_string(), format!("Index directory {:?} does not exist", file_path));
+        assert!(!missing_path.exists());
+    }
+
+    #[test]
+    fn segment_files_are_resolved_in_their_directory() {
+        let index = TestIndex::new("index_directory_segments");
+        let segment_path = index.path().join("segment_3");
+
+        let segment_directory = index.directory.segment("segment_3");
+        // Segments are only created on demand
+        assert!(!segment_path.exists());
+        index.directory.create_segment("segment_3").unwrap();
+        assert!(segment_path.is_dir());
+
+        assert_eq!(segment_directory.postings_index_path("body"), segment_path.join("postings_index_body.fst"));
+        assert_eq!(segment_directory.postings_data_path("body"), segment_path.join("postings_data_body.bin"));
+        assert_eq!(segment_directory.reversed_postings_index_path("body"), segment_path.join("reversed_postings_index_body.fst"));
+        assert_eq!(segment_directory.lengths_index_path("title"), segment_path.join("lengths_index_title.fst"));
+        assert_eq!(segment_directory.docids_index_path(), segment_path.join("docids_index.fst"));
+        assert_eq!(segment_directory.docids_data_path(), segment_path.join("docids_data.bin"));
+        assert_eq!(segment_directory.stats_path(), segment_path.join("index_stats.json"));
+        assert_eq!(segment_directory.spimi_run_path("body", 2), segment_path.join("spimi_runs").join("run_2_body.bin"));
+
+        segment_directory.remove().unwrap();
+        assert!(!segment_path.exists());
+        assert!(index.path().is_dir());
+    }
+}
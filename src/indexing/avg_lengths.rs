@@ -1,10 +1,11 @@
 // Writer for the average document lengths for each field. Although this uses a FST-backed index, we could simply use
 // a json file or similar, since the number of fields shouldn't be extremely high.
 
+use crate::index_directory::SegmentDirectory;
 use anyhow::{Result};
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::{io, mem};
+use std::io;
 use fst::MapBuilder;
 
 pub struct Avglengths {
@@ -20,17 +21,15 @@ impl Avglengths {
         self.avg_lengths.insert(index_key, avg_length);
     }
 
-    pub fn write_avg_lengths(&self) -> Result<()> {
-        let avg_lengths_file = File::create("avg_lengths_index.fst")?;
+    pub fn write_avg_lengths(&self, segment_directory: &SegmentDirectory) -> Result<()> {
+        let avg_lengths_file = File::create(segment_directory.avg_lengths_index_path())?;
         let wtr = io::BufWriter::new(avg_lengths_file);
 
         let mut build = MapBuilder::new(wtr)?;
         for (index_key, length) in &self.avg_lengths {
             // Dirty trick: Since the FST only allows integers, we can
             // simply write the f64 as an u64
-            let length_as_u64 = unsafe {
-                mem::transmute(length)
-            };
+            let length_as_u64 = length.to_bits();
 
             build.insert(index_key, length_as_u64)?;
         }
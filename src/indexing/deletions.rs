@@ -0,0 +1,65 @@
+// Tombstones for the documents deleted from a segment. Segments are immutable, so deleted documents stay in their
+// postings and lengths files and are skipped at retrieval time, until the segment is merged away
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use anyhow::{Context, Result};
+use crate::index_directory::SegmentDirectory;
+use crate::indexing::docids::DocId;
+
+#[derive(Debug, Default)]
+pub struct DeletedDocs {
+    doc_ids: BTreeSet<DocId>,
+}
+
+impl DeletedDocs {
+    /// Loads the segment's tombstones, or an empty set if none of its documents were deleted
+    pub fn load(segment_directory: &SegmentDirectory) -> Result<Self> {
+        let deleted_docs_path = segment_directory.deleted_docs_path();
+        if !deleted_docs_path.exists() {
+            return Ok(Self::default());
+        }
+
+        let doc_ids = serde_json::from_reader(io::BufReader::new(File::open(&deleted_docs_path)?))
+            .with_context(|| format!("Failed to read deleted documents {:?}", deleted_docs_path))?;
+
+        Ok(Self { doc_ids })
+    }
+
+    pub fn contains(&self, doc_id: DocId) -> bool {
+        self.doc_ids.contains(&doc_id)
+    }
+
+    /// Marks the doc id as deleted, returning false if it already was
+    pub fn insert(&mut self, doc_id: DocId) -> bool {
+        self.doc_ids.insert(doc_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DocId> {
+        self.doc_ids.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.doc_ids.len()
+    }
+
+    /// Replaces the segment's tombstones file through a rename, so readers never see a partially written one
+    pub fn write(&self, segment_directory: &SegmentDirectory) -> Result<()> {
+        let deleted_docs_path = segment_directory.deleted_docs_path();
+        let tmp_deleted_docs_path = deleted_docs_path.with_extension("json.tmp");
+
+        let deleted_docs_json = serde_json::to_string(&self.doc_ids)?;
+        let mut wtr = io::BufWriter::new(File::create(&tmp_deleted_docs_path)?);
+        wtr.write_all(deleted_docs_json.as_bytes())?;
+        wtr.flush()?;
+        drop(wtr);
+
+        fs::rename(&tmp_deleted_docs_path, &deleted_docs_path)
+            .with_context(|| format!("Failed to replace deleted documents {:?}", deleted_docs_path))?;
+
+        Ok(())
+    }
+}
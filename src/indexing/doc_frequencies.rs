@@ -0,0 +1,69 @@
+// Writer for the number of documents containing each token in any of their fields, which BM25F takes as the token's
+// document frequency. The postings header of every field already holds the field's document frequency, but documents
+// containing the token in several fields can't be told apart from it, so this FST is written once the postings of every
+// field are, streaming the union of the fields' tokens and only decoding the postings of the ones found in several
+// fields
+
+use crate::index_directory::SegmentDirectory;
+use crate::indexing::docids::DocId;
+use crate::indexing::postings_codec::{PostingsCodec, CURRENT_POSTINGS_CODEC_VERSION};
+use anyhow::Result;
+use fst::map::OpBuilder;
+use fst::{Map, MapBuilder, Streamer};
+use memmap::Mmap;
+use std::collections::{BTreeSet, HashSet};
+use std::fs::File;
+use std::io;
+
+pub fn write_doc_frequencies(segment_directory: &SegmentDirectory,
+                             index_keys: &[String],
+                             positional_index_keys: &HashSet<String>) -> Result<()> {
+    let codec = PostingsCodec::from_version(CURRENT_POSTINGS_CODEC_VERSION)?;
+
+    // Postings FST, data file and whether positions are stored, for the fields with any token
+    let mut fields = Vec::new();
+    for index_key in index_keys {
+        let postings_data_file = File::open(segment_directory.postings_data_path(index_key))?;
+        if postings_data_file.metadata()?.len() == 0 {
+            continue;
+        }
+
+        let postings_map = unsafe {
+            Map::new(Mmap::map(&File::open(segment_directory.postings_index_path(index_key))?)?)?
+        };
+        let postings_data = unsafe { Mmap::map(&postings_data_file)? };
+        fields.push((postings_map, postings_data, positional_index_keys.contains(index_key)));
+    }
+
+    let mut op_builder = OpBuilder::new();
+    for (postings_map, _, _) in &fields {
+        op_builder.push(postings_map.stream());
+    }
+
+    let mut builder = MapBuilder::new(io::BufWriter::new(File::create(segment_directory.doc_frequencies_index_path())?))?;
+    let mut union = op_builder.union();
+    while let Some((token, postings_positions)) = union.next() {
+        let doc_frequency = match postings_positions {
+            [postings_position] => {
+                let (_, postings_data, with_positions) = &fields[postings_position.index];
+                codec.cursor(&postings_data[postings_position.value as usize..], *with_positions)?.n_postings()
+            }
+            _ => {
+                let mut doc_ids: BTreeSet<DocId> = BTreeSet::new();
+                for postings_position in postings_positions {
+                    let (_, postings_data, with_positions) = &fields[postings_position.index];
+                    let mut cursor = codec.cursor(&postings_data[postings_position.value as usize..], *with_positions)?;
+                    while let Some((doc_id, _)) = cursor.next()? {
+                        doc_ids.insert(doc_id);
+                    }
+                }
+                doc_ids.len()
+            }
+        };
+
+        builder.insert(token, doc_frequency as u64)?;
+    }
+    builder.finish()?;
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+// Writer for the docid dictionary of a segment. Documents are assigned dense u32 ids in indexing order, which are the
+// ones stored in the postings and lengths. The dictionary maps them in both directions: an FST maps external docids
+// to their id, and a data file holds the external docids as fixed-size (NUL-padded) records, indexed by id
+
+use crate::aux;
+use crate::index_directory::SegmentDirectory;
+use anyhow::{anyhow, Result};
+use fst::MapBuilder;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+
+pub type DocId = u32;
+
+pub const DOCID_RECORD_LENGTH: usize = 32;
+
+/// Fails if a docid (in bytes) doesn't fit in the records of the docids data file
+pub fn check_docid_length(docid: &str) -> Result<()> {
+    if docid.len() > DOCID_RECORD_LENGTH {
+        return Err(anyhow!(format!("docid length exceeded for document {}", docid)));
+    }
+
+    Ok(())
+}
+
+pub struct Docids {
+    docids: Vec<String>,
+}
+
+impl Docids {
+    pub fn new() -> Self {
+        Self { docids: Vec::new() }
+    }
+
+    /// Assigns the next internal id to an external docid
+    pub fn add_docid(&mut self, docid: String) -> DocId {
+        self.docids.push(docid);
+
+        (self.docids.len() - 1) as DocId
+    }
+
+    pub fn n_docs(&self) -> usize {
+        self.docids.len()
+    }
+
+    /// The external docids, in internal id order
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.docids.iter().map(String::as_str)
+    }
+
+    /// Writes both directions of the dictionary. If an external docid was added more than once, only its last id is
+    /// kept in the dictionary, and the superseded ids are returned so they can be marked as deleted
+    pub fn write_docids(&self, segment_directory: &SegmentDirectory) -> Result<Vec<DocId>> {
+        let mut data_wtr = io::BufWriter::new(File::create(segment_directory.docids_data_path())?);
+        let mut ordered_docids: BTreeMap<&str, DocId> = BTreeMap::new();
+        let mut superseded_doc_ids = Vec::new();
+
+        for (doc_id, docid) in self.docids.iter().enumerate() {
+            data_wtr.write_all(&aux::padded_docid(docid)?)?;
+
+            if let Some(superseded_doc_id) = ordered_docids.insert(docid, doc_id as DocId) {
+                superseded_doc_ids.push(superseded_doc_id);
+            }
+        }
+        data_wtr.flush()?;
+
+        let index_wtr = io::BufWriter::new(File::create(segment_directory.docids_index_path())?);
+        let mut build = MapBuilder::new(index_wtr)?;
+        for (docid, doc_id) in ordered_docids {
+            build.insert(docid, doc_id as u64)?;
+        }
+        build.finish()?;
+
+        Ok(superseded_doc_ids)
+    }
+}
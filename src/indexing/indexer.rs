@@ -1,52 +1,132 @@
 // Main indexing process with a naive multi-threaded implementation (create per-thread postings and lengths, then merge
-// them). This can cause OOMs if the collection is massively large
+// them). This can cause OOMs if the collection is massively large, in which case the single-pass indexing with a
+// memory budget (see spimi.rs) should be used instead
+//
+// Every indexing run writes a new immutable segment, which is added to the index manifest once fully written. This
+// way documents can be added to an existing index without rewriting it. Deletions are recorded as per-segment
+// tombstones, and updates are a deletion from the existing segments plus the indexing of the new document version.
+// Segments are merged (dropping their deleted documents) on demand, to keep their number bounded
+//
+// Fields can optionally store the positions of their tokens, which are needed for phrase queries, and their original
+// text, so that results can be returned with their contents. Every field is analyzed into tokens by its own analyzer,
+// which is recorded in the manifest and can't change while the index has segments
 
 use crate::indexing::avg_lengths::Avglengths;
+use crate::indexing::deletions::DeletedDocs;
+use crate::indexing::doc_frequencies;
+use crate::indexing::docids::{check_docid_length, DocId, Docids};
 use crate::indexing::lengths::Lengths;
+use crate::indexing::merge_policy::TieredMergePolicy;
+use crate::indexing::merger::SegmentMerger;
 use crate::indexing::postings::Postings;
+use crate::indexing::spimi::SpimiPostings;
 use crate::indexing::stats::Stats;
-use crate::tokenizer;
-use anyhow::{Result};
+use crate::indexing::stored_fields::{StoredDoc, StoredFieldsWriter};
+use crate::index_directory::{IndexDirectory, Manifest, SegmentDirectory, SegmentInfo};
+use crate::retrieval::segment::Segment;
+use crate::tokenizer::Analyzer;
+use anyhow::Result;
 use crossbeam_channel::bounded;
-use std::collections::HashMap;
+use fst::Map;
+use memmap::Mmap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
 use std::thread;
 use std::time::Duration;
 use indicatif::{ProgressBar, ProgressStyle};
 
 pub struct Indexer {
     field_keys: HashMap<String, String>,
+    index_directory: IndexDirectory,
+    positional_index_keys: HashSet<String>,
+    stored_index_keys: HashSet<String>,
+    // Fields not found here use the default analyzer
+    analyzers: HashMap<String, Analyzer>,
 }
 
-type IndexJob = (String, HashMap<String, String>);
+type IndexJob = (DocId, HashMap<String, String>);
 type IndexResults = (HashMap<String, Postings>, HashMap<String, Lengths>);
 
 impl Indexer {
-    pub fn new(field_keys: HashMap<String, String>) -> Self {
-        Self { field_keys }
+    pub fn new(field_keys: HashMap<String, String>, index_directory: IndexDirectory) -> Self {
+        Self {
+            field_keys,
+            index_directory,
+            positional_index_keys: HashSet::new(),
+            stored_index_keys: HashSet::new(),
+            analyzers: HashMap::new(),
+        }
+    }
+
+    /// Also stores the positions of the field's tokens, for the segments written from now on
+    pub fn store_positions(&mut self, index_key: &str) {
+        self.positional_index_keys.insert(index_key.to_string());
+    }
+
+    /// Also stores the original text of the field, for the segments written from now on
+    pub fn store_fields(&mut self, index_key: &str) {
+        self.stored_index_keys.insert(index_key.to_string());
+    }
+
+    /// Analyzes the field with a custom analyzer. Must match the field's analyzer of the existing segments, if any
+    pub fn set_analyzer(&mut self, index_key: &str, analyzer: Analyzer) {
+        self.analyzers.insert(index_key.to_string(), analyzer);
+    }
+
+    /// Analyzer of every field
+    fn field_analyzers(&self) -> HashMap<String, Analyzer> {
+        self.index_keys()
+            .into_iter()
+            .map(|index_key| {
+                let analyzer = self.analyzers.get(&index_key).cloned().unwrap_or_default();
+                (index_key, analyzer)
+            })
+            .collect()
+    }
+
+    /// The stored fields of a document
+    fn stored_doc(&self, fields_text: &HashMap<String, String>) -> StoredDoc {
+        fields_text.iter()
+            .filter(|(index_key, _)| self.stored_index_keys.contains(*index_key))
+            .map(|(index_key, field_text)| (index_key.clone(), field_text.clone()))
+            .collect()
+    }
+
+    fn index_keys(&self) -> Vec<String> {
+        self.field_keys.values().cloned().collect()
+    }
+
+    fn progress_spinner() -> ProgressBar {
+        let fancy_spinner = ProgressBar::new_spinner();
+        fancy_spinner.enable_steady_tick(Duration::from_millis(120));
+        fancy_spinner.set_style(
+            ProgressStyle::with_template("{spinner:.red} {msg}")
+                .unwrap()
+                .tick_strings(&[
+                    "▹▹▹▹▹",
+                    "▸▹▹▹▹",
+                    "▹▸▹▹▹",
+                    "▹▹▸▹▹",
+                    "▹▹▹▸▹",
+                    "▹▹▹▹▸",
+                    "▪▪▪▪▪",
+                ]),
+        );
+
+        fancy_spinner
     }
 
     fn index_worker_function(
         postings_writers: &mut HashMap<String, Postings>,
         lengths_writers: &mut HashMap<String, Lengths>,
+        analyzers: &HashMap<String, Analyzer>,
         index_keys: Vec<String>,
-        docid: String,
+        doc_id: DocId,
         fields_text: HashMap<String, String>,
     ) {
-        // Only take the first 32 characters from the docid
-        let mut doc_id_bytes: [u8; 32] = [0; 32];
-
-        for (byte_i, docid_byte) in docid.as_bytes().iter().enumerate() {
-            doc_id_bytes[byte_i] = *docid_byte;
-        }
-
-        let docid = std::str::from_utf8(&doc_id_bytes).unwrap().to_string();
-
-        for index_key in &(*index_keys) {
+        for index_key in &index_keys {
             if let Some(field_text) = fields_text.get(index_key) {
-                let tokens = tokenizer::tokenize(field_text)
-                    .iter()
-                    .map(|t| t.clean())
-                    .collect::<Vec<String>>();
+                let tokens = analyzers.get(index_key).unwrap().analyze(field_text);
 
                 for token in &tokens {
                     // Get the PostingsWriter for this field
@@ -54,22 +134,22 @@ impl Indexer {
                         .get_mut(index_key)
                         .unwrap()
                         // And count the token for the docid
-                        .add_token_to_docid(&docid, token);
+                        .add_token_to_docid(doc_id, &token.text, token.position);
                 }
 
                 lengths_writers
                     .get_mut(index_key)
                     .unwrap()
-                    .add_length(docid.clone(), tokens.len() as u64);
+                    .add_length(doc_id, tokens.len() as u64);
             }
         }
     }
 
+    /// Indexes the documents in a new segment. Documents which were already in the index are replaced by their new
+    /// version
     pub fn index(&mut self, docs_iter: impl Iterator<Item = Option<(String, HashMap<String, String>)>>) -> Result<()> {
-        let mut index_keys = Vec::new();
-        for (_, index_key) in self.field_keys.clone().into_iter() {
-            index_keys.push(index_key);
-        }
+        let index_keys = self.index_keys();
+        let (segment_name, segment_directory) = self.create_segment()?;
 
         let (jobs_channel_send, jobs_channel_send_recv) =
             bounded::<Option<IndexJob>>(num_cpus::get());
@@ -89,6 +169,8 @@ impl Indexer {
             );
 
             let ik = index_keys.clone();
+            let positional_ik = self.positional_index_keys.clone();
+            let analyzers = self.field_analyzers();
             //let c = postings_writers.clone();
             //let l = length_writers.clone();
 
@@ -97,7 +179,8 @@ impl Indexer {
                 let mut lengths_writers = HashMap::new();
 
                 for index_key in &ik {
-                    postings_writers.insert(index_key.clone(), Postings::new(index_key.clone()));
+                    let with_positions = positional_ik.contains(index_key);
+                    postings_writers.insert(index_key.clone(), Postings::new(index_key.clone(), with_positions));
                     lengths_writers.insert(index_key.clone(), Lengths::new(index_key.clone()));
                 }
 
@@ -106,6 +189,7 @@ impl Indexer {
                         Indexer::index_worker_function(
                             &mut postings_writers,
                             &mut lengths_writers,
+                            &analyzers,
                             ik.clone(),
                             job.0,
                             job.1,
@@ -121,39 +205,38 @@ impl Indexer {
         }
 
         println!("Starting indexing...");
-        let fancy_spinner = ProgressBar::new_spinner();
-        fancy_spinner.enable_steady_tick(Duration::from_millis(120));
-        fancy_spinner.set_style(
-            ProgressStyle::with_template("{spinner:.red} {msg}")
-                .unwrap()
-                .tick_strings(&[
-                    "▹▹▹▹▹",
-                    "▸▹▹▹▹",
-                    "▹▸▹▹▹",
-                    "▹▹▸▹▹",
-                    "▹▹▹▸▹",
-                    "▹▹▹▹▸",
-                    "▪▪▪▪▪",
-                ]),
-        );
-
-        let mut n_docs: usize = 0;
+        let fancy_spinner = Self::progress_spinner();
+
+        // Internal doc ids are assigned here, in the order the documents are read, which is also the order the
+        // stored fields must be written in
+        let mut docids = Docids::new();
+        let mut stored_fields_writer = StoredFieldsWriter::create(&segment_directory)?;
+        // Documents with invalid docids stop the indexing, once the workers are done
+        let mut docid_error = None;
         for (docid, fields_text) in docs_iter.flatten() {
+            if let Err(error) = check_docid_length(&docid) {
+                docid_error = Some(error);
+                break;
+            }
+
+            let doc_id = docids.add_docid(docid);
+            stored_fields_writer.add_doc(doc_id, self.stored_doc(&fields_text))?;
+
             // Since the queue is bounded, it's a decent progress approximation
-            n_docs += 1;
-            fancy_spinner.set_message(format!("Indexed document {n_docs}"));
+            fancy_spinner.set_message(format!("Indexed document {}", docids.n_docs()));
 
-            jobs_channel_send.send(Some((docid, fields_text)))?;
+            jobs_channel_send.send(Some((doc_id, fields_text)))?;
         }
         fancy_spinner.finish_with_message("All documents processed! Writing index files...");
+        stored_fields_writer.finish()?;
 
         // Join all worker postings and lengths and write them
-        let mut avg_lengths_writer = Avglengths::new();
         let mut postings_writers = HashMap::new();
         let mut lengths_writers = HashMap::new();
 
         for index_key in &index_keys {
-            postings_writers.insert(index_key.clone(), Postings::new(index_key.clone()));
+            let with_positions = self.positional_index_keys.contains(index_key);
+            postings_writers.insert(index_key.clone(), Postings::new(index_key.clone(), with_positions));
             lengths_writers.insert(index_key.clone(), Lengths::new(index_key.clone()));
         }
 
@@ -171,7 +254,7 @@ impl Indexer {
                 lengths_writers
                     .get_mut(index_key)
                     .unwrap()
-                    .add_lengths(&worker_lengths);
+                    .add_lengths(worker_lengths);
             }
         }
 
@@ -179,20 +262,360 @@ impl Indexer {
             handles.pop().unwrap().join().unwrap();
         }
 
+        if let Some(error) = docid_error {
+            segment_directory.remove()?;
+            return Err(error);
+        }
+
         for index_key in index_keys.iter() {
             postings_writers
                 .get_mut(index_key)
                 .unwrap()
-                .write_postings()?;
-            let avg_length = lengths_writers.get_mut(index_key).unwrap().write_lengths()?;
+                .write_postings(&segment_directory, lengths_writers.get(index_key).unwrap())?;
+        }
+
+        let n_deleted = self.write_segment_files(&segment_directory,
+                                                 &index_keys,
+                                                 &lengths_writers,
+                                                 &docids,
+                                                 &self.positional_index_keys)?;
+        self.commit_segment(segment_name, &segment_directory, &docids, n_deleted)
+    }
+
+    /// Single-pass indexing which keeps (approximately) at most `memory_budget` bytes in memory. Whenever the budget is
+    /// reached, the postings are spilled to disk as sorted runs, which are merged once every document of the segment
+    /// has been processed. The docids, lengths and vocabulary can't be spilled, so once they take half of the budget
+    /// the segment is written and the remaining documents go to a new one. Slower than `index`, but allows indexing
+    /// collections much larger than the available memory. A document with an invalid docid stops the indexing, dropping
+    /// the segment being written, but not the ones already written
+    pub fn index_with_memory_budget(&mut self,
+                                    docs_iter: impl Iterator<Item = Option<(String, HashMap<String, String>)>>,
+                                    memory_budget: usize) -> Result<()> {
+        println!("Starting indexing with a memory budget of {} bytes...", memory_budget);
+        let fancy_spinner = Self::progress_spinner();
+
+        let mut docs_iter = docs_iter.flatten().peekable();
+        let mut n_indexed_docs = 0;
+        loop {
+            n_indexed_docs += self.index_segment_with_memory_budget(&mut docs_iter, memory_budget, &fancy_spinner, n_indexed_docs)?;
+            if docs_iter.peek().is_none() {
+                break;
+            }
+        }
+        fancy_spinner.finish_with_message(format!("All {} documents indexed!", n_indexed_docs));
+
+        Ok(())
+    }
+
+    /// Indexes documents in a new segment until they run out, or the segment takes half of the memory budget. Returns
+    /// the number of indexed documents
+    fn index_segment_with_memory_budget(&mut self,
+                                        docs_iter: &mut impl Iterator<Item = (String, HashMap<String, String>)>,
+                                        memory_budget: usize,
+                                        fancy_spinner: &ProgressBar,
+                                        n_indexed_docs: usize) -> Result<usize> {
+        let index_keys = self.index_keys();
+        let (segment_name, segment_directory) = self.create_segment()?;
+
+        let mut spimi_postings = SpimiPostings::new(&segment_directory, &self.positional_index_keys, memory_budget)?;
+        let analyzers = self.field_analyzers();
+        let mut lengths_writers = HashMap::new();
+        for index_key in &index_keys {
+            lengths_writers.insert(index_key.clone(), Lengths::new(index_key.clone()));
+        }
+
+        let mut docids = Docids::new();
+        let mut stored_fields_writer = StoredFieldsWriter::create(&segment_directory)?;
+        for (docid, fields_text) in docs_iter.by_ref() {
+            if let Err(error) = check_docid_length(&docid) {
+                segment_directory.remove()?;
+                return Err(error);
+            }
+
+            spimi_postings.add_docid(&docid);
+            let doc_id = docids.add_docid(docid);
+            stored_fields_writer.add_doc(doc_id, self.stored_doc(&fields_text))?;
+            fancy_spinner.set_message(format!("Indexed document {}", n_indexed_docs + docids.n_docs()));
+
+            for index_key in &index_keys {
+                if let Some(field_text) = fields_text.get(index_key) {
+                    let tokens = analyzers.get(index_key).unwrap().analyze(field_text);
+                    spimi_postings.add_tokens(index_key, doc_id, &tokens)?;
+
+                    lengths_writers
+                        .get_mut(index_key)
+                        .unwrap()
+                        .add_length(doc_id, tokens.len() as u64);
+                }
+            }
+
+            if spimi_postings.is_segment_full() {
+                break;
+            }
+        }
+        fancy_spinner.set_message(format!("Merging the runs and writing the files of {}...", segment_name));
+        stored_fields_writer.finish()?;
+
+        spimi_postings.write_postings(&index_keys, &lengths_writers)?;
+
+        let n_deleted = self.write_segment_files(&segment_directory,
+                                                 &index_keys,
+                                                 &lengths_writers,
+                                                 &docids,
+                                                 &self.positional_index_keys)?;
+        self.commit_segment(segment_name, &segment_directory, &docids, n_deleted)?;
+
+        Ok(docids.n_docs())
+    }
+
+    /// Writes every segment file but the postings, which must be written beforehand. Returns the number of deleted
+    /// documents, which are the ones superseded by a later document with the same docid
+    fn write_segment_files(&self,
+                           segment_directory: &SegmentDirectory,
+                           index_keys: &[String],
+                           lengths_writers: &HashMap<String, Lengths>,
+                           docids: &Docids,
+                           positional_index_keys: &HashSet<String>) -> Result<usize> {
+        let superseded_doc_ids = docids.write_docids(segment_directory)?;
+        let n_deleted = superseded_doc_ids.len();
+        if n_deleted > 0 {
+            let mut deleted_docs = DeletedDocs::default();
+            for doc_id in superseded_doc_ids {
+                deleted_docs.insert(doc_id);
+            }
+            deleted_docs.write(segment_directory)?;
+        }
+
+        let mut avg_lengths_writer = Avglengths::new();
+        let mut field_n_docs = HashMap::new();
+
+        for index_key in index_keys {
+            let lengths_writer = lengths_writers.get(index_key).unwrap();
+            let avg_length = lengths_writer.write_lengths(segment_directory)?;
 
             avg_lengths_writer.add_avg_length(index_key.clone(), avg_length);
-            avg_lengths_writer.write_avg_lengths()?;
+            field_n_docs.insert(index_key.clone(), lengths_writer.n_docs());
+        }
+        avg_lengths_writer.write_avg_lengths(segment_directory)?;
+
+        doc_frequencies::write_doc_frequencies(segment_directory, index_keys, positional_index_keys)?;
+
+        let stats_writer = Stats::new(docids.n_docs(), field_n_docs, positional_index_keys.clone());
+        stats_writer.write_stats(segment_directory)?;
+
+        Ok(n_deleted)
+    }
+
+    /// Reserves a new segment name in the manifest and creates its directory. Fails if a field's analyzer doesn't match
+    /// the one of the existing segments
+    fn create_segment(&self) -> Result<(String, SegmentDirectory)> {
+        let mut manifest = Manifest::load(&self.index_directory)?;
+        manifest.set_analyzers(&self.field_analyzers())?;
+        let segment_name = manifest.next_segment_name();
+        manifest.write(&self.index_directory)?;
+
+        let segment_directory = self.index_directory.create_segment(&segment_name)?;
+
+        Ok((segment_name, segment_directory))
+    }
+
+    /// Adds a fully written segment to the manifest, making it visible to new retrievers, and deletes the previous
+    /// versions of its documents from the earlier segments. Empty segments are dropped
+    fn commit_segment(&self,
+                      segment_name: String,
+                      segment_directory: &SegmentDirectory,
+                      docids: &Docids,
+                      n_deleted: usize) -> Result<()> {
+        let n_docs = docids.n_docs();
+        if n_docs == 0 {
+            return segment_directory.remove();
         }
 
-        let stats_writer = Stats::new(n_docs);
-        stats_writer.write_stats()?;
+        let mut manifest = Manifest::load(&self.index_directory)?;
+        let earlier_segment_names: Vec<String> = manifest.segments.iter()
+            .map(|segment_info| segment_info.name.clone())
+            .collect();
+        manifest.add_segment(SegmentInfo { name: segment_name, n_docs, n_deleted });
+        manifest.write(&self.index_directory)?;
+
+        // The new versions are committed beforehand, so that the documents never go missing from the index
+        self.delete_from_segments(&docids.iter().collect::<Vec<_>>(), &earlier_segment_names)?;
 
         Ok(())
     }
+
+    /// Deletes a document from every segment containing it. Returns whether a live version of the document was found
+    pub fn delete(&mut self, docid: &str) -> Result<bool> {
+        let manifest = Manifest::load(&self.index_directory)?;
+        let segment_names: Vec<String> = manifest.segments.into_iter()
+            .map(|segment_info| segment_info.name)
+            .collect();
+
+        self.delete_from_segments(&[docid], &segment_names)
+    }
+
+    /// Replaces a document with a new version of its fields. Like indexing it again, since the new version is indexed
+    /// in a new segment before deleting the old one, so the document never goes missing from the index
+    pub fn update(&mut self, docid: &str, fields_text: HashMap<String, String>) -> Result<()> {
+        check_docid_length(docid)?;
+
+        self.index(std::iter::once(Some((docid.to_string(), fields_text))))
+    }
+
+    /// Deletes documents from the given segments. Returns whether a live version of any of them was found
+    fn delete_from_segments(&self, docids: &[&str], segment_names: &[String]) -> Result<bool> {
+        let mut manifest = Manifest::load(&self.index_directory)?;
+        let mut found = false;
+
+        for segment_name in segment_names {
+            let segment_directory = self.index_directory.segment(segment_name);
+            let docids_map = unsafe {
+                Map::new(Mmap::map(&File::open(segment_directory.docids_index_path())?)?)?
+            };
+
+            // Versions of the documents which were already deleted don't count as found
+            let mut deleted_docs = DeletedDocs::load(&segment_directory)?;
+            let mut segment_found = false;
+            for docid in docids {
+                if let Some(doc_id) = docids_map.get(docid) {
+                    segment_found |= deleted_docs.insert(doc_id as DocId);
+                }
+            }
+
+            if segment_found {
+                deleted_docs.write(&segment_directory)?;
+
+                if let Some(segment_info) = manifest.get_segment_mut(segment_name) {
+                    segment_info.n_deleted = deleted_docs.len();
+                }
+                found = true;
+            }
+        }
+
+        manifest.write(&self.index_directory)?;
+
+        Ok(found)
+    }
+
+    /// Merges segments for as long as the merge policy finds the index has too many of them. Returns the number of
+    /// merges performed
+    pub fn merge(&mut self, merge_policy: &TieredMergePolicy) -> Result<usize> {
+        let mut n_merges = 0;
+
+        while let Some(segment_names) = merge_policy.find_merge(&Manifest::load(&self.index_directory)?.segments) {
+            self.merge_segments(&segment_names)?;
+            n_merges += 1;
+        }
+
+        Ok(n_merges)
+    }
+
+    /// Merges every segment into a single one, dropping all the deleted documents
+    pub fn force_merge(&mut self) -> Result<()> {
+        let manifest = Manifest::load(&self.index_directory)?;
+        let has_deletions = manifest.segments.iter().any(|segment_info| segment_info.n_deleted > 0);
+
+        if manifest.segments.len() > 1 || has_deletions {
+            let segment_names: Vec<String> = manifest.segments.into_iter()
+                .map(|segment_info| segment_info.name)
+                .collect();
+            self.merge_segments(&segment_names)?;
+        }
+
+        Ok(())
+    }
+
+    fn merge_segments(&self, segment_names: &[String]) -> Result<()> {
+        let index_keys = self.index_keys();
+        println!("Merging {} segments...", segment_names.len());
+
+        let mut segments = Vec::new();
+        for segment_name in segment_names {
+            segments.push(Segment::open(&index_keys, &self.index_directory.segment(segment_name))?);
+        }
+
+        let merger = SegmentMerger::new(&index_keys, segments)?;
+        let (segment_name, segment_directory) = self.create_segment()?;
+        let n_docs = merger.docids().n_docs();
+
+        let lengths_writers = merger.merge_lengths()?;
+        merger.write_postings(&segment_directory, &lengths_writers)?;
+        merger.write_stored_fields(&segment_directory)?;
+        self.write_segment_files(&segment_directory,
+                                 &index_keys,
+                                 &lengths_writers,
+                                 merger.docids(),
+                                 merger.positional_index_keys())?;
+        drop(merger);
+
+        let merged_segment_info = if n_docs > 0 {
+            Some(SegmentInfo { name: segment_name, n_docs, n_deleted: 0 })
+        } else {
+            segment_directory.remove()?;
+            None
+        };
+
+        let mut manifest = Manifest::load(&self.index_directory)?;
+        manifest.replace_segments(segment_names, merged_segment_info);
+        manifest.write(&self.index_directory)?;
+
+        // Retrievers which already opened the old segments keep their mmaps valid after the removal
+        for segment_name in segment_names {
+            self.index_directory.segment(segment_name).remove()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexing::docids::DOCID_RECORD_LENGTH;
+    use crate::test_index::{bm25f, body_docs, sorted_docids, TestIndex};
+
+    #[test]
+    fn reindexed_documents_replace_their_previous_version() {
+        let index = TestIndex::new("indexer_reindex");
+        let mut indexer = index.indexer(&["body"]);
+        indexer.index(body_docs(&[("d1", "rust"), ("d2", "rust search")])).unwrap();
+        indexer.index(body_docs(&[("d2", "rust index"), ("d3", "rust")])).unwrap();
+        indexer.index_with_memory_budget(body_docs(&[("d3", "search")]), 1024).unwrap();
+
+        let segments = Manifest::load(&index.directory).unwrap().segments;
+        let retriever = index.retriever(&["body"]);
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+        let rust_docids = sorted_docids(retriever.retrieval_multiple_tokens(&vec!["rust".to_string()], &bm25f).unwrap());
+        let search_docids = sorted_docids(retriever.retrieval_multiple_tokens(&vec!["search".to_string()], &bm25f).unwrap());
+
+        let n_docs_deleted: Vec<(usize, usize)> = segments.iter()
+            .map(|segment_info| (segment_info.n_docs, segment_info.n_deleted))
+            .collect();
+        assert_eq!(n_docs_deleted, [(2, 1), (2, 1), (1, 0)]);
+        assert_eq!(rust_docids, ["d1", "d2"]);
+        assert_eq!(search_docids, ["d3"]);
+    }
+
+    #[test]
+    fn long_docids_are_rejected() {
+        let index = TestIndex::new("indexer_long_docids");
+        let long_docid = "d".repeat(DOCID_RECORD_LENGTH + 1);
+        let expected_error = format!("docid length exceeded for document {}", long_docid);
+        let mut indexer = index.indexer(&["body"]);
+        let index_error = indexer.index(body_docs(&[("d1", "rust"), (&long_docid, "search")])).unwrap_err();
+        let budget_error = indexer.index_with_memory_budget(body_docs(&[("d1", "rust"), (&long_docid, "search")]), 1024).unwrap_err();
+        let update_error = indexer.update(&long_docid, HashMap::new()).unwrap_err();
+
+        let n_segments = Manifest::load(&index.directory).unwrap().segments.len();
+        let n_segment_directories = std::fs::read_dir(index.path()).unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().is_dir())
+            .count();
+
+        assert_eq!(index_error.to_string(), expected_error);
+        assert_eq!(budget_error.to_string(), expected_error);
+        assert_eq!(update_error.to_string(), expected_error);
+        // Nothing is left from the failed indexing runs
+        assert_eq!(n_segments, 0);
+        assert_eq!(n_segment_directories, 0);
+    }
 }
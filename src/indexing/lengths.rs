@@ -1,5 +1,8 @@
-// Writer for the document lengths for a given field
+// Writer for the document lengths for a given field. The FST is keyed by the big-endian bytes of the internal doc
+// ids, so that the keys keep the ids' order
 
+use crate::index_directory::SegmentDirectory;
+use crate::indexing::docids::DocId;
 use anyhow::Result;
 use std::collections::BTreeMap;
 use std::fs::File;
@@ -8,7 +11,7 @@ use fst::MapBuilder;
 
 pub struct Lengths {
     index_key: String,
-    lengths: BTreeMap<String, u64>
+    lengths: BTreeMap<DocId, u64>
 }
 
 impl Lengths {
@@ -16,24 +19,34 @@ impl Lengths {
         Self { index_key, lengths: BTreeMap::new() }
     }
 
-    pub fn add_length(&mut self, docid: String, length: u64) {
-        self.lengths.insert(docid, length);
+    pub fn add_length(&mut self, doc_id: DocId, length: u64) {
+        self.lengths.insert(doc_id, length);
     }
 
     pub fn add_lengths(&mut self, lengths_to_add: &Lengths) {
         for (doc_id, length) in &lengths_to_add.lengths {
-            self.add_length(doc_id.clone(), *length)
+            self.add_length(*doc_id, *length)
         }
     }
 
-    pub fn write_lengths(&self) -> Result<f64> {
-        let wtr = io::BufWriter::new(File::create(format!("lengths_index_{}.fst", self.index_key))?);
+    /// Length of a document's field, 0 if the document doesn't contain it
+    pub fn get_length(&self, doc_id: DocId) -> u64 {
+        self.lengths.get(&doc_id).copied().unwrap_or(0)
+    }
+
+    /// Number of documents containing the field
+    pub fn n_docs(&self) -> u64 {
+        self.lengths.len() as u64
+    }
+
+    pub fn write_lengths(&self, segment_directory: &SegmentDirectory) -> Result<f64> {
+        let wtr = io::BufWriter::new(File::create(segment_directory.lengths_index_path(&self.index_key))?);
 
         let mut avg_length: u64 = 0;
 
         let mut build = MapBuilder::new(wtr)?;
-        for (docid, length) in &self.lengths {
-            build.insert(docid, *length)?;
+        for (doc_id, length) in &self.lengths {
+            build.insert(doc_id.to_be_bytes(), *length)?;
             avg_length += length;
         }
         build.finish()?;
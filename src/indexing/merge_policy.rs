@@ -0,0 +1,181 @@
+// Tiered merge policy, loosely based on Lucene's TieredMergePolicy. Segments are grouped in tiers of exponentially
+// growing sizes, and each tier is allowed to hold `segments_per_tier` segments. Whenever the index holds more segments
+// than allowed, the policy picks the cheapest and most balanced set of segments to merge, favouring the ones with
+// many deleted documents so that they are reclaimed
+
+use crate::index_directory::SegmentInfo;
+
+#[derive(Debug, Clone)]
+pub struct TieredMergePolicy {
+    // Number of segments allowed in each tier
+    segments_per_tier: usize,
+    // Maximum number of segments merged at once
+    max_merge_at_once: usize,
+    // Smaller segments are treated as having this many documents, so that tiny segments get merged eagerly
+    floor_segment_docs: usize,
+    // Segments with more than half of this many live documents are never selected for merging
+    max_merged_segment_docs: usize,
+}
+
+impl Default for TieredMergePolicy {
+    fn default() -> Self {
+        Self::new(10, 10, 1_000, 5_000_000)
+    }
+}
+
+impl TieredMergePolicy {
+    pub fn new(segments_per_tier: usize,
+               max_merge_at_once: usize,
+               floor_segment_docs: usize,
+               max_merged_segment_docs: usize) -> Self {
+        Self {
+            segments_per_tier: segments_per_tier.max(2),
+            max_merge_at_once: max_merge_at_once.max(2),
+            floor_segment_docs: floor_segment_docs.max(1),
+            max_merged_segment_docs,
+        }
+    }
+
+    /// Returns the names of the segments to merge together, if the index has more segments than allowed
+    pub fn find_merge(&self, segments: &[SegmentInfo]) -> Option<Vec<String>> {
+        let mut eligible: Vec<&SegmentInfo> = segments.iter()
+            .filter(|segment_info| Self::live_docs(segment_info) <= self.max_merged_segment_docs / 2)
+            .collect();
+        eligible.sort_by_key(|segment_info| std::cmp::Reverse(Self::live_docs(segment_info)));
+
+        let total_docs: usize = eligible.iter().map(|segment_info| self.floored_docs(segment_info)).sum();
+        if eligible.len() <= self.allowed_segment_count(total_docs) {
+            return None;
+        }
+
+        // Score every window of consecutive (by size) segments, lower is better
+        let mut best_merge: Option<(f64, &[&SegmentInfo])> = None;
+        for start in 0..eligible.len() {
+            let end = (start + self.max_merge_at_once).min(eligible.len());
+            let candidate = &eligible[start..end];
+            if candidate.len() < 2 {
+                break;
+            }
+
+            let score = self.merge_score(candidate);
+            if best_merge.is_none_or(|(best_score, _)| score < best_score) {
+                best_merge = Some((score, candidate));
+            }
+        }
+
+        best_merge.map(|(_, candidate)| {
+            candidate.iter().map(|segment_info| segment_info.name.clone()).collect()
+        })
+    }
+
+    /// Number of segments allowed for an index with this many (floored) documents
+    fn allowed_segment_count(&self, total_docs: usize) -> usize {
+        let mut allowed_segment_count = 0;
+        let mut tier_segment_docs = self.floor_segment_docs;
+        let mut remaining_docs = total_docs;
+
+        loop {
+            let tier_segment_count = remaining_docs.div_ceil(tier_segment_docs);
+            if tier_segment_count <= self.segments_per_tier {
+                return allowed_segment_count + tier_segment_count;
+            }
+
+            allowed_segment_count += self.segments_per_tier;
+            remaining_docs -= self.segments_per_tier * tier_segment_docs;
+            tier_segment_docs *= self.max_merge_at_once;
+        }
+    }
+
+    /// Skew of the merge (how much the biggest segment dominates it), slightly penalising big merges, and favouring
+    /// the ones that reclaim deleted documents
+    fn merge_score(&self, candidate: &[&SegmentInfo]) -> f64 {
+        let floored_docs: Vec<usize> = candidate.iter().map(|segment_info| self.floored_docs(segment_info)).collect();
+        let total_floored_docs: usize = floored_docs.iter().sum();
+        let skew = (*floored_docs.iter().max().unwrap() as f64) / (total_floored_docs as f64);
+
+        let total_docs: usize = candidate.iter().map(|segment_info| segment_info.n_docs).sum();
+        let total_live_docs: usize = candidate.iter().map(|segment_info| Self::live_docs(segment_info)).sum();
+        let live_ratio = (total_live_docs as f64) / (total_docs.max(1) as f64);
+
+        skew * (total_live_docs.max(1) as f64).powf(0.05) * live_ratio.powi(2)
+    }
+
+    fn live_docs(segment_info: &SegmentInfo) -> usize {
+        segment_info.n_docs - segment_info.n_deleted
+    }
+
+    fn floored_docs(&self, segment_info: &SegmentInfo) -> usize {
+        Self::live_docs(segment_info).max(self.floor_segment_docs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment_info(name: &str, n_docs: usize, n_deleted: usize) -> SegmentInfo {
+        SegmentInfo { name: name.to_string(), n_docs, n_deleted }
+    }
+
+    /// Segments named s0, s1... with the given live documents
+    fn segments(live_docs: &[usize]) -> Vec<SegmentInfo> {
+        live_docs.iter().enumerate().map(|(i, n_docs)| segment_info(&format!("s{}", i), *n_docs, 0)).collect()
+    }
+
+    #[test]
+    fn segments_are_allowed_per_tier() {
+        // Tiers of 3 segments, of 10, 30, 90... documents
+        let policy = TieredMergePolicy::new(3, 3, 10, 1_000);
+
+        assert_eq!(policy.allowed_segment_count(0), 0);
+        assert_eq!(policy.allowed_segment_count(30), 3);
+        assert_eq!(policy.allowed_segment_count(40), 4);
+        assert_eq!(policy.allowed_segment_count(120), 6);
+        assert_eq!(policy.allowed_segment_count(360), 9);
+    }
+
+    #[test]
+    fn nothing_is_merged_under_the_budget() {
+        let policy = TieredMergePolicy::new(3, 3, 10, 1_000);
+
+        assert_eq!(policy.find_merge(&[]), None);
+        assert_eq!(policy.find_merge(&segments(&[10, 10, 10])), None);
+        assert_eq!(policy.find_merge(&segments(&[270, 10, 10, 10, 10, 10, 10, 10, 10])), None);
+        // Segments over half the maximum merged size don't count, and are never merged
+        assert_eq!(policy.find_merge(&segments(&[600, 700, 10, 10, 10])), None);
+    }
+
+    #[test]
+    fn smallest_segments_are_merged_first() {
+        let policy = TieredMergePolicy::new(3, 3, 10, 1_000);
+
+        let merge = policy.find_merge(&segments(&[270, 10, 10, 10, 10, 10, 10, 10, 10, 10]));
+
+        assert_eq!(merge, Some(vec!["s1".to_string(), "s2".to_string(), "s3".to_string()]));
+    }
+
+    #[test]
+    fn merges_are_capped_at_max_merge_at_once() {
+        let policy = TieredMergePolicy::new(3, 4, 10, 1_000);
+
+        let merge = policy.find_merge(&segments(&[10; 20])).unwrap();
+
+        assert_eq!(merge, ["s0", "s1", "s2", "s3"]);
+    }
+
+    #[test]
+    fn segments_full_of_deletes_are_merged_first() {
+        let policy = TieredMergePolicy::new(3, 3, 10, 1_000);
+        let mut segment_infos = segments(&[10, 10, 10, 10, 10]);
+        assert_eq!(policy.find_merge(&segment_infos), Some(vec!["s0".to_string(), "s1".to_string(), "s2".to_string()]));
+
+        // All of the documents of s3 were deleted
+        segment_infos[3] = segment_info("s3", 40, 40);
+        assert_eq!(policy.find_merge(&segment_infos), Some(vec!["s4".to_string(), "s3".to_string()]));
+
+        let live_candidate = segments(&[10, 10]);
+        let deleted_candidate = [segment_info("s0", 10, 0), segment_info("s1", 20, 10)];
+        let score = |candidate: &[SegmentInfo]| policy.merge_score(&candidate.iter().collect::<Vec<_>>());
+        assert!(score(&deleted_candidate) < score(&live_candidate));
+    }
+}
@@ -0,0 +1,275 @@
+// Merger of several segments into a single one. Postings are merged with a union over the segments' postings FSTs,
+// so that tokens are streamed in order and written without holding every postings list in memory. Deleted documents
+// are dropped along the way, and the remaining ones get new consecutive doc ids in the merged segment. Segments can
+// only be merged if each field stores positions in all of them or in none, since phrase queries would otherwise
+// silently stop matching the documents whose positions were dropped. Stored fields are kept for every document that
+// has them
+
+use crate::index_directory::SegmentDirectory;
+use crate::indexing::docids::{DocId, Docids};
+use crate::indexing::lengths::Lengths;
+use crate::indexing::postings::PostingsFileWriter;
+use crate::indexing::postings_codec::Posting;
+use crate::indexing::stored_fields::{StoredDoc, StoredFieldsWriter};
+use crate::retrieval::segment::Segment;
+use anyhow::{anyhow, Result};
+use fst::map::OpBuilder;
+use fst::Streamer;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+pub struct SegmentMerger<'a> {
+    index_keys: &'a [String],
+    segments: Vec<Segment>,
+    // For every segment, the merged doc id of each of its documents, if they are kept
+    doc_id_maps: Vec<Vec<Option<DocId>>>,
+    docids: Docids,
+    positional_index_keys: HashSet<String>,
+}
+
+impl<'a> SegmentMerger<'a> {
+    pub fn new(index_keys: &'a [String], segments: Vec<Segment>) -> Result<Self> {
+        // Later segments hold the most recent version of a document, in case it was indexed more than once
+        let mut latest_versions: HashMap<String, (usize, DocId)> = HashMap::new();
+        for (segment_i, segment) in segments.iter().enumerate() {
+            for doc_id in 0..segment.n_docs() as DocId {
+                if !segment.is_deleted(doc_id) {
+                    latest_versions.insert(segment.get_docid(doc_id), (segment_i, doc_id));
+                }
+            }
+        }
+
+        let mut docids = Docids::new();
+        let mut doc_id_maps = Vec::new();
+        for (segment_i, segment) in segments.iter().enumerate() {
+            let mut doc_id_map = vec![None; segment.n_docs()];
+
+            for doc_id in 0..segment.n_docs() as DocId {
+                let docid = segment.get_docid(doc_id);
+                if latest_versions.get(&docid) == Some(&(segment_i, doc_id)) {
+                    doc_id_map[doc_id as usize] = Some(docids.add_docid(docid));
+                }
+            }
+
+            doc_id_maps.push(doc_id_map);
+        }
+
+        let mut positional_index_keys = HashSet::new();
+        for index_key in index_keys {
+            let n_positional = segments.iter().filter(|segment| segment.has_positions(index_key)).count();
+            if n_positional == segments.len() {
+                positional_index_keys.insert(index_key.clone());
+            } else if n_positional > 0 {
+                return Err(anyhow!(format!(
+                    "Field {} stores positions in only {} of the {} segments to merge, so merging them would drop its \
+                     positions", index_key, n_positional, segments.len())));
+            }
+        }
+
+        Ok(Self { index_keys, segments, doc_id_maps, docids, positional_index_keys })
+    }
+
+    /// The docid dictionary of the merged segment
+    pub fn docids(&self) -> &Docids {
+        &self.docids
+    }
+
+    /// Fields whose positions are kept in the merged segment
+    pub fn positional_index_keys(&self) -> &HashSet<String> {
+        &self.positional_index_keys
+    }
+
+    /// Writes the merged postings of every field, given the merged lengths of the kept documents
+    pub fn write_postings(&self, segment_directory: &SegmentDirectory, lengths_writers: &HashMap<String, Lengths>) -> Result<()> {
+        for index_key in self.index_keys {
+            let mut op_builder = OpBuilder::new();
+            let mut field_segments = Vec::new();
+            for (segment_i, segment) in self.segments.iter().enumerate() {
+                if let Some(postings_map) = segment.get_postings_map(index_key) {
+                    op_builder.push(postings_map.stream());
+                    field_segments.push(segment_i);
+                }
+            }
+
+            let with_positions = self.positional_index_keys.contains(index_key);
+            let field_lengths = lengths_writers.get(index_key).unwrap();
+            let mut writer = PostingsFileWriter::create(segment_directory, index_key, with_positions, field_lengths)?;
+            let mut union = op_builder.union();
+            while let Some((token, postings_positions)) = union.next() {
+                let mut merged_postings: BTreeMap<DocId, Posting> = BTreeMap::new();
+
+                for postings_position in postings_positions {
+                    let segment_i = field_segments[postings_position.index];
+                    let segment = &self.segments[segment_i];
+
+                    let mut postings_cursor = segment.read_postings(index_key, postings_position.value)?;
+                    while let Some((doc_id, tf)) = postings_cursor.next()? {
+                        if let Some(merged_doc_id) = self.doc_id_maps[segment_i][doc_id as usize] {
+                            let positions = if with_positions { postings_cursor.positions().to_vec() } else { Vec::new() };
+                            merged_postings.insert(merged_doc_id, Posting { tf, positions });
+                        }
+                    }
+                }
+
+                // Tokens which only appeared in dropped documents are dropped as well
+                if !merged_postings.is_empty() {
+                    writer.add_postings(std::str::from_utf8(token)?, &merged_postings)?;
+                }
+            }
+
+            writer.finish()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the stored fields of the kept documents, in their merged doc id order
+    pub fn write_stored_fields(&self, segment_directory: &SegmentDirectory) -> Result<()> {
+        let mut writer = StoredFieldsWriter::create(segment_directory)?;
+
+        for (segment_i, segment) in self.segments.iter().enumerate() {
+            let doc_id_map = &self.doc_id_maps[segment_i];
+
+            match segment.get_stored_fields() {
+                Some(stored_fields) => {
+                    for stored_doc in stored_fields.docs() {
+                        let (doc_id, stored_doc) = stored_doc?;
+                        if let Some(merged_doc_id) = doc_id_map[doc_id as usize] {
+                            writer.add_doc(merged_doc_id, stored_doc)?;
+                        }
+                    }
+                }
+                None => {
+                    for merged_doc_id in doc_id_map.iter().flatten() {
+                        writer.add_doc(*merged_doc_id, StoredDoc::new())?;
+                    }
+                }
+            }
+        }
+
+        writer.finish()
+    }
+
+    /// Returns the lengths of the kept documents of every field
+    pub fn merge_lengths(&self) -> Result<HashMap<String, Lengths>> {
+        let mut lengths_writers = HashMap::new();
+
+        for index_key in self.index_keys {
+            let mut lengths_writer = Lengths::new(index_key.clone());
+
+            for (segment_i, segment) in self.segments.iter().enumerate() {
+                let mut lengths_stream = segment.get_lengths_map(index_key).stream();
+                while let Some((doc_id_bytes, length)) = lengths_stream.next() {
+                    let doc_id = DocId::from_be_bytes(doc_id_bytes.try_into()?);
+                    if let Some(merged_doc_id) = self.doc_id_maps[segment_i][doc_id as usize] {
+                        lengths_writer.add_length(merged_doc_id, length);
+                    }
+                }
+            }
+
+            lengths_writers.insert(index_key.clone(), lengths_writer);
+        }
+
+        Ok(lengths_writers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index_directory::Manifest;
+    use crate::indexing::Indexer;
+    use crate::retrieval::segment::Segment;
+    use crate::retrieval::Retriever;
+    use crate::test_index::{assert_same_results, bm25f, body_docs, doc, TestIndex};
+    use std::collections::HashMap;
+
+    const INDEX_KEYS: [&str; 2] = ["title", "body"];
+
+    const DOCS: [(&str, &str, &str); 4] = [
+        ("d1", "rust", "rust search engine"),
+        ("d2", "search", "search search rust index"),
+        ("d3", "python search", "python"),
+        ("d4", "java", "java index"),
+    ];
+
+    fn index_docs(indexer: &mut Indexer, docs: &[(&str, &str, &str)]) {
+        indexer.index(docs.iter().map(|(docid, title, body)| doc(docid, &[("title", title), ("body", body)]))).unwrap();
+    }
+
+    fn rust_search_results(retriever: &Retriever) -> Vec<(String, f64)> {
+        let bm25f = bm25f(&[("title", 0.75, 2.0), ("body", 0.5, 1.0)]);
+        retriever.retrieval_multiple_tokens(&vec!["rust".to_string(), "search".to_string()], &bm25f).unwrap()
+    }
+
+    /// Results of "rust search" on an index of the given documents, in a single segment
+    fn expected_results(index_name: &str, docs: &[(&str, &str, &str)]) -> Vec<(String, f64)> {
+        let index = TestIndex::new(index_name);
+        index_docs(&mut index.indexer(&INDEX_KEYS), docs);
+
+        rust_search_results(&index.retriever(&INDEX_KEYS))
+    }
+
+    #[test]
+    fn merges_keep_the_scores() {
+        let index = TestIndex::new("merge_scores");
+        let mut indexer = index.indexer(&INDEX_KEYS);
+        index_docs(&mut indexer, &DOCS[..1]);
+        index_docs(&mut indexer, &DOCS[1..3]);
+        index_docs(&mut indexer, &DOCS[3..]);
+
+        let results_before = rust_search_results(&index.retriever(&INDEX_KEYS));
+        indexer.force_merge().unwrap();
+        let n_segments = Manifest::load(&index.directory).unwrap().segments.len();
+        let results_after = rust_search_results(&index.retriever(&INDEX_KEYS));
+
+        assert_eq!(n_segments, 1);
+        let expected_results = expected_results("merge_scores_expected", &DOCS);
+        assert_same_results(&results_before, &expected_results);
+        assert_same_results(&results_after, &expected_results);
+    }
+
+    #[test]
+    fn merges_drop_deleted_documents() {
+        let index = TestIndex::new("merge_deletes");
+        let mut indexer = index.indexer(&INDEX_KEYS);
+        index_docs(&mut indexer, &[DOCS[0], DOCS[1], DOCS[2], ("d4", "rust", "rust search engine index")]);
+        index_docs(&mut indexer, &[("d5", "search engine", "rust")]);
+        indexer.delete("d5").unwrap();
+        indexer.update("d4", HashMap::from([("title".to_string(), "java".to_string()), ("body".to_string(), "java index".to_string())])).unwrap();
+
+        indexer.force_merge().unwrap();
+        let segments = Manifest::load(&index.directory).unwrap().segments;
+        let index_keys: Vec<String> = INDEX_KEYS.iter().map(|index_key| index_key.to_string()).collect();
+        let segment = Segment::open(&index_keys, &index.directory.segment(&segments[0].name)).unwrap();
+        let rust_stats = segment.get_token_stats("rust", None).unwrap();
+        let retriever = index.retriever(&INDEX_KEYS);
+
+        assert_eq!(segments.len(), 1);
+        assert_eq!((segments[0].n_docs, segments[0].n_deleted), (4, 0));
+        assert!(retriever.get_document("d4").is_ok());
+        assert!(retriever.get_document("d5").is_err());
+        // The stored statistics are exact again, so the scores are those of an index which never had the deleted
+        // documents
+        assert_eq!(rust_stats.doc_frequency, 2);
+        assert_eq!((rust_stats.field("body").doc_frequency, rust_stats.field("body").collection_frequency), (2, 2.0));
+        assert_same_results(&rust_search_results(&retriever), &expected_results("merge_deletes_expected", &DOCS));
+    }
+
+    #[test]
+    fn merges_never_drop_positions() {
+        let index = TestIndex::new("merge_positions");
+        let mut indexer = index.indexer(&["body"]);
+        indexer.store_positions("body");
+        indexer.index(body_docs(&[("d1", "quick brown fox")])).unwrap();
+        indexer.index(body_docs(&[("d2", "lazy brown dog")])).unwrap();
+        // Both segments store the positions
+        indexer.force_merge().unwrap();
+
+        // A segment without them
+        index.indexer(&["body"]).index(body_docs(&[("d3", "brown fox")])).unwrap();
+        let merge_result = indexer.force_merge();
+        let n_segments = Manifest::load(&index.directory).unwrap().segments.len();
+
+        assert!(merge_result.unwrap_err().to_string().contains("Field body stores positions in only 1 of the 2 segments"));
+        assert_eq!(n_segments, 2);
+    }
+}
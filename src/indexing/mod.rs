@@ -2,9 +2,21 @@
 mod postings;
 mod lengths;
 mod avg_lengths;
-pub(crate) mod stats; // The only writer intended to be exposed
+mod doc_frequencies;
+mod spimi;
+pub(crate) mod stats; // Writers intended to be exposed, since the retriever reads them back
+pub(crate) mod deletions;
+pub(crate) mod docids;
+pub(crate) mod postings_codec;
+pub(crate) mod postings_cursor;
+pub(crate) mod stored_fields;
+
+// Segment merging
+mod merger;
+mod merge_policy;
 
 // Main indexer implementation
 mod indexer;
 
-pub use self::indexer::Indexer as Indexer;
\ No newline at end of file
+pub use self::indexer::Indexer as Indexer;
+pub use self::merge_policy::TieredMergePolicy;
\ No newline at end of file
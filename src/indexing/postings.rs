@@ -1,25 +1,25 @@
 // Writer for the index postings for a given field
 // Postings are divided into a FST-backed index file and a raw postings file. The index maps tokens to the raw file's 
-// starting position of its serialized postings map, which contains the length of the serialized value immediately 
-// followed by it
-
-use crate::aux;
-use crate::aux::{write_buffer_to_binary_file, write_value_to_binary_file};
+// starting position of its postings list, encoded with the current postings codec. A second FST maps the reversed
+// tokens to the same positions, so that leading-wildcard queries can walk it from the end of the tokens
+
+use crate::aux::write_buffer_to_binary_file;
+use crate::index_directory::SegmentDirectory;
+use crate::indexing::docids::DocId;
+use crate::indexing::lengths::Lengths;
+use crate::indexing::postings_codec::{Position, Posting, PostingsCodec, CURRENT_POSTINGS_CODEC_VERSION};
 use anyhow::Result;
 use fst::MapBuilder;
 use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io;
 
-type Docid = String;
-type Tf = u64;
-
 struct PostingsBTree {
     postings: HashMap<
         // Token
         String,
         // Map of postings
-        HashMap<Docid, Tf>,
+        HashMap<DocId, Posting>,
     >,
 }
 
@@ -30,13 +30,15 @@ impl PostingsBTree {
         }
     }
 
-    pub fn add_token_to_docid(&mut self, docid: &String, token: &str) {
+    pub fn add_token_to_docid(&mut self, doc_id: DocId, token: &str, position: Position, with_positions: bool) {
         let postings_for_token = self
             .postings
             .entry(token.to_string())
             .or_default();
-        let tf = postings_for_token.entry(docid.to_string()).or_insert(0);
-        *tf += 1;
+        postings_for_token
+            .entry(doc_id)
+            .or_default()
+            .add_occurrence(position, with_positions);
     }
 
     /// For merging Postings instances
@@ -47,8 +49,8 @@ impl PostingsBTree {
                 .entry(token.to_string())
                 .or_default();
 
-            for (doc_id, tf) in postings_map {
-                *target_entry.entry(doc_id.to_string()).or_insert(0) += tf;
+            for (doc_id, posting) in postings_map {
+                target_entry.entry(*doc_id).or_default().merge(posting.clone());
             }
         }
     }
@@ -56,19 +58,21 @@ impl PostingsBTree {
 
 pub struct Postings {
     index_key: String,
+    with_positions: bool,
     postings_tree: PostingsBTree,
 }
 
 impl Postings {
-    pub fn new(index_key: String) -> Self {
+    pub fn new(index_key: String, with_positions: bool) -> Self {
         Self {
             index_key,
+            with_positions,
             postings_tree: PostingsBTree::new(),
         }
     }
 
-    pub fn add_token_to_docid(&mut self, docid: &String, token: &str) {
-        self.postings_tree.add_token_to_docid(docid, token);
+    pub fn add_token_to_docid(&mut self, doc_id: DocId, token: &str, position: Position) {
+        self.postings_tree.add_token_to_docid(doc_id, token, position, self.with_positions);
     }
 
     /// Merge both Postings instances into this one
@@ -76,45 +80,80 @@ impl Postings {
         self.postings_tree.add_tree(postings_to_merge);
     }
 
-    pub fn write_postings(&mut self) -> Result<()> {
-        let posting_positions = self.create_postings_file();
-        self.create_postings_fst_file(&posting_positions?)
-    }
-
-    fn create_postings_file(&mut self) -> Result<BTreeMap<String, u64>> {
-        let mut file = File::create(format!("postings_data_{}.bin", &self.index_key))?;
-        let mut positions: BTreeMap<String, u64> = BTreeMap::new();
-
+    pub fn write_postings(&mut self, segment_directory: &SegmentDirectory, field_lengths: &Lengths) -> Result<()> {
         let mut ordered_postings: Vec<_> = self.postings_tree.postings.drain().collect();
         ordered_postings.sort_by(|a, b| a.0.cmp(&b.0));
 
-        for (posting, value) in &ordered_postings {
-            let serialized_value = aux::serialize_value(value);
+        let mut writer = PostingsFileWriter::create(segment_directory, &self.index_key, self.with_positions, field_lengths)?;
+        for (posting, value) in ordered_postings {
+            writer.add_postings(&posting, &value.into_iter().collect())?;
+        }
+
+        writer.finish()
+    }
+}
+
+/// Streaming writer for the postings data and FST index files of a field. Tokens must be added in lexicographical
+/// order, which allows writing both files without holding every postings list in memory at once (only the reversed
+/// tokens are kept until the end, since they need to be sorted). The field lengths of the documents are needed for the
+/// blocks' maxima
+pub struct PostingsFileWriter<'a> {
+    data_file: File,
+    index_builder: MapBuilder<io::BufWriter<File>>,
+    reversed_index_file: File,
+    reversed_tokens: Vec<(String, u64)>,
+    codec: PostingsCodec,
+    with_positions: bool,
+    field_lengths: &'a Lengths,
+}
+
+impl<'a> PostingsFileWriter<'a> {
+    pub fn create(segment_directory: &SegmentDirectory,
+                  index_key: &str,
+                  with_positions: bool,
+                  field_lengths: &'a Lengths) -> Result<Self> {
+        let data_file = File::create(segment_directory.postings_data_path(index_key))?;
+        let index_builder = MapBuilder::new(io::BufWriter::new(
+            File::create(segment_directory.postings_index_path(index_key))?,
+        ))?;
+
+        let reversed_index_file = File::create(segment_directory.reversed_postings_index_path(index_key))?;
+
+        let codec = PostingsCodec::from_version(CURRENT_POSTINGS_CODEC_VERSION)?;
+
+        Ok(Self {
+            data_file,
+            index_builder,
+            reversed_index_file,
+            reversed_tokens: Vec::new(),
+            codec,
+            with_positions,
+            field_lengths,
+        })
+    }
 
-            // Write its length first!
-            let (start_position, _end_position) =
-                write_value_to_binary_file(&mut file, &serialized_value.len())?;
-            let (_start_position, _end_position) =
-                write_buffer_to_binary_file(&mut file, serialized_value)?;
+    pub fn add_postings(&mut self, token: &str, postings: &BTreeMap<DocId, Posting>) -> Result<()> {
+        let encoded_postings = self.codec.encode(postings, self.with_positions, self.field_lengths);
+        let (start_position, _end_position) =
+            write_buffer_to_binary_file(&mut self.data_file, encoded_postings)?;
 
-            // And store that length's start position
-            positions.insert(posting.clone(), start_position);
-        }
+        // And index its start position
+        self.index_builder.insert(token, start_position)?;
+        self.reversed_tokens.push((token.chars().rev().collect(), start_position));
 
-        Ok(positions)
+        Ok(())
     }
 
-    fn create_postings_fst_file(&self, positions: &BTreeMap<String, u64>) -> Result<()> {
-        let wtr = io::BufWriter::new(
-            File::create(format!("postings_index_{}.fst", self.index_key))?,
-        );
+    pub fn finish(mut self) -> Result<()> {
+        self.index_builder.finish()?;
 
-        let mut build = MapBuilder::new(wtr)?;
-        for (posting, pos) in positions {
-            build.insert(posting, *pos)?;
+        self.reversed_tokens.sort_unstable();
+        let mut reversed_index_builder = MapBuilder::new(io::BufWriter::new(self.reversed_index_file))?;
+        for (reversed_token, start_position) in self.reversed_tokens {
+            reversed_index_builder.insert(reversed_token, start_position)?;
         }
-        build.finish()?;
-        
+        reversed_index_builder.finish()?;
+
         Ok(())
     }
 }
@@ -0,0 +1,315 @@
+// Encoding of a token's postings list in the postings data file. Postings are sorted by doc id, so they are stored as
+// delta-gaps between consecutive doc ids, and both the gaps and the term frequencies as variable-byte integers (7 bits
+// per byte, with the high bit set on every byte but the last one). The codec used by a segment is recorded as a
+// version number in its stats, so segments written by older versions can still be read (and are upgraded on merge)
+//
+// Since version 2, postings are split in blocks of POSTINGS_BLOCK_SIZE, preceded by skip data holding the last doc id
+// and the encoded size of every block, so that readers can jump over whole blocks without decoding them. Since version
+// 3, the postings of fields storing positions (listed in the segment stats) are followed by their token positions.
+// Since version 4, the skip data also holds the maximum tf and the minimum field length of every block's documents,
+// which bound the scores of the block for any retrieval parameters, so that top-k retrieval can skip whole blocks.
+// Since version 5, the number of postings is followed by the total frequency of the token, so that along with it (the
+// document frequency) the token's statistics can be read without decoding any block
+
+use crate::aux;
+use crate::indexing::docids::DocId;
+use crate::indexing::lengths::Lengths;
+use crate::indexing::postings_cursor::PostingsCursor;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+type Tf = u64;
+pub type Position = u32;
+
+/// Version of the postings codec used when writing new segments
+pub const CURRENT_POSTINGS_CODEC_VERSION: u32 = 5;
+
+pub const POSTINGS_BLOCK_SIZE: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostingsCodec {
+    // Version 0: the bincode-serialized map of doc id -> tf, preceded by its length as a bincode u64
+    Bincode,
+    // Version 1: the number of postings, followed by the (doc id gap, tf) pairs, all of them as varints
+    DeltaVarint,
+    // Version 2: the number of postings, the (last doc id gap, size in bytes) skip entry of every block, and then the
+    // blocks' (doc id gap, tf) pairs, all of them as varints. Gaps run across blocks
+    // Version 3: the same, where fields storing positions follow every tf with the gaps between the token positions
+    BlockVarint,
+    // Version 4: the same, where skip entries are (last doc id gap, size in bytes, maximum tf, minimum field length)
+    BlockMaxVarint,
+    // Version 5: the same, where the number of postings is followed by the sum of their tfs
+    BlockMaxStatsVarint,
+}
+
+/// A document's entry in a postings list. The positions are only kept for fields storing them
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Posting {
+    pub tf: Tf,
+    pub positions: Vec<Position>,
+}
+
+impl Posting {
+    pub fn add_occurrence(&mut self, position: Position, with_positions: bool) {
+        self.tf += 1;
+        if with_positions {
+            self.positions.push(position);
+        }
+    }
+
+    /// Adds the occurrences of another posting of the same document
+    pub fn merge(&mut self, posting: Posting) {
+        self.tf += posting.tf;
+        self.positions.extend(posting.positions);
+        self.positions.sort_unstable();
+    }
+}
+
+impl PostingsCodec {
+    pub fn from_version(version: u32) -> Result<Self> {
+        match version {
+            0 => Ok(Self::Bincode),
+            1 => Ok(Self::DeltaVarint),
+            2 | 3 => Ok(Self::BlockVarint),
+            4 => Ok(Self::BlockMaxVarint),
+            5 => Ok(Self::BlockMaxStatsVarint),
+            _ => Err(anyhow!(format!("Unsupported postings codec version {}", version))),
+        }
+    }
+
+    /// Encodes a postings list, along with its positions if `with_positions` (only supported by block codecs). The
+    /// field lengths of the documents are only needed by codecs storing block maxima
+    pub fn encode(&self, postings: &BTreeMap<DocId, Posting>, with_positions: bool, field_lengths: &Lengths) -> Vec<u8> {
+        match self {
+            Self::Bincode => {
+                let tfs: BTreeMap<DocId, Tf> = postings.iter().map(|(doc_id, posting)| (*doc_id, posting.tf)).collect();
+                let serialized_postings = aux::serialize_value(&tfs);
+
+                let mut buffer = aux::serialize_value(&(serialized_postings.len() as u64));
+                buffer.extend(serialized_postings);
+                buffer
+            }
+            Self::DeltaVarint => {
+                let mut buffer = Vec::new();
+                write_varint(&mut buffer, postings.len() as u64);
+
+                let mut previous_doc_id = 0;
+                for (doc_id, posting) in postings {
+                    write_varint(&mut buffer, (doc_id - previous_doc_id) as u64);
+                    write_varint(&mut buffer, posting.tf);
+                    previous_doc_id = *doc_id;
+                }
+
+                buffer
+            }
+            Self::BlockVarint | Self::BlockMaxVarint | Self::BlockMaxStatsVarint => {
+                let mut skip_data = Vec::new();
+                let mut blocks_data = Vec::new();
+
+                let mut previous_doc_id = 0;
+                let mut previous_block_last_doc_id = 0;
+                let postings: Vec<(&DocId, &Posting)> = postings.iter().collect();
+                for block in postings.chunks(POSTINGS_BLOCK_SIZE) {
+                    let block_start = blocks_data.len();
+                    for (doc_id, posting) in block {
+                        write_varint(&mut blocks_data, (*doc_id - previous_doc_id) as u64);
+                        write_varint(&mut blocks_data, posting.tf);
+                        previous_doc_id = **doc_id;
+
+                        if with_positions {
+                            let mut previous_position = 0;
+                            for position in &posting.positions {
+                                write_varint(&mut blocks_data, (position - previous_position) as u64);
+                                previous_position = *position;
+                            }
+                        }
+                    }
+
+                    write_varint(&mut skip_data, (previous_doc_id - previous_block_last_doc_id) as u64);
+                    write_varint(&mut skip_data, (blocks_data.len() - block_start) as u64);
+                    previous_block_last_doc_id = previous_doc_id;
+
+                    if *self != Self::BlockVarint {
+                        let max_tf = block.iter().map(|(_, posting)| posting.tf).max().unwrap_or(0);
+                        let min_length = block.iter().map(|(doc_id, _)| field_lengths.get_length(**doc_id)).min().unwrap_or(0);
+                        write_varint(&mut skip_data, max_tf);
+                        write_varint(&mut skip_data, min_length);
+                    }
+                }
+
+                let mut buffer = Vec::new();
+                write_varint(&mut buffer, postings.len() as u64);
+                if *self == Self::BlockMaxStatsVarint {
+                    write_varint(&mut buffer, postings.iter().map(|(_, posting)| posting.tf).sum());
+                }
+                buffer.extend(skip_data);
+                buffer.extend(blocks_data);
+                buffer
+            }
+        }
+    }
+
+    /// Opens a cursor over the postings list starting at the beginning of the buffer
+    pub fn cursor<'a>(&self, buffer: &'a [u8], with_positions: bool) -> Result<PostingsCursor<'a>> {
+        match self {
+            Self::Bincode => {
+                let postings_size: u64 = bincode::deserialize(buffer.get(..8).unwrap_or(buffer))
+                    .with_context(|| "Failed to read the postings length")?;
+                let postings_bytes = buffer.get(8..8 + postings_size as usize)
+                    .ok_or_else(|| anyhow!("Truncated postings"))?;
+                let postings: BTreeMap<DocId, Tf> = bincode::deserialize(postings_bytes)
+                    .with_context(|| "Failed to deserialize the postings")?;
+
+                Ok(PostingsCursor::from_postings(postings.into_iter().collect()))
+            }
+            Self::DeltaVarint => {
+                let mut position = 0;
+                let n_postings = read_varint(buffer, &mut position)?;
+
+                let mut postings = Vec::new();
+                let mut doc_id: DocId = 0;
+                for _ in 0..n_postings {
+                    doc_id += read_varint(buffer, &mut position)? as DocId;
+                    let tf = read_varint(buffer, &mut position)?;
+                    postings.push((doc_id, tf));
+                }
+
+                Ok(PostingsCursor::from_postings(postings))
+            }
+            Self::BlockVarint => PostingsCursor::from_blocks(buffer, with_positions, false, false),
+            Self::BlockMaxVarint => PostingsCursor::from_blocks(buffer, with_positions, true, false),
+            Self::BlockMaxStatsVarint => PostingsCursor::from_blocks(buffer, with_positions, true, true),
+        }
+    }
+}
+
+pub fn write_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+/// Reads a varint at the position of the buffer, moving the position past it
+pub fn read_varint(buffer: &[u8], position: &mut usize) -> Result<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = *buffer.get(*position).ok_or_else(|| anyhow!("Truncated postings"))?;
+        *position += 1;
+
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+
+        shift += 7;
+        if shift >= 64 {
+            return Err(anyhow!("Malformed varint in postings"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Spans several blocks, with growing gaps, tfs and lengths
+    fn postings() -> (BTreeMap<DocId, Posting>, Lengths) {
+        let mut postings = BTreeMap::new();
+        let mut lengths = Lengths::new("body".to_string());
+
+        for i in 0..(2 * POSTINGS_BLOCK_SIZE as u32 + 10) {
+            let doc_id = i * i + 3;
+            let tf = (i % 7 + 1) as Tf;
+            let positions = (0..tf as Position).map(|position| position * (i + 2) + i % 3).collect();
+            postings.insert(doc_id, Posting { tf, positions });
+            lengths.add_length(doc_id, (i % 50 + 100) as u64);
+        }
+
+        (postings, lengths)
+    }
+
+    fn decode(codec: PostingsCodec, buffer: &[u8], with_positions: bool) -> BTreeMap<DocId, Posting> {
+        let mut cursor = codec.cursor(buffer, with_positions).unwrap();
+
+        let mut decoded_postings = BTreeMap::new();
+        while let Some((doc_id, tf)) = cursor.next().unwrap() {
+            decoded_postings.insert(doc_id, Posting { tf, positions: cursor.positions().to_vec() });
+        }
+
+        decoded_postings
+    }
+
+    #[test]
+    fn every_codec_version_round_trips() {
+        let (postings, lengths) = postings();
+        let tfs_only: BTreeMap<DocId, Posting> = postings.iter()
+            .map(|(doc_id, posting)| (*doc_id, Posting { tf: posting.tf, positions: Vec::new() }))
+            .collect();
+
+        for version in 0..=CURRENT_POSTINGS_CODEC_VERSION {
+            let codec = PostingsCodec::from_version(version).unwrap();
+            let buffer = codec.encode(&postings, false, &lengths);
+            assert_eq!(decode(codec, &buffer, false), tfs_only, "version {}", version);
+
+            let cursor = codec.cursor(&buffer, false).unwrap();
+            assert_eq!(cursor.n_postings(), postings.len(), "version {}", version);
+
+            // Positions are only supported by the block codecs
+            if version >= 2 {
+                let buffer = codec.encode(&postings, true, &lengths);
+                assert_eq!(decode(codec, &buffer, true), postings, "version {}", version);
+            }
+        }
+
+        assert!(PostingsCodec::from_version(CURRENT_POSTINGS_CODEC_VERSION + 1).is_err());
+    }
+
+    #[test]
+    fn block_maxes_and_collection_frequency_are_stored() {
+        let (postings, lengths) = postings();
+        let postings_list: Vec<(&DocId, &Posting)> = postings.iter().collect();
+        let expected_block_maxes: Vec<(Tf, u64)> = postings_list.chunks(POSTINGS_BLOCK_SIZE)
+            .map(|block| {
+                let max_tf = block.iter().map(|(_, posting)| posting.tf).max().unwrap();
+                let min_length = block.iter().map(|(doc_id, _)| lengths.get_length(**doc_id)).min().unwrap();
+                (max_tf, min_length)
+            })
+            .collect();
+        let collection_frequency: Tf = postings.values().map(|posting| posting.tf).sum();
+
+        for version in [4, 5] {
+            let codec = PostingsCodec::from_version(version).unwrap();
+            let buffer = codec.encode(&postings, true, &lengths);
+            let cursor = codec.cursor(&buffer, true).unwrap();
+            assert_eq!(cursor.block_maxes().collect::<Vec<_>>(), expected_block_maxes, "version {}", version);
+        }
+
+        let buffer = PostingsCodec::BlockMaxStatsVarint.encode(&postings, true, &lengths);
+        assert_eq!(PostingsCodec::BlockMaxStatsVarint.cursor(&buffer, true).unwrap().collection_frequency(), Some(collection_frequency));
+        let buffer = PostingsCodec::BlockMaxVarint.encode(&postings, true, &lengths);
+        assert_eq!(PostingsCodec::BlockMaxVarint.cursor(&buffer, true).unwrap().collection_frequency(), None);
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        let values = [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, u32::MAX as u64, u64::MAX];
+
+        let mut buffer = Vec::new();
+        for value in values {
+            write_varint(&mut buffer, value);
+        }
+
+        let mut position = 0;
+        for value in values {
+            assert_eq!(read_varint(&buffer, &mut position).unwrap(), value);
+        }
+        assert_eq!(position, buffer.len());
+        assert!(read_varint(&buffer, &mut position).is_err());
+        assert!(read_varint(&[0x80], &mut 0).is_err());
+    }
+}
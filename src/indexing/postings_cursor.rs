@@ -0,0 +1,322 @@
+// Cursor over a token's postings list, decoding it lazily from the mmapped postings data. Postings are decoded one
+// block at a time, and `advance` uses the blocks' skip data to jump over the ones that can't contain the target doc id.
+// Postings written by codecs without blocks are decoded at once, as a single block. The blocks' maximum tf and minimum
+// field length bound the scores of their postings, and are as loose as possible for codecs not storing them. The number
+// of postings and their total tf (if stored by the codec) are known without decoding any block
+
+use crate::indexing::docids::DocId;
+use crate::indexing::postings_codec::{read_varint, Position, POSTINGS_BLOCK_SIZE};
+use anyhow::Result;
+use std::ops::Range;
+
+type Tf = u64;
+
+#[derive(Debug)]
+struct SkipEntry {
+    last_doc_id: DocId,
+    // Position of the block's first byte, relative to the start of the blocks data
+    offset: usize,
+    n_postings: usize,
+    max_tf: Tf,
+    min_length: u64,
+}
+
+#[derive(Debug)]
+pub struct PostingsCursor<'a> {
+    blocks_data: &'a [u8],
+    skip_entries: Vec<SkipEntry>,
+    with_positions: bool,
+    n_postings: usize,
+    collection_frequency: Option<Tf>,
+
+    // Block holding the next posting to visit, and its decoded postings
+    block_i: usize,
+    loaded_block_i: Option<usize>,
+    block_postings: Vec<(DocId, Tf)>,
+    // Positions of all the postings of the block, and the range of each posting's ones
+    block_positions: Vec<Position>,
+    block_positions_ranges: Vec<Range<usize>>,
+    block_posting_i: usize,
+
+    current: Option<(DocId, Tf)>,
+    current_positions: Range<usize>,
+}
+
+impl<'a> PostingsCursor<'a> {
+    /// Cursor over already decoded postings, sorted by doc id
+    pub fn from_postings(postings: Vec<(DocId, Tf)>) -> Self {
+        let n_postings = postings.len();
+        let collection_frequency = Some(postings.iter().map(|(_, tf)| *tf).sum());
+        let skip_entries = match postings.last() {
+            Some((last_doc_id, _)) => vec![SkipEntry {
+                last_doc_id: *last_doc_id,
+                offset: 0,
+                n_postings: postings.len(),
+                max_tf: postings.iter().map(|(_, tf)| *tf).max().unwrap_or(0),
+                min_length: 0,
+            }],
+            None => Vec::new(),
+        };
+
+        Self {
+            blocks_data: &[],
+            skip_entries,
+            with_positions: false,
+            n_postings,
+            collection_frequency,
+            block_i: 0,
+            loaded_block_i: Some(0),
+            block_postings: postings,
+            block_positions: Vec::new(),
+            block_positions_ranges: Vec::new(),
+            block_posting_i: 0,
+            current: None,
+            current_positions: 0..0,
+        }
+    }
+
+    /// Cursor over postings encoded in blocks, reading only their header (with their total tf, if stored) and skip
+    /// data (with their maxima, if stored)
+    pub fn from_blocks(buffer: &'a [u8],
+                       with_positions: bool,
+                       with_block_maxes: bool,
+                       with_collection_frequency: bool) -> Result<Self> {
+        let mut position = 0;
+        let n_postings = read_varint(buffer, &mut position)? as usize;
+        let collection_frequency = match with_collection_frequency {
+            true => Some(read_varint(buffer, &mut position)?),
+            false => None,
+        };
+
+        let mut skip_entries = Vec::new();
+        let mut last_doc_id = 0;
+        let mut offset = 0;
+        for block_i in 0..n_postings.div_ceil(POSTINGS_BLOCK_SIZE) {
+            last_doc_id += read_varint(buffer, &mut position)? as DocId;
+            let block_size = read_varint(buffer, &mut position)? as usize;
+            let n_block_postings = POSTINGS_BLOCK_SIZE.min(n_postings - block_i * POSTINGS_BLOCK_SIZE);
+            let (max_tf, min_length) = if with_block_maxes {
+                (read_varint(buffer, &mut position)?, read_varint(buffer, &mut position)?)
+            } else {
+                (Tf::MAX, 0)
+            };
+
+            skip_entries.push(SkipEntry { last_doc_id, offset, n_postings: n_block_postings, max_tf, min_length });
+            offset += block_size;
+        }
+
+        Ok(Self {
+            blocks_data: &buffer[position..],
+            skip_entries,
+            with_positions,
+            n_postings,
+            collection_frequency,
+            block_i: 0,
+            loaded_block_i: None,
+            block_postings: Vec::new(),
+            block_positions: Vec::new(),
+            block_positions_ranges: Vec::new(),
+            block_posting_i: 0,
+            current: None,
+            current_positions: 0..0,
+        })
+    }
+
+    /// Moves to the next posting, returning its doc id and tf
+    pub fn next(&mut self) -> Result<Option<(DocId, Tf)>> {
+        let target = match self.current {
+            Some((doc_id, _)) => doc_id + 1,
+            None => 0,
+        };
+
+        self.advance(target)
+    }
+
+    /// Moves to the first posting with a doc id greater than or equal to the target, returning its doc id and tf.
+    /// The cursor never moves backwards, so it stays on the current posting if it's already past the target
+    pub fn advance(&mut self, target: DocId) -> Result<Option<(DocId, Tf)>> {
+        if let Some((doc_id, tf)) = self.current {
+            if doc_id >= target {
+                return Ok(Some((doc_id, tf)));
+            }
+        }
+
+        // Skip the blocks ending before the target, without decoding them
+        while self.block_i < self.skip_entries.len() && self.skip_entries[self.block_i].last_doc_id < target {
+            self.block_i += 1;
+            self.block_posting_i = 0;
+        }
+
+        if self.block_i == self.skip_entries.len() {
+            self.current = None;
+            return Ok(None);
+        }
+
+        self.load_block()?;
+
+        // The block's last doc id is at least the target, so it is found within the block
+        while self.block_postings[self.block_posting_i].0 < target {
+            self.block_posting_i += 1;
+        }
+
+        self.current = Some(self.block_postings[self.block_posting_i]);
+        self.current_positions = self.block_positions_ranges.get(self.block_posting_i).cloned().unwrap_or(0..0);
+        self.block_posting_i += 1;
+        if self.block_posting_i == self.block_postings.len() {
+            self.block_i += 1;
+            self.block_posting_i = 0;
+        }
+
+        Ok(self.current)
+    }
+
+    /// Number of documents in the postings list, including the deleted ones
+    pub fn n_postings(&self) -> usize {
+        self.n_postings
+    }
+
+    /// Sum of the tfs of the postings list (including the deleted documents), if known without decoding it
+    pub fn collection_frequency(&self) -> Option<Tf> {
+        self.collection_frequency
+    }
+
+    /// Maximum tf and minimum field length of every block of the postings list
+    pub fn block_maxes(&self) -> impl Iterator<Item = (Tf, u64)> + '_ {
+        self.skip_entries.iter().map(|skip_entry| (skip_entry.max_tf, skip_entry.min_length))
+    }
+
+    /// Last doc id, maximum tf and minimum field length of the block that would hold the target doc id, if any,
+    /// without moving the cursor
+    pub fn block_max(&self, target: DocId) -> Option<(DocId, Tf, u64)> {
+        let block_i = self.skip_entries.partition_point(|skip_entry| skip_entry.last_doc_id < target);
+
+        self.skip_entries.get(block_i)
+            .map(|skip_entry| (skip_entry.last_doc_id, skip_entry.max_tf, skip_entry.min_length))
+    }
+
+    /// Token positions of the current posting, empty if the field doesn't store them
+    pub fn positions(&self) -> &[Position] {
+        &self.block_positions[self.current_positions.clone()]
+    }
+
+    fn load_block(&mut self) -> Result<()> {
+        if self.loaded_block_i == Some(self.block_i) {
+            return Ok(());
+        }
+
+        let skip_entry = &self.skip_entries[self.block_i];
+        let mut doc_id = match self.block_i {
+            0 => 0,
+            block_i => self.skip_entries[block_i - 1].last_doc_id,
+        };
+
+        let mut position = skip_entry.offset;
+        self.block_postings.clear();
+        self.block_positions.clear();
+        self.block_positions_ranges.clear();
+        for _ in 0..skip_entry.n_postings {
+            doc_id += read_varint(self.blocks_data, &mut position)? as DocId;
+            let tf = read_varint(self.blocks_data, &mut position)?;
+            self.block_postings.push((doc_id, tf));
+
+            if self.with_positions {
+                let positions_start = self.block_positions.len();
+                let mut token_position = 0;
+                for _ in 0..tf {
+                    token_position += read_varint(self.blocks_data, &mut position)? as Position;
+                    self.block_positions.push(token_position);
+                }
+                self.block_positions_ranges.push(positions_start..self.block_positions.len());
+            }
+        }
+
+        self.loaded_block_i = Some(self.block_i);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexing::lengths::Lengths;
+    use crate::indexing::postings_codec::{Posting, PostingsCodec};
+    use std::collections::BTreeMap;
+
+    // Even doc ids, so that every block holds POSTINGS_BLOCK_SIZE of them and odd targets fall between postings
+    const N_POSTINGS: usize = 3 * POSTINGS_BLOCK_SIZE + 5;
+
+    fn encoded_postings(codec: PostingsCodec) -> Vec<u8> {
+        let postings: BTreeMap<DocId, Posting> = (0..N_POSTINGS as DocId)
+            .map(|i| (2 * i, Posting { tf: (i % 3 + 1) as Tf, positions: (0..i % 3 + 1).map(|position| position * 2).collect() }))
+            .collect();
+
+        codec.encode(&postings, true, &Lengths::new("body".to_string()))
+    }
+
+    fn tf(doc_id: DocId) -> Tf {
+        (doc_id / 2 % 3 + 1) as Tf
+    }
+
+    #[test]
+    fn advance_across_blocks() {
+        for codec in [PostingsCodec::DeltaVarint, PostingsCodec::BlockVarint, PostingsCodec::BlockMaxStatsVarint] {
+            let buffer = encoded_postings(codec);
+            let mut cursor = codec.cursor(&buffer, true).unwrap();
+            let block_last_doc_id = 2 * (POSTINGS_BLOCK_SIZE as DocId - 1);
+
+            assert_eq!(cursor.next().unwrap(), Some((0, 1)));
+            // The last posting of the first block, and the first one of the second block
+            assert_eq!(cursor.advance(block_last_doc_id).unwrap(), Some((block_last_doc_id, tf(block_last_doc_id))));
+            assert_eq!(cursor.next().unwrap(), Some((block_last_doc_id + 2, tf(block_last_doc_id + 2))));
+            // Between two postings of the third block, skipping the rest of the second one
+            let target = 2 * (2 * POSTINGS_BLOCK_SIZE as DocId) + 11;
+            assert_eq!(cursor.advance(target).unwrap(), Some((target + 1, tf(target + 1))));
+            // Never backwards
+            assert_eq!(cursor.advance(3).unwrap(), Some((target + 1, tf(target + 1))));
+        }
+    }
+
+    #[test]
+    fn advance_past_the_end() {
+        for codec in [PostingsCodec::DeltaVarint, PostingsCodec::BlockMaxStatsVarint] {
+            let buffer = encoded_postings(codec);
+            let mut cursor = codec.cursor(&buffer, true).unwrap();
+            let last_doc_id = 2 * (N_POSTINGS as DocId - 1);
+
+            assert_eq!(cursor.advance(last_doc_id).unwrap(), Some((last_doc_id, tf(last_doc_id))));
+            assert_eq!(cursor.next().unwrap(), None);
+            assert_eq!(cursor.next().unwrap(), None);
+
+            let mut cursor = codec.cursor(&buffer, true).unwrap();
+            assert_eq!(cursor.advance(last_doc_id + 1).unwrap(), None);
+            assert_eq!(cursor.advance(DocId::MAX).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn positions_follow_the_cursor() {
+        let codec = PostingsCodec::BlockMaxStatsVarint;
+        let buffer = encoded_postings(codec);
+        let mut cursor = codec.cursor(&buffer, true).unwrap();
+
+        for target in [0, 2 * POSTINGS_BLOCK_SIZE as DocId + 4, 2 * (N_POSTINGS as DocId - 1)] {
+            let (doc_id, tf) = cursor.advance(target).unwrap().unwrap();
+            assert_eq!(doc_id, target);
+            assert_eq!(cursor.positions(), (0..tf as Position).map(|position| position * 2).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn block_max_of_target() {
+        let codec = PostingsCodec::BlockMaxStatsVarint;
+        let buffer = encoded_postings(codec);
+        let cursor = codec.cursor(&buffer, true).unwrap();
+        let block_last_doc_id = 2 * (POSTINGS_BLOCK_SIZE as DocId - 1);
+
+        assert_eq!(cursor.block_max(0), Some((block_last_doc_id, 3, 0)));
+        assert_eq!(cursor.block_max(block_last_doc_id + 1), Some((2 * block_last_doc_id + 2, 3, 0)));
+        assert_eq!(cursor.block_max(2 * N_POSTINGS as DocId), None);
+        assert_eq!(cursor.block_maxes().count(), N_POSTINGS.div_ceil(POSTINGS_BLOCK_SIZE));
+    }
+}
@@ -0,0 +1,369 @@
+// Single-pass in-memory indexing (SPIMI) with a memory budget. Postings are accumulated in memory until the budget is
+// reached, at which point every field's postings are sorted by token and flushed to disk as a run. Once all documents
+// have been processed, the runs of each field are k-way merged into the regular postings data and FST index files
+//
+// The docids, lengths and vocabulary (kept for the reversed tokens index) of a segment can't be spilled as runs, so they
+// also count in the budget, and once they take half of it the segment is finished and a new one started
+
+use crate::index_directory::SegmentDirectory;
+use crate::indexing::docids::DocId;
+use crate::indexing::lengths::Lengths;
+use crate::indexing::postings::PostingsFileWriter;
+use crate::indexing::postings_codec::{Position, Posting};
+use crate::tokenizer::Token;
+use anyhow::{Context, Result};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::path::Path;
+
+type Run = (String, Vec<(DocId, Posting)>);
+
+// Rough per-entry overheads (String/Vec headers and hash table slots), used to estimate the used memory
+const TOKEN_OVERHEAD: usize = 64;
+const POSTING_SIZE: usize = mem::size_of::<(DocId, Posting)>();
+const POSITION_SIZE: usize = mem::size_of::<Position>();
+const DOCID_OVERHEAD: usize = mem::size_of::<String>();
+// A length per field of every document, in a BTreeMap
+const LENGTH_SIZE: usize = 2 * mem::size_of::<(DocId, u64)>();
+
+pub struct SpimiPostings {
+    segment_directory: SegmentDirectory,
+    positional_index_keys: HashSet<String>,
+    memory_budget: usize,
+    used_memory: usize,
+    // Memory of the docids, lengths and vocabulary of the segment, which is only released once the segment is written
+    segment_memory: usize,
+    n_runs: usize,
+    // Index key -> Token -> Postings, in doc id order
+    postings: HashMap<String, HashMap<String, Vec<(DocId, Posting)>>>,
+    // Index key -> Tokens of the segment
+    vocabulary: HashMap<String, HashSet<String>>,
+}
+
+impl SpimiPostings {
+    pub fn new(segment_directory: &SegmentDirectory,
+               positional_index_keys: &HashSet<String>,
+               memory_budget: usize) -> Result<Self> {
+        fs::create_dir_all(segment_directory.spimi_runs_path())
+            .with_context(|| "Failed to create the directory for the indexing runs")?;
+
+        Ok(Self {
+            segment_directory: segment_directory.clone(),
+            positional_index_keys: positional_index_keys.clone(),
+            memory_budget,
+            used_memory: 0,
+            segment_memory: 0,
+            n_runs: 0,
+            postings: HashMap::new(),
+            vocabulary: HashMap::new(),
+        })
+    }
+
+    /// Counts the memory of a document's docid, which is kept until the segment is written
+    pub fn add_docid(&mut self, docid: &str) {
+        self.segment_memory += DOCID_OVERHEAD + docid.len();
+    }
+
+    /// Whether the docids, lengths and vocabulary take half of the memory budget, leaving too little for the postings,
+    /// so that the segment must be finished
+    pub fn is_segment_full(&self) -> bool {
+        self.segment_memory >= self.memory_budget / 2
+    }
+
+    /// Add all the tokens of a document's field, flushing a new run to disk if the memory budget is exceeded
+    pub fn add_tokens(&mut self, index_key: &str, doc_id: DocId, tokens: &[Token]) -> Result<()> {
+        let with_positions = self.positional_index_keys.contains(index_key);
+        let mut doc_postings: HashMap<&String, Posting> = HashMap::new();
+        for token in tokens {
+            doc_postings.entry(&token.text).or_default().add_occurrence(token.position, with_positions);
+        }
+
+        let field_postings = self.postings.entry(index_key.to_string()).or_default();
+        let field_vocabulary = self.vocabulary.entry(index_key.to_string()).or_default();
+        self.segment_memory += LENGTH_SIZE;
+        for (token, posting) in doc_postings {
+            if !field_postings.contains_key(token) {
+                self.used_memory += TOKEN_OVERHEAD + token.len();
+            }
+            // Once in the vocabulary, and once in the reversed tokens written with the postings
+            if field_vocabulary.insert(token.clone()) {
+                self.segment_memory += 2 * (TOKEN_OVERHEAD + token.len());
+            }
+            self.used_memory += POSTING_SIZE + POSITION_SIZE * posting.positions.len();
+
+            field_postings
+                .entry(token.clone())
+                .or_default()
+                .push((doc_id, posting));
+        }
+
+        if self.used_memory + self.segment_memory >= self.memory_budget {
+            self.flush_run()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the in-memory postings of every field as a new sorted run
+    fn flush_run(&mut self) -> Result<()> {
+        for (index_key, field_postings) in self.postings.iter_mut() {
+            let mut ordered_postings: Vec<Run> = field_postings.drain().collect();
+            ordered_postings.sort_by(|a, b| a.0.cmp(&b.0));
+
+            let run_path = self.segment_directory.spimi_run_path(index_key, self.n_runs);
+            let mut wtr = io::BufWriter::new(File::create(&run_path)?);
+
+            // The number of tokens first, so that readers know when to stop
+            bincode::serialize_into(&mut wtr, &(ordered_postings.len() as u64))?;
+            for entry in &ordered_postings {
+                bincode::serialize_into(&mut wtr, entry)
+                    .with_context(|| format!("Failed to write indexing run {:?}", run_path))?;
+            }
+            wtr.flush()?;
+        }
+
+        self.n_runs += 1;
+        self.used_memory = 0;
+
+        Ok(())
+    }
+
+    /// Flush the remaining postings and merge every field's runs into its final postings files, removing the runs
+    pub fn write_postings(mut self, index_keys: &[String], lengths_writers: &HashMap<String, Lengths>) -> Result<()> {
+        if self.used_memory > 0 {
+            self.flush_run()?;
+        }
+
+        for index_key in index_keys {
+            let mut run_readers = Vec::new();
+            for run in 0..self.n_runs {
+                let run_path = self.segment_directory.spimi_run_path(index_key, run);
+                if run_path.exists() {
+                    run_readers.push(RunReader::open(&run_path)?);
+                }
+            }
+
+            self.merge_runs(index_key, run_readers, lengths_writers.get(index_key).unwrap())?;
+        }
+
+        fs::remove_dir_all(self.segment_directory.spimi_runs_path())
+            .with_context(|| "Failed to remove the indexing runs")?;
+
+        Ok(())
+    }
+
+    fn merge_runs(&self, index_key: &str, mut run_readers: Vec<RunReader>, field_lengths: &Lengths) -> Result<()> {
+        let with_positions = self.positional_index_keys.contains(index_key);
+        let mut writer = PostingsFileWriter::create(&self.segment_directory, index_key, with_positions, field_lengths)?;
+
+        // Min-heap of the current token of every run
+        let mut heap = BinaryHeap::new();
+        for (run_i, run_reader) in run_readers.iter_mut().enumerate() {
+            if let Some(token) = run_reader.next_token()? {
+                heap.push(Reverse((token, run_i)));
+            }
+        }
+
+        while let Some(Reverse((token, run_i))) = heap.pop() {
+            let mut merged_postings: BTreeMap<DocId, Posting> = BTreeMap::new();
+            let mut runs_to_advance = vec![run_i];
+
+            while let Some(Reverse((next_token, _))) = heap.peek() {
+                if *next_token != token {
+                    break;
+                }
+
+                let Reverse((_, next_run_i)) = heap.pop().unwrap();
+                runs_to_advance.push(next_run_i);
+            }
+
+            for run_i in runs_to_advance {
+                for (doc_id, posting) in run_readers[run_i].take_postings() {
+                    merged_postings.entry(doc_id).or_default().merge(posting);
+                }
+
+                if let Some(next_token) = run_readers[run_i].next_token()? {
+                    heap.push(Reverse((next_token, run_i)));
+                }
+            }
+
+            writer.add_postings(&token, &merged_postings)?;
+        }
+
+        writer.finish()
+    }
+}
+
+/// Sequential reader over a run file
+struct RunReader {
+    reader: io::BufReader<File>,
+    remaining: u64,
+    current_postings: Vec<(DocId, Posting)>,
+}
+
+impl RunReader {
+    fn open(run_path: &Path) -> Result<Self> {
+        let mut reader = io::BufReader::new(
+            File::open(run_path).with_context(|| format!("Failed to open indexing run {:?}", run_path))?,
+        );
+        let remaining: u64 = bincode::deserialize_from(&mut reader)?;
+
+        Ok(Self { reader, remaining, current_postings: Vec::new() })
+    }
+
+    /// Read the next token of the run, keeping its postings until they are taken
+    fn next_token(&mut self) -> Result<Option<String>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        let (token, postings): Run = bincode::deserialize_from(&mut self.reader)
+            .with_context(|| "Failed to read indexing run")?;
+        self.remaining -= 1;
+        self.current_postings = postings;
+
+        Ok(Some(token))
+    }
+
+    fn take_postings(&mut self) -> Vec<(DocId, Posting)> {
+        mem::take(&mut self.current_postings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index_directory::{IndexDirectory, Manifest};
+    use crate::retrieval::segment::Segment;
+    use crate::test_index::{doc, Doc, TestIndex};
+    use fst::Streamer;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const N_DOCS: usize = 300;
+
+    type FieldPostings = (String, String, Vec<(DocId, u64, Vec<Position>)>);
+
+    const INDEX_KEYS: [&str; 2] = ["title", "body"];
+
+    // Few distinct words, so that every token is spread over many runs, and repeated within the documents
+    fn random_docs() -> Vec<Doc> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut random_text = |max_length: usize| {
+            (0..rng.gen_range(1..=max_length))
+                .map(|_| format!("w{}", rng.gen_range(0..20)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        (0..N_DOCS)
+            .map(|doc_i| {
+                let title = random_text(3);
+                let body = random_text(30);
+                doc(&format!("d{}", doc_i), &[("title", &title), ("body", &body)])
+            })
+            .collect()
+    }
+
+    /// Every token of every field, with its decoded postings and their positions
+    fn all_postings(index_directory: &IndexDirectory) -> Vec<FieldPostings> {
+        let index_keys: Vec<String> = INDEX_KEYS.iter().map(|index_key| index_key.to_string()).collect();
+        let segment_name = Manifest::load(index_directory).unwrap().segments[0].name.clone();
+        let segment = Segment::open(&index_keys, &index_directory.segment(&segment_name)).unwrap();
+
+        let mut all_postings = Vec::new();
+        for index_key in index_keys {
+            let mut stream = segment.get_postings_map(&index_key).unwrap().stream();
+            while let Some((token, postings_position)) = stream.next() {
+                let mut cursor = segment.read_postings(&index_key, postings_position).unwrap();
+                let mut postings = Vec::new();
+                while let Some((doc_id, tf)) = cursor.next().unwrap() {
+                    postings.push((doc_id, tf, cursor.positions().to_vec()));
+                }
+                all_postings.push((index_key.clone(), String::from_utf8(token.to_vec()).unwrap(), postings));
+            }
+        }
+
+        all_postings
+    }
+
+    #[test]
+    fn runs_are_spilled_once_over_budget() {
+        let index = TestIndex::new("spimi_runs");
+        let segment_directory = index.directory.segment("segment");
+        fs::create_dir_all(segment_directory.spimi_runs_path()).unwrap();
+
+        let tokens = |text: &str| -> Vec<Token> {
+            text.split(' ').enumerate().map(|(position, token)| Token::new(token.to_string(), position as Position)).collect()
+        };
+        let positional_index_keys = HashSet::from(["body".to_string()]);
+        // A posting with a position and a new token take the whole budget, so every document is spilled
+        let mut spimi_postings = SpimiPostings::new(&segment_directory, &positional_index_keys, TOKEN_OVERHEAD + POSTING_SIZE).unwrap();
+        let mut lengths = Lengths::new("body".to_string());
+        for (doc_id, text) in ["b a", "a c", "c a a"].iter().enumerate() {
+            spimi_postings.add_tokens("body", doc_id as DocId, &tokens(text)).unwrap();
+            lengths.add_length(doc_id as DocId, tokens(text).len() as u64);
+        }
+        let n_runs = spimi_postings.n_runs;
+        let run_exists = segment_directory.spimi_run_path("body", 2).exists();
+        spimi_postings.write_postings(&["body".to_string()], &HashMap::from([("body".to_string(), lengths)])).unwrap();
+        let runs_removed = !segment_directory.spimi_runs_path().exists();
+
+        assert_eq!(n_runs, 3);
+        assert!(run_exists);
+        assert!(runs_removed);
+    }
+
+    #[test]
+    fn merged_runs_match_in_memory_indexing() {
+        let spimi_index = TestIndex::new("spimi_merge_spimi");
+        let mut indexer = spimi_index.indexer(&INDEX_KEYS);
+        indexer.store_positions("body");
+        // A budget big enough for the docids, lengths and vocabulary of a single segment, but spilling a few runs
+        indexer.index_with_memory_budget(random_docs().into_iter(), 80 * 1024).unwrap();
+        assert_eq!(Manifest::load(&spimi_index.directory).unwrap().segments.len(), 1);
+
+        let in_memory_index = TestIndex::new("spimi_merge_in_memory");
+        let mut indexer = in_memory_index.indexer(&INDEX_KEYS);
+        indexer.store_positions("body");
+        indexer.index(random_docs().into_iter()).unwrap();
+
+        let spimi_postings = all_postings(&spimi_index.directory);
+        let in_memory_postings = all_postings(&in_memory_index.directory);
+
+        assert!(spimi_postings.iter().any(|(index_key, _, postings)| index_key == "title" && postings.len() > 1));
+        assert_eq!(spimi_postings, in_memory_postings);
+    }
+
+    #[test]
+    fn docids_and_lengths_alone_start_new_segments() {
+        let index = TestIndex::new("spimi_docids");
+        // Documents without tokens, so that the postings take no memory at all
+        let docs: Vec<Doc> = (0..100).map(|doc_i| doc(&format!("document_{:03}", doc_i), &[("title", ""), ("body", "")])).collect();
+
+        let mut spimi_postings = SpimiPostings::new(&index.directory.segment("segment"), &HashSet::new(), 1024).unwrap();
+        let mut n_segment_docs = 0;
+        while !spimi_postings.is_segment_full() {
+            spimi_postings.add_docid("document_000");
+            spimi_postings.add_tokens("title", n_segment_docs, &[]).unwrap();
+            spimi_postings.add_tokens("body", n_segment_docs, &[]).unwrap();
+            n_segment_docs += 1;
+        }
+        // Every document takes its docid and a length per field
+        let doc_memory = DOCID_OVERHEAD + "document_000".len() + 2 * LENGTH_SIZE;
+        assert_eq!(n_segment_docs as usize, 512_usize.div_ceil(doc_memory));
+        assert_eq!(spimi_postings.n_runs, 0);
+
+        index.indexer(&INDEX_KEYS).index_with_memory_budget(docs.into_iter(), 1024).unwrap();
+        let segments = Manifest::load(&index.directory).unwrap().segments;
+
+        assert!(segments.len() > 1, "{:?}", segments);
+        assert!(segments.iter().all(|segment_info| segment_info.n_docs <= n_segment_docs as usize));
+        assert_eq!(segments.iter().map(|segment_info| segment_info.n_docs).sum::<usize>(), 100);
+    }
+}
@@ -1,11 +1,22 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io;
 use std::io::Write;
 use anyhow::Result;
+use crate::index_directory::SegmentDirectory;
+use crate::indexing::postings_codec::CURRENT_POSTINGS_CODEC_VERSION;
 use serde::{Deserialize, Serialize};
 #[derive(Serialize, Deserialize, Debug)]
 pub struct IndexStats {
-    pub n_docs: usize
+    pub n_docs: usize,
+    // Number of documents containing each field, needed for combining the average lengths of several segments
+    pub field_n_docs: HashMap<String, u64>,
+    // Version of the codec the postings were written with. Segments written before it was recorded used version 0
+    #[serde(default)]
+    pub postings_codec_version: u32,
+    // Fields whose postings store the token positions
+    #[serde(default)]
+    pub positional_fields: HashSet<String>,
     // More stuff could go here
 }
 
@@ -14,14 +25,21 @@ pub struct Stats {
 }
 
 impl Stats {
-    pub fn new(n_docs: usize) -> Self {
-        Self { stats: IndexStats { n_docs } }
+    pub fn new(n_docs: usize, field_n_docs: HashMap<String, u64>, positional_fields: HashSet<String>) -> Self {
+        Self {
+            stats: IndexStats {
+                n_docs,
+                field_n_docs,
+                postings_codec_version: CURRENT_POSTINGS_CODEC_VERSION,
+                positional_fields,
+            }
+        }
     }
 
-    pub fn write_stats(&self) -> Result<()> {
+    pub fn write_stats(&self, segment_directory: &SegmentDirectory) -> Result<()> {
         let stats_json = serde_json::to_string(&self.stats)?;
 
-        let mut wtr = io::BufWriter::new(File::create("index_stats.json")?);
+        let mut wtr = io::BufWriter::new(File::create(segment_directory.stats_path())?);
         wtr.write_all(stats_json.as_bytes())?;
         
         Ok(())
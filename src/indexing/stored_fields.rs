@@ -0,0 +1,257 @@
+// Stored fields of a segment, keeping the original text of the selected fields of every document so that results can
+// be returned with their contents. Documents are serialized in doc id order into blocks of around
+// STORED_FIELDS_BLOCK_SIZE bytes, which are LZ4-compressed and appended to the data file. The index file holds a
+// fixed-size record per block (its first doc id and its position in the data file), so a document is read by binary
+// searching its block and decompressing only that one
+
+use crate::index_directory::SegmentDirectory;
+use crate::indexing::docids::DocId;
+use anyhow::{anyhow, Context, Result};
+use memmap::Mmap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+
+// Index key -> Original text of the field
+pub type StoredDoc = HashMap<String, String>;
+
+const STORED_FIELDS_BLOCK_SIZE: usize = 16 * 1024;
+const BLOCK_RECORD_LENGTH: usize = 12; // First doc id (u32) and position in the data file (u64)
+
+pub struct StoredFieldsWriter {
+    index_wtr: io::BufWriter<File>,
+    data_wtr: io::BufWriter<File>,
+    data_position: u64,
+    block_first_doc_id: DocId,
+    block_docs: Vec<StoredDoc>,
+    block_size: usize,
+}
+
+impl StoredFieldsWriter {
+    pub fn create(segment_directory: &SegmentDirectory) -> Result<Self> {
+        Ok(Self {
+            index_wtr: io::BufWriter::new(File::create(segment_directory.stored_fields_index_path())?),
+            data_wtr: io::BufWriter::new(File::create(segment_directory.stored_fields_data_path())?),
+            data_position: 0,
+            block_first_doc_id: 0,
+            block_docs: Vec::new(),
+            block_size: 0,
+        })
+    }
+
+    /// Every document of the segment must be added, in doc id order, even if it has no stored fields
+    pub fn add_doc(&mut self, doc_id: DocId, stored_doc: StoredDoc) -> Result<()> {
+        if self.block_docs.is_empty() {
+            self.block_first_doc_id = doc_id;
+        }
+
+        self.block_size += stored_doc.iter().map(|(index_key, text)| index_key.len() + text.len()).sum::<usize>();
+        self.block_docs.push(stored_doc);
+
+        if self.block_size >= STORED_FIELDS_BLOCK_SIZE {
+            self.flush_block()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> Result<()> {
+        let compressed_block = lz4_flex::compress_prepend_size(&bincode::serialize(&self.block_docs)?);
+
+        self.index_wtr.write_all(&self.block_first_doc_id.to_le_bytes())?;
+        self.index_wtr.write_all(&self.data_position.to_le_bytes())?;
+        self.data_wtr.write_all(&compressed_block)?;
+
+        self.data_position += compressed_block.len() as u64;
+        self.block_docs.clear();
+        self.block_size = 0;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        if !self.block_docs.is_empty() {
+            self.flush_block()?;
+        }
+
+        self.index_wtr.flush()?;
+        self.data_wtr.flush()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct StoredFields {
+    index_file: Mmap,
+    data_file: Mmap,
+}
+
+impl StoredFields {
+    /// Opens the segment's stored fields, if it has any (segments written before they were stored don't)
+    pub fn open(segment_directory: &SegmentDirectory) -> Result<Option<Self>> {
+        let index_path = segment_directory.stored_fields_index_path();
+        if !index_path.exists() {
+            return Ok(None);
+        }
+
+        let index_file = unsafe { Mmap::map(&File::open(&index_path)?)? };
+        let data_file = unsafe { Mmap::map(&File::open(segment_directory.stored_fields_data_path())?)? };
+
+        Ok(Some(Self { index_file, data_file }))
+    }
+
+    fn n_blocks(&self) -> usize {
+        self.index_file.len() / BLOCK_RECORD_LENGTH
+    }
+
+    fn block_record(&self, block_i: usize) -> (DocId, usize) {
+        let record = &self.index_file[block_i * BLOCK_RECORD_LENGTH..(block_i + 1) * BLOCK_RECORD_LENGTH];
+
+        (DocId::from_le_bytes(record[..4].try_into().unwrap()), u64::from_le_bytes(record[4..].try_into().unwrap()) as usize)
+    }
+
+    /// First doc id and stored documents of a block
+    fn read_block(&self, block_i: usize) -> Result<(DocId, Vec<StoredDoc>)> {
+        let (first_doc_id, start) = self.block_record(block_i);
+        let end = if block_i + 1 < self.n_blocks() { self.block_record(block_i + 1).1 } else { self.data_file.len() };
+
+        let block = lz4_flex::decompress_size_prepended(&self.data_file[start..end])
+            .with_context(|| format!("Failed to decompress stored fields block {}", block_i))?;
+
+        Ok((first_doc_id, bincode::deserialize(&block)?))
+    }
+
+    pub fn get_doc(&self, doc_id: DocId) -> Result<StoredDoc> {
+        // The last block starting at or before the document
+        let (mut low, mut high) = (0, self.n_blocks());
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.block_record(mid).0 <= doc_id {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        let block_i = low.checked_sub(1)
+            .ok_or_else(|| anyhow!(format!("Couldn't find the stored fields of doc id {}", doc_id)))?;
+
+        let (first_doc_id, mut block_docs) = self.read_block(block_i)?;
+        let block_doc_i = (doc_id - first_doc_id) as usize;
+        if block_doc_i >= block_docs.len() {
+            return Err(anyhow!(format!("Couldn't find the stored fields of doc id {}", doc_id)));
+        }
+
+        Ok(block_docs.swap_remove(block_doc_i))
+    }
+
+    /// Every stored document, in doc id order, decompressing each block once
+    pub fn docs(&self) -> impl Iterator<Item = Result<(DocId, StoredDoc)>> + '_ {
+        (0..self.n_blocks()).flat_map(move |block_i| {
+            let block_docs: Vec<Result<(DocId, StoredDoc)>> = match self.read_block(block_i) {
+                Ok((first_doc_id, block_docs)) => block_docs.into_iter()
+                    .enumerate()
+                    .map(|(block_doc_i, stored_doc)| Ok((first_doc_id + block_doc_i as DocId, stored_doc)))
+                    .collect(),
+                Err(error) => vec![Err(error)],
+            };
+
+            block_docs
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index_directory::Manifest;
+    use crate::test_index::{doc, TestIndex};
+
+    fn stored_doc(fields: &[(&str, &str)]) -> StoredDoc {
+        fields.iter().map(|(index_key, text)| (index_key.to_string(), text.to_string())).collect()
+    }
+
+    #[test]
+    fn docs_are_read_back_across_blocks() {
+        let index = TestIndex::new("stored_fields_blocks");
+        let segment_directory = index.directory.create_segment("segment").unwrap();
+
+        // A third of a block each, so that blocks end in the middle of the documents, and some without stored fields
+        let docs: Vec<StoredDoc> = (0..10)
+            .map(|doc_i| match doc_i % 4 {
+                3 => StoredDoc::new(),
+                _ => stored_doc(&[("title", &format!("title {}", doc_i)), ("body", &format!("{} ", doc_i).repeat(STORED_FIELDS_BLOCK_SIZE / 6))]),
+            })
+            .collect();
+        let mut writer = StoredFieldsWriter::create(&segment_directory).unwrap();
+        for (doc_id, doc) in docs.iter().enumerate() {
+            writer.add_doc(doc_id as DocId, doc.clone()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let stored_fields = StoredFields::open(&segment_directory).unwrap().unwrap();
+        assert!(stored_fields.n_blocks() > 2, "{} blocks", stored_fields.n_blocks());
+        for (doc_id, doc) in docs.iter().enumerate() {
+            assert_eq!(&stored_fields.get_doc(doc_id as DocId).unwrap(), doc, "doc id {}", doc_id);
+        }
+        let read_docs: Vec<(DocId, StoredDoc)> = stored_fields.docs().collect::<Result<_>>().unwrap();
+        assert_eq!(read_docs, docs.into_iter().enumerate().map(|(doc_id, doc)| (doc_id as DocId, doc)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn missing_and_deleted_docs_are_not_found() {
+        let index = TestIndex::new("stored_fields_missing");
+        let segment_directory = index.directory.create_segment("segment").unwrap();
+        assert!(StoredFields::open(&segment_directory).unwrap().is_none());
+
+        let mut writer = StoredFieldsWriter::create(&segment_directory).unwrap();
+        writer.add_doc(0, stored_doc(&[("body", "rust")])).unwrap();
+        writer.finish().unwrap();
+        let stored_fields = StoredFields::open(&segment_directory).unwrap().unwrap();
+        assert_eq!(stored_fields.get_doc(1).unwrap_err().to_string(), "Couldn't find the stored fields of doc id 1");
+
+        let mut indexer = index.indexer(&["body"]);
+        indexer.store_fields("body");
+        indexer.index([doc("d1", &[("body", "rust")]), doc("d2", &[("body", "java")])].into_iter()).unwrap();
+        indexer.delete("d2").unwrap();
+        let retriever = index.retriever(&["body"]);
+
+        assert_eq!(retriever.get_document("d1").unwrap(), stored_doc(&[("body", "rust")]));
+        assert_eq!(retriever.get_document("d2").unwrap_err().to_string(), "Couldn't find a live document with docid d2");
+        assert_eq!(retriever.get_document("d3").unwrap_err().to_string(), "Couldn't find a live document with docid d3");
+    }
+
+    #[test]
+    fn merged_segments_keep_the_stored_fields() {
+        let index = TestIndex::new("stored_fields_merge");
+        let mut indexer = index.indexer(&["title", "body"]);
+        indexer.store_fields("title");
+        indexer.store_fields("body");
+        let long_body = "rust ".repeat(STORED_FIELDS_BLOCK_SIZE / 4);
+        let docs = |docids: &[&str]| -> Vec<_> {
+            docids.iter().map(|docid| doc(docid, &[("title", &format!("title of {}", docid)), ("body", &long_body)])).collect()
+        };
+        indexer.index(docs(&["d1", "d2", "d3"]).into_iter()).unwrap();
+        indexer.index(docs(&["d4", "d5"]).into_iter()).unwrap();
+        indexer.index(docs(&["d6"]).into_iter()).unwrap();
+        indexer.delete("d2").unwrap();
+
+        // Like a segment written before the fields were stored
+        let segment_name = Manifest::load(&index.directory).unwrap().segments[2].name.clone();
+        let segment_directory = index.directory.segment(&segment_name);
+        std::fs::remove_file(segment_directory.stored_fields_index_path()).unwrap();
+        std::fs::remove_file(segment_directory.stored_fields_data_path()).unwrap();
+
+        indexer.force_merge().unwrap();
+        let retriever = index.retriever(&["title", "body"]);
+
+        assert_eq!(Manifest::load(&index.directory).unwrap().segments.len(), 1);
+        for docid in ["d1", "d3", "d4", "d5"] {
+            let expected_doc = stored_doc(&[("title", &format!("title of {}", docid)), ("body", &long_body)]);
+            assert_eq!(retriever.get_document(docid).unwrap(), expected_doc, "{}", docid);
+        }
+        assert!(retriever.get_document("d2").is_err());
+        assert_eq!(retriever.get_document("d6").unwrap(), StoredDoc::new());
+    }
+}
@@ -1,11 +1,15 @@
 mod aux;
 mod document_reader;
+mod index_directory;
 mod indexing;
 mod retrieval;
+#[cfg(test)]
+mod test_index;
 mod tokenizer;
 
 use crate::document_reader::*;
-use crate::indexing::Indexer;
+use crate::index_directory::IndexDirectory;
+use crate::indexing::{Indexer, TieredMergePolicy};
 use anyhow::{Context, Result};
 use rand::{Rng};
 use serde::Serialize;
@@ -33,7 +37,7 @@ fn get_random_doc_id() -> String {
         .collect()
 }
 
-fn generate_files_to_index(n_files: usize) {
+fn generate_files_to_index(n_files: usize) -> Vec<String> {
     let mut rng = rand::thread_rng();
 
     let mut tokens_field_1 : Vec<&str> = Vec::new();
@@ -46,6 +50,7 @@ fn generate_files_to_index(n_files: usize) {
         tokens_field_2.push(random_word::gen(Lang::En));
     }
 
+    let mut doc_ids = Vec::new();
     for _ in 0..n_files {
         let doc_id = get_random_doc_id();
         let data = DummyFile {
@@ -57,13 +62,25 @@ fn generate_files_to_index(n_files: usize) {
         let json = serde_json::to_string_pretty(&data).expect("Failed to serialize data");
 
         std::fs::write(format!("documents/{}.json", doc_id), json).expect("Failed to write file");
+        doc_ids.push(doc_id);
     }
+
+    doc_ids
+}
+
+/// Returns the value following a `--name value` command line argument, if present
+fn get_arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1).cloned())
 }
 
 fn main() -> Result<()> {
     let n_docs = 1000;
     println!("Generating random document collection of size {}...", n_docs);
-    generate_files_to_index(n_docs);
+    let doc_ids = generate_files_to_index(n_docs);
 
     let mut field_keys = HashMap::new(); // Document field name -> Index field name
     field_keys.insert("field1".to_string(), "field1_index_name".to_string());
@@ -71,9 +88,75 @@ fn main() -> Result<()> {
     let doc_reader = DocumentReader::new(field_keys.clone(), "./documents".to_string());
     let docs_iter = doc_reader.process_documents().unwrap();
 
-    let mut indexer = Indexer::new(field_keys.clone());
-    indexer.index(docs_iter)
-        .with_context(|| "Error during indexing:")?;
+    // Every run re-indexes the whole documents directory, so start from an empty index instead of adding a new
+    // segment with the same documents to the previous one
+    if std::path::Path::new("./index").exists() {
+        std::fs::remove_dir_all("./index")
+            .with_context(|| "Error removing the previous index:")?;
+    }
+    let index_directory = IndexDirectory::create("./index")
+        .with_context(|| "Error creating the index directory:")?;
+
+    let mut indexer = Indexer::new(field_keys.clone(), index_directory);
+    // Token positions are needed for phrase queries, and stored fields for returning the results with their contents
+    indexer.store_positions("field1_index_name");
+    indexer.store_fields("field2_index_name");
+
+    // Fields use the default analyzer unless configured otherwise, e.g. splitting the first field by the Unicode word
+    // boundaries (and its CJK text into bigrams), folding its accents (so that "café" matches "cafe"), removing its
+    // stopwords and stemming its words so that queries match their other forms, or keeping the punctuation of the
+    // second field and indexing its trigrams, so that queries match parts of its words.
+    // The stopwords are the built-in English ones, unless a custom list is given (e.g. `--stopwords-file words.txt`)
+    let stopword_filter = match get_arg_value("--stopwords-file") {
+        Some(stopwords_path) => tokenizer::StopwordFilter::from_file(std::path::Path::new(&stopwords_path))
+            .with_context(|| "Error loading the stopwords:")?,
+        None => tokenizer::StopwordFilter::for_language(tokenizer::Language::English),
+    };
+    indexer.set_analyzer("field1_index_name", tokenizer::Analyzer::new(
+        tokenizer::TokenizerConfig::Unicode(tokenizer::UnicodeTokenizer),
+        vec![
+            tokenizer::TokenFilterConfig::CjkBigram(tokenizer::CjkBigramFilter),
+            tokenizer::TokenFilterConfig::Lowercase(tokenizer::LowercaseFilter),
+            tokenizer::TokenFilterConfig::AsciiFolding(tokenizer::AsciiFoldingFilter),
+            tokenizer::TokenFilterConfig::Stopword(stopword_filter),
+            tokenizer::TokenFilterConfig::Stemmer(tokenizer::StemmerFilter::new(tokenizer::Language::English)),
+        ],
+    ));
+    indexer.set_analyzer("field2_index_name", tokenizer::Analyzer::new(
+        tokenizer::TokenizerConfig::Whitespace(tokenizer::WhitespaceTokenizer),
+        vec![
+            tokenizer::TokenFilterConfig::Lowercase(tokenizer::LowercaseFilter),
+            tokenizer::TokenFilterConfig::NGram(tokenizer::NGramFilter::new(3, 3)),
+        ],
+    ));
+
+    // With a memory budget (e.g. `--memory-budget-mb 512`), the postings are spilled to disk instead of being kept
+    // in memory by every indexing thread
+    match get_arg_value("--memory-budget-mb") {
+        Some(memory_budget_mb) => {
+            let memory_budget_mb: usize = memory_budget_mb.parse()
+                .with_context(|| "Invalid memory budget:")?;
+            indexer.index_with_memory_budget(docs_iter, memory_budget_mb * 1024 * 1024)
+        }
+        None => indexer.index(docs_iter),
+    }.with_context(|| "Error during indexing:")?;
+
+    // Documents can also be removed or replaced after indexing
+    indexer.delete(&doc_ids[0])
+        .with_context(|| "Error deleting a document:")?;
+
+    let mut updated_fields = HashMap::new();
+    updated_fields.insert("field1_index_name".to_string(), random_word::gen(Lang::En).to_string());
+    indexer.update(&doc_ids[1], updated_fields)
+        .with_context(|| "Error updating a document:")?;
+
+    // Which leaves a small segment plus some deletions behind, that can be compacted (`--force-merge` merges every
+    // segment into a single one)
+    if std::env::args().any(|arg| arg == "--force-merge") {
+        indexer.force_merge()
+    } else {
+        indexer.merge(&TieredMergePolicy::new(2, 10, 1_000, 5_000_000)).map(|_| ())
+    }.with_context(|| "Error merging segments:")?;
 
     let mut field_weights = HashMap::new();
     let mut field_k1s = HashMap::new();
@@ -85,8 +168,12 @@ fn main() -> Result<()> {
     field_weights.insert("field2_index_name".to_string(), 0.5);
     field_k1s.insert("field2_index_name".to_string(), 1.2);
     field_bs.insert("field2_index_name".to_string(), 0.75);
+    let bm25f = retrieval::Bm25f::new(field_k1s.clone(), field_bs.clone(), field_weights.clone());
 
-    let retriever = retrieval::Retriever::new(field_keys.into_values().collect())
+    // The index could be opened from any other process, given its path
+    let index_directory = IndexDirectory::open("./index")
+        .with_context(|| "Error opening the index directory:")?;
+    let retriever = retrieval::Retriever::new(field_keys.clone().into_values().collect(), &index_directory)
         .with_context(|| "Error during retriever initialization:")?;
 
     let mut query_tokens : Vec<String> = Vec::new();
@@ -96,9 +183,7 @@ fn main() -> Result<()> {
 
     let results = retriever.retrieval_multiple_tokens(
             &query_tokens,
-            &field_k1s,
-            &field_bs,
-            &field_weights
+            &bm25f
         ).with_context(|| "Error during retrieval:")?;
 
     println!(
@@ -106,5 +191,178 @@ fn main() -> Result<()> {
         results[..5].to_vec()
     );
 
+    // Same results, skipping the documents that can't make it into the top five
+    let top_k_results = retriever.retrieval_top_k(
+            &query_tokens,
+            5,
+            &bm25f
+        ).with_context(|| "Error during top-k retrieval:")?;
+
+    println!(
+        "Top five results with dynamic pruning: {:?}",
+        top_k_results
+    );
+
+    // The best result with its stored fields, read from the index instead of the documents directory
+    if let Some((best_docid, _)) = top_k_results.first() {
+        let best_document = retriever.get_document(best_docid)
+            .with_context(|| format!("Error reading the stored fields of {}:", best_docid))?;
+
+        for (index_key, field_text) in &best_document {
+            println!(
+                "Stored {} of {}: {:?}...",
+                index_key,
+                best_docid,
+                field_text.chars().take(80).collect::<String>()
+            );
+        }
+    }
+
+    // The same query, scored with every other model
+    let similarities: Vec<(&str, Box<dyn retrieval::Similarity>)> = vec![
+        ("standard BM25F", Box::new(retrieval::Bm25f::standard(1.2, field_bs.clone(), field_weights.clone()))),
+        ("BM25", Box::new(retrieval::Bm25::new(field_k1s, field_bs, field_weights.clone()))),
+        ("TF-IDF", Box::new(retrieval::TfIdf::new(field_weights.clone()))),
+        ("Dirichlet query likelihood", Box::new(retrieval::LmDirichlet::new(2000.0, field_weights.clone()))),
+        ("Jelinek-Mercer query likelihood", Box::new(retrieval::LmJelinekMercer::new(0.7, field_weights.clone()))),
+        ("DFR InL2", Box::new(retrieval::DfrInL2::new(1.0, field_weights))),
+    ];
+    for (similarity_name, similarity) in &similarities {
+        let similarity_results = retriever.retrieval_top_k(&query_tokens, 5, similarity.as_ref())
+            .with_context(|| format!("Error during {} retrieval:", similarity_name))?;
+
+        println!(
+            "Top five results with {}: {:?}",
+            similarity_name,
+            similarity_results
+        );
+    }
+
+    // Documents where the query tokens appear close together get a higher score
+    let proximity_results = retriever.retrieval_multiple_tokens_with_proximity(
+            &query_tokens,
+            &bm25f
+        ).with_context(|| "Error during proximity retrieval:")?;
+
+    println!(
+        "Top five results with term proximity: {:?}",
+        proximity_results.iter().take(5).collect::<Vec<_>>()
+    );
+
+    // Fuzzy query, also matching the tokens one typo away from the query ones (with half their score)
+    // Analyzed like the first field, which is the one storing positions
+    let field1_analyzer = &retriever.analyzers()["field1_index_name"];
+    let fuzzy_query_tokens: Vec<String> = query_tokens.iter().take(3).flat_map(|token| field1_analyzer.analyze_texts(token)).collect();
+    let fuzzy_results = retriever.retrieval_fuzzy(
+            &fuzzy_query_tokens,
+            1,
+            0.5,
+            &bm25f
+        ).with_context(|| "Error during fuzzy retrieval:")?;
+
+    println!(
+        "Top five results for the fuzzy query {:?}: {:?}",
+        fuzzy_query_tokens,
+        fuzzy_results.iter().take(5).collect::<Vec<_>>()
+    );
+
+    // Phrase query, allowing a word in between the phrase tokens
+    let phrase_text = query_tokens.iter().take(2).cloned().collect::<Vec<_>>().join(" ");
+    let phrase_tokens = field1_analyzer.analyze(&phrase_text);
+    let phrase_results = retriever.retrieval_phrase(
+            &phrase_tokens,
+            1,
+            &bm25f
+        ).with_context(|| "Error during phrase retrieval:")?;
+
+    println!(
+        "Top five results for the phrase {:?}: {:?}",
+        phrase_tokens,
+        phrase_results.iter().take(5).collect::<Vec<_>>()
+    );
+
+    // Prefix query, matching at most 50 index tokens
+    let wildcard_prefix: String = phrase_tokens.first().map(|token| token.text.chars().take(3).collect()).unwrap_or_default();
+    let wildcard_pattern = format!("{}*", wildcard_prefix);
+    let wildcard_results = retriever.retrieval_wildcard(
+            &wildcard_pattern,
+            50,
+            &bm25f
+        ).with_context(|| "Error during wildcard retrieval:")?;
+
+    println!(
+        "Top five results for the wildcard query {:?}: {:?}",
+        wildcard_pattern,
+        wildcard_results.iter().take(5).collect::<Vec<_>>()
+    );
+
+    // Regex query, with at most a 1MB automaton matching at most 50 index tokens
+    let regex_query = retrieval::RegexQuery::new(&format!("{}.*(s|ed|ing)", wildcard_prefix), 1024 * 1024, 50)
+        .with_context(|| "Error building the regex query:")?;
+    let regex_results = retriever.retrieval_regex(
+            &regex_query,
+            &bm25f
+        ).with_context(|| "Error during regex retrieval:")?;
+
+    println!(
+        "Top five results for the regex query {:?}: {:?}",
+        regex_query.pattern(),
+        regex_results.iter().take(5).collect::<Vec<_>>()
+    );
+
+    // Both of them in a query tree, where the wildcard is required and the regex only adds to the score
+    let expansions_query = retrieval::Query::Boolean {
+        clauses: vec![
+            retrieval::Clause::new(retrieval::Occur::Must, retrieval::Query::Wildcard { pattern: wildcard_pattern, max_expansions: 50 }),
+            retrieval::Clause::new(retrieval::Occur::Should, retrieval::Query::Regex(Box::new(regex_query))),
+        ],
+        minimum_should_match: 0,
+    };
+    let expansions_results = retriever.retrieval_query(
+            &expansions_query,
+            &bm25f
+        ).with_context(|| "Error during wildcard and regex retrieval:")?;
+
+    println!(
+        "Top five results for the wildcard and regex query: {:?}",
+        expansions_results.iter().take(5).collect::<Vec<_>>()
+    );
+    if let Some((docid, _)) = expansions_results.first() {
+        let explanation = retriever.explain(&expansions_query, docid, &bm25f)
+            .with_context(|| format!("Error explaining the score of {}:", docid))?;
+        println!("Score of {} for the wildcard and regex query:\n{}", docid, explanation);
+    }
+
+    // Boolean query parsed from a query string, analyzed like the indexed fields
+    let boolean_token = |i: usize| query_tokens.get(i).cloned().unwrap_or_default();
+    let query_string = format!(
+        "+{} -{} field1:\"{} {}\"~2^2 {}~1",
+        boolean_token(0),
+        boolean_token(1),
+        boolean_token(0),
+        boolean_token(2),
+        boolean_token(3)
+    );
+    let boolean_query = retrieval::QueryParser::new(field_keys, retriever.analyzers()).parse(&query_string)
+        .with_context(|| format!("Error parsing the query {}:", query_string))?;
+    let boolean_results = retriever.retrieval_query(
+            &boolean_query,
+            &bm25f
+        ).with_context(|| "Error during boolean retrieval:")?;
+
+    println!(
+        "Top five results for the boolean query {:?}: {:?}",
+        query_string,
+        boolean_results.iter().take(5).collect::<Vec<_>>()
+    );
+
+    // Breakdown of the best boolean result's score (or why the best multi-token result doesn't match)
+    let explained_docid = &boolean_results.first().unwrap_or(&results[0]).0;
+    let explanation = retriever.explain(&boolean_query, explained_docid, &bm25f)
+        .with_context(|| format!("Error explaining the score of {}:", explained_docid))?;
+
+    println!("Score of {} for the boolean query:\n{}", explained_docid, explanation);
+    println!("As JSON:\n{}", explanation.to_json()?);
+
     Ok(())
 }
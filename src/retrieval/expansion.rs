@@ -0,0 +1,161 @@
+// Expansion of a query token to the index tokens matched by an automaton over the segments' postings FSTs, used by the
+// fuzzy queries (Levenshtein automata), the wildcard ones and the regex ones
+
+use std::collections::BTreeSet;
+use anyhow::Result;
+use fst::Automaton;
+use fst::automaton::Str;
+use crate::retrieval::segment::Segment;
+use crate::retrieval::wildcard::WildcardAutomaton;
+
+/// The first `max_tokens` tokens (in lexicographical order) of any field of any segment matched by the automaton
+pub fn get_matching_tokens<A: Automaton>(segments: &[Segment], automaton: &A, max_tokens: usize) -> Result<BTreeSet<String>> {
+    let mut matching_tokens = BTreeSet::new();
+
+    for segment in segments {
+        matching_tokens.extend(segment.get_matching_tokens(automaton, max_tokens)?);
+    }
+
+    Ok(matching_tokens.into_iter().take(max_tokens).collect())
+}
+
+/// At most `max_tokens` tokens of any field of any segment matched by a wildcard pattern. Prefix patterns (`foo*`) are
+/// resolved with a prefix automaton, and patterns starting with a wildcard but ending with a literal (`*bar`, `?ar`)
+/// by running the reversed pattern over the FSTs of the reversed tokens, which are then searched from their (literal)
+/// start instead of every token being visited
+pub fn get_wildcard_matching_tokens(segments: &[Segment], pattern: &str, max_tokens: usize) -> Result<BTreeSet<String>> {
+    if !WildcardAutomaton::is_wildcard(pattern) {
+        return Ok(BTreeSet::from([pattern.to_string()]));
+    }
+
+    let prefix = pattern.strip_suffix('*').filter(|prefix| !WildcardAutomaton::is_wildcard(prefix));
+    if let Some(prefix) = prefix {
+        return get_matching_tokens(segments, &Str::new(prefix).starts_with(), max_tokens);
+    }
+
+    let automaton = WildcardAutomaton::new(pattern);
+    if !pattern.starts_with(['*', '?']) || pattern.ends_with(['*', '?']) {
+        return get_matching_tokens(segments, &automaton, max_tokens);
+    }
+
+    let reversed_pattern: String = pattern.chars().rev().collect();
+    let reversed_automaton = WildcardAutomaton::new(&reversed_pattern);
+
+    let mut matching_tokens = BTreeSet::new();
+    for segment in segments {
+        // Segments written before the reversed tokens were indexed are scanned in full
+        if segment.has_reversed_tokens() {
+            matching_tokens.extend(segment.get_matching_reversed_tokens(&reversed_automaton, max_tokens)?);
+        } else {
+            matching_tokens.extend(segment.get_matching_tokens(&automaton, max_tokens)?);
+        }
+    }
+
+    Ok(matching_tokens.into_iter().take(max_tokens).collect())
+}
+
+/// Levenshtein distance between two tokens, counting characters like the fst Levenshtein automaton does
+pub fn get_edit_distance(token: &str, other_token: &str) -> usize {
+    let other_chars: Vec<char> = other_token.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=other_chars.len()).collect();
+
+    for (char_i, token_char) in token.chars().enumerate() {
+        let mut row = vec![char_i + 1];
+        for (other_char_i, other_char) in other_chars.iter().enumerate() {
+            let substitution_cost = if token_char == *other_char { 0 } else { 1 };
+
+            row.push((previous_row[other_char_i] + substitution_cost)
+                .min(previous_row[other_char_i + 1] + 1)
+                .min(row[other_char_i] + 1));
+        }
+
+        previous_row = row;
+    }
+
+    previous_row[other_chars.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_index::{bm25f, body_docs, TestIndex};
+    use std::collections::HashMap;
+
+    #[test]
+    fn edit_distances_count_characters() {
+        assert_eq!(get_edit_distance("rust", "rust"), 0);
+        assert_eq!(get_edit_distance("rust", "rusty"), 1);
+        assert_eq!(get_edit_distance("rust", "bust"), 1);
+        assert_eq!(get_edit_distance("rust", "rst"), 1);
+        assert_eq!(get_edit_distance("rust", "ruby"), 2);
+        assert_eq!(get_edit_distance("rust", "trusty"), 2);
+        assert_eq!(get_edit_distance("kitten", "sitting"), 3);
+        assert_eq!(get_edit_distance("", "abc"), 3);
+        // A single substitution, even if the characters take a different number of bytes
+        assert_eq!(get_edit_distance("bär", "bar"), 1);
+    }
+
+    #[test]
+    fn fuzzy_queries_score_the_best_expansion() {
+        let index = TestIndex::new("fuzzy_query");
+        let mut indexer = index.indexer(&["body"]);
+        indexer.index(body_docs(&[
+            ("d1", "rust"),
+            ("d2", "rusty"),
+            ("d3", "bust"),
+            ("d4", "ruby"),
+            ("d5", "trusty"),
+            ("d6", "rust rusty"),
+            ("d7", "java"),
+        ])).unwrap();
+        // Only matches through an expansion, which is still in the postings FST
+        indexer.delete("d3").unwrap();
+
+        let retriever = index.retriever(&["body"]);
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+        let token_score = |token: &str, docid: &str| {
+            retriever.retrieval_multiple_tokens(&vec![token.to_string()], &bm25f).unwrap()
+                .into_iter()
+                .find(|(result_docid, _)| result_docid == docid)
+                .unwrap().1
+        };
+        let fuzzy_scores = |max_distance: u32| -> HashMap<String, f64> {
+            retriever.retrieval_fuzzy(&["rust".to_string()], max_distance, 0.5, &bm25f).unwrap().into_iter().collect()
+        };
+        let assert_scores = |scores: &HashMap<String, f64>, expected_scores: &[(&str, f64)]| {
+            assert_eq!(scores.len(), expected_scores.len(), "{:?}", scores);
+            for (docid, expected_score) in expected_scores {
+                let score = scores[*docid];
+                assert!((score - expected_score).abs() < 1e-12, "{}: {} != {}", docid, score, expected_score);
+            }
+        };
+
+        let d6_score = token_score("rust", "d6").max(0.5 * token_score("rusty", "d6"));
+        assert_scores(&fuzzy_scores(0), &[("d1", token_score("rust", "d1")), ("d6", token_score("rust", "d6"))]);
+        assert_scores(&fuzzy_scores(1), &[
+            ("d1", token_score("rust", "d1")),
+            ("d2", 0.5 * token_score("rusty", "d2")),
+            ("d6", d6_score),
+        ]);
+        assert_scores(&fuzzy_scores(2), &[
+            ("d1", token_score("rust", "d1")),
+            ("d2", 0.5 * token_score("rusty", "d2")),
+            ("d4", 0.25 * token_score("ruby", "d4")),
+            ("d5", 0.25 * token_score("trusty", "d5")),
+            ("d6", d6_score),
+        ]);
+        assert!(retriever.retrieval_multiple_tokens(&vec!["bust".to_string()], &bm25f).unwrap().is_empty());
+    }
+
+    #[test]
+    fn fuzzy_automata_are_bounded() {
+        let index = TestIndex::new("fuzzy_bounded");
+        index.indexer(&["body"]).index(body_docs(&[("d1", "rust")])).unwrap();
+        let retriever = index.retriever(&["body"]);
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+
+        // The Levenshtein automaton of a long token with a big distance has too many states to be built
+        let long_token = "abcdefghijklmnopqrstuvwxyz".to_string();
+        assert!(retriever.retrieval_fuzzy(&[long_token], 4, 0.5, &bm25f).is_err());
+    }
+}
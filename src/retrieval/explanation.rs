@@ -0,0 +1,208 @@
+// Breakdown of the score of a document for a query, as returned by `Retriever::explain`. Every node holds a value, a
+// description of how it is computed, and the nodes it is computed from, down to the statistics and parameters of the
+// scoring model, e.g.
+//
+//     2.05 = sum of the matching clauses:
+//       2.05 = required clause:
+//         2.05 = term "rust":
+//           2.05 = BM25F, sum of the field contributions:
+//             2.05 = contribution of field title, idf * (w * tf * (k1 + 1)) / (k1 * (...) + w * tf)):
+//               1 = tf
+//               ...
+//
+// Explanations are printed like this with `Display`, and can also be serialized to JSON
+
+use std::fmt;
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    // Whether the document matches the query (or subquery). Non-matching documents aren't scored
+    pub matched: bool,
+    pub value: f64,
+    pub description: String,
+    pub details: Vec<Explanation>,
+}
+
+impl Explanation {
+    pub fn new(value: f64, description: impl Into<String>, details: Vec<Explanation>) -> Self {
+        Self { matched: true, value, description: description.into(), details }
+    }
+
+    /// A statistic or parameter the scores are computed from
+    pub fn leaf(value: f64, description: impl Into<String>) -> Self {
+        Self::new(value, description, Vec::new())
+    }
+
+    pub fn no_match(description: impl Into<String>, details: Vec<Explanation>) -> Self {
+        Self { matched: false, value: 0.0, description: description.into(), details }
+    }
+
+    /// Wraps an explanation in a node with the same value, describing where it comes from
+    pub fn wrap(self, description: impl Into<String>) -> Self {
+        match self.matched {
+            true => Self::new(self.value, description, vec![self]),
+            false => Self::no_match(description, vec![self]),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).with_context(|| "Failed to serialize the explanation")
+    }
+
+    fn write_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let suffix = if self.details.is_empty() { "" } else { ":" };
+        let no_match = if self.matched { "" } else { " (no match)" };
+        writeln!(f, "{}{} = {}{}{}", "  ".repeat(depth), self.value, self.description, no_match, suffix)?;
+
+        for detail in &self.details {
+            detail.write_indented(f, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retrieval::{Clause, Occur, Query};
+    use crate::test_index::{bm25f, doc, TestIndex};
+
+    const INDEX_KEYS: [&str; 2] = ["title", "body"];
+
+    fn assert_close(value: f64, expected_value: f64) {
+        assert!((value - expected_value).abs() < 1e-12, "{} != {}", value, expected_value);
+    }
+
+    /// The detail whose description starts with the given one
+    fn detail<'a>(explanation: &'a Explanation, description: &str) -> &'a Explanation {
+        explanation.details.iter()
+            .find(|detail| detail.description.starts_with(description))
+            .unwrap_or_else(|| panic!("No detail {:?} in {}", description, explanation))
+    }
+
+    fn sum_of_details(explanation: &Explanation) -> f64 {
+        explanation.details.iter().map(|detail| detail.value).sum()
+    }
+
+    fn rust_or_search() -> Query {
+        Query::Boolean {
+            clauses: vec![
+                Clause::new(Occur::Should, Query::Term("rust".to_string())),
+                Clause::new(Occur::Should, Query::Term("search".to_string())),
+            ],
+            minimum_should_match: 0,
+        }
+    }
+
+    #[test]
+    fn explanations_are_displayed_as_indented_trees() {
+        let explanation = Explanation::new(3.0, "sum", vec![
+            Explanation::new(2.0, "product", vec![Explanation::leaf(1.0, "a"), Explanation::leaf(2.0, "b")]),
+            Explanation::leaf(1.0, "c"),
+            Explanation::no_match("d", Vec::new()),
+        ]);
+
+        assert_eq!(explanation.to_string(), "3 = sum:\n  2 = product:\n    1 = a\n    2 = b\n  1 = c\n  0 = d (no match)\n");
+        assert_eq!(Explanation::leaf(1.5, "a").wrap("wrapped a").to_string(), "1.5 = wrapped a:\n  1.5 = a\n");
+        assert!(!Explanation::no_match("d", Vec::new()).wrap("wrapped d").matched);
+    }
+
+    #[test]
+    fn explanations_are_serialized_to_json() {
+        let explanation = Explanation::new(2.0, "product", vec![Explanation::leaf(2.0, "b"), Explanation::no_match("d", Vec::new())]);
+
+        let json: serde_json::Value = serde_json::from_str(&explanation.to_json().unwrap()).unwrap();
+
+        assert_eq!(json, serde_json::json!({
+            "matched": true,
+            "value": 2.0,
+            "description": "product",
+            "details": [
+                {"matched": true, "value": 2.0, "description": "b", "details": []},
+                {"matched": false, "value": 0.0, "description": "d", "details": []},
+            ],
+        }));
+    }
+
+    #[test]
+    fn scores_are_explained_down_to_the_field_statistics() {
+        let index = TestIndex::new("explanation_fields");
+        let docs = [
+            ("d1", "rust", "rust search engine"),
+            ("d2", "search", "search search rust index"),
+            ("d3", "python search", "python"),
+            ("d4", "java", "java index"),
+        ];
+        let mut indexer = index.indexer(&INDEX_KEYS);
+        indexer.index(docs.iter().map(|(docid, title, body)| doc(docid, &[("title", title), ("body", body)]))).unwrap();
+        indexer.delete("d3").unwrap();
+
+        let retriever = index.retriever(&INDEX_KEYS);
+        let bm25f = bm25f(&[("title", 0.75, 2.0), ("body", 0.5, 1.0)]);
+        let explanation = retriever.explain(&rust_or_search(), "d1", &bm25f).unwrap();
+        let results = retriever.retrieval_query(&rust_or_search(), &bm25f).unwrap();
+        let top_k_results = retriever.retrieval_top_k(&["rust".to_string(), "search".to_string()], 1, &bm25f).unwrap();
+        let (top_docid, top_score) = &top_k_results[0];
+        let top_explanation = retriever.explain(&rust_or_search(), top_docid, &bm25f).unwrap();
+
+        // The scores are the ones of the retrieval, and the sum of the scores of the clauses
+        assert_close(top_explanation.value, *top_score);
+        assert!(explanation.matched);
+        assert_close(explanation.value, results.iter().find(|(docid, _)| docid == "d1").unwrap().1);
+        assert_eq!(explanation.details.len(), 2);
+        assert_close(explanation.value, sum_of_details(&explanation));
+
+        // "rust", in 2 of the 3 live documents, with the average lengths of the live documents: 1 (title) and 3 (body)
+        let rust = detail(detail(detail(&explanation, "optional clause"), "term \"rust\""), "BM25F (standard)");
+        let idf = detail(rust, "idf");
+        let saturation = detail(rust, "normalized tf");
+        assert_close(idf.value, (1.0f64 + 1.5 / 2.5).ln());
+        assert_eq!(detail(idf, "number of documents (N)").value, 3.0);
+        assert_eq!(detail(idf, "number of documents containing the token").value, 2.0);
+        assert_close(rust.value, idf.value * saturation.value);
+
+        let title = detail(saturation, "contribution of field title");
+        let body = detail(saturation, "contribution of field body");
+        let field_stats = |field: &Explanation| {
+            ["tf", "field weight", "field length", "average field length", "b"].map(|description| detail(field, description).value)
+        };
+        assert_eq!(field_stats(title), [1.0, 2.0, 1.0, 1.0, 0.75]);
+        assert_eq!(field_stats(body), [1.0, 1.0, 3.0, 3.0, 0.5]);
+        assert_close(title.value, 2.0);
+        assert_close(body.value, 1.0);
+
+        let normalized_tf = title.value + body.value;
+        assert_eq!(detail(saturation, "k1").value, 1.2);
+        assert_close(saturation.value, normalized_tf / (1.2 + normalized_tf));
+    }
+
+    #[test]
+    fn only_live_documents_are_explained() {
+        let index = TestIndex::new("explanation_live");
+        let mut indexer = index.indexer(&["body"]);
+        indexer.index([doc("d1", &[("body", "rust")]), doc("d2", &[("body", "rust")]), doc("d3", &[("body", "java")])].into_iter()).unwrap();
+        indexer.delete("d2").unwrap();
+
+        let retriever = index.retriever(&["body"]);
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+        let deleted_error = retriever.explain(&rust_or_search(), "d2", &bm25f).unwrap_err();
+        let missing_error = retriever.explain(&rust_or_search(), "d9", &bm25f).unwrap_err();
+        let no_match = retriever.explain(&rust_or_search(), "d3", &bm25f).unwrap();
+
+        assert_eq!(deleted_error.to_string(), "Couldn't find a live document with docid d2");
+        assert_eq!(missing_error.to_string(), "Couldn't find a live document with docid d9");
+        // Documents not matching the query are explained, with a score of 0
+        assert!(!no_match.matched);
+        assert_eq!(no_match.value, 0.0);
+        assert_eq!(no_match.description, "0 optional clauses matched, out of the 1 needed");
+    }
+}
@@ -1,71 +1,211 @@
-// BM25F-based retriever implementation (Warning: I didn't verify its correctness)
-
-use std::collections::{BTreeMap, HashMap, HashSet};
-use std::fs::File;
-use std::{io, thread};
+// Retriever implementation, scoring documents with BM25F or any other `Similarity` (Warning: I didn't verify its
+// correctness)
+
+pub(crate) mod segment;
+mod expansion;
+mod explanation;
+mod phrase;
+mod proximity;
+mod query;
+mod query_parser;
+mod regex;
+mod similarity;
+mod top_k;
+mod wildcard;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::thread;
 use anyhow::{anyhow, Result};
+use fst::automaton::Levenshtein;
 use crossbeam_channel::bounded;
-use fst::Map;
 use indicatif::{ProgressBar};
-use memmap::Mmap;
-use crate::{aux, indexing};
+use crate::index_directory::{IndexDirectory, Manifest};
+use crate::indexing::docids::DocId;
+use crate::retrieval::segment::Segment;
+use crate::retrieval::similarity::{CollectionStats, FieldStats, TokenStats};
+use crate::retrieval::top_k::{TokenCursors, TopDocs};
+use crate::tokenizer::{Analyzer, Token};
+
+pub use self::explanation::Explanation;
+pub use self::query::{Clause, Occur, Query};
+pub use self::query_parser::QueryParser;
+pub use self::regex::RegexQuery;
+pub use self::similarity::{Bm25, Bm25f, DfrInL2, LmDirichlet, LmJelinekMercer, Similarity, TfIdf};
+
+// Documents are identified by the position of their segment, and their internal doc id within it
+type SegmentDocId = (usize, DocId);
+
+// A document matching a token (or a phrase, or a union of tokens), with the frequency of the match in every field
+// containing it
+type DocFieldTfs<'a> = (SegmentDocId, Vec<(&'a str, f64)>);
 
 #[derive(Debug)]
 pub struct Retriever {
-    index_keys: Vec<String>,
-
-    lengths_maps: HashMap<String, Map<Mmap>>,
-    avg_lengths_map: Map<Mmap>,
-    postings_maps: HashMap<String, Map<Mmap>>,
+    segments: Vec<Segment>,
 
-    postings_data_files: HashMap<String, Mmap>,
+    // Collection statistics, combined over all the live segments
+    collection_stats: CollectionStats,
 
-    index_stats: indexing::stats::IndexStats
+    // Analyzers the fields were indexed with, for analyzing the queries in the same way
+    analyzers: HashMap<String, Analyzer>,
 }
 
 
 impl Retriever {
-    pub fn new(index_keys: Vec<String>) -> Result<Self> {
-        let mut lengths_maps = HashMap::new();
-        let mut postings_maps = HashMap::new();
-        let mut postings_data_files = HashMap::new();
+    /// Opens every live segment listed in the index manifest
+    pub fn new(index_keys: Vec<String>, index_directory: &IndexDirectory) -> Result<Self> {
+        let manifest = Manifest::load(index_directory)?;
+
+        let mut segments = Vec::new();
+        for segment_info in &manifest.segments {
+            segments.push(Segment::open(&index_keys, &index_directory.segment(&segment_info.name))?);
+        }
+
+        let collection_stats = Self::combine_collection_stats(&index_keys, &segments);
+        let analyzers = index_keys.iter()
+            .map(|index_key| (index_key.clone(), manifest.analyzer(index_key)))
+            .collect();
 
-        let avg_lengths_map = unsafe {
-            Map::new(Mmap::map(&File::open("avg_lengths_index.fst")?)?)?
+        Ok(Self { segments, collection_stats, analyzers })
+    }
+
+    /// Analyzer of every field, as recorded in the index. Query tokens must be analyzed with them (e.g. by a
+    /// `QueryParser`) to match the indexed ones
+    pub fn analyzers(&self) -> &HashMap<String, Analyzer> {
+        &self.analyzers
+    }
+
+    /// Number of documents, and total length of each field, over the live documents of all segments
+    fn combine_collection_stats(index_keys: &[String], segments: &[Segment]) -> CollectionStats {
+        let mut collection_stats = CollectionStats {
+            n_docs: segments.iter().map(|segment| segment.n_live_docs()).sum(),
+            field_stats: HashMap::new(),
         };
 
-        for index_key in &index_keys {
-            let mmap = unsafe {
-                Mmap::map(&File::open(format!("lengths_index_{}.fst", index_key))?)?
-            };
-            lengths_maps.insert(index_key.clone(), Map::new(mmap)?);
-
-            let mmap = unsafe {
-                Mmap::map(&File::open(format!("postings_index_{}.fst", index_key))?)?
-            };
-            postings_maps.insert(index_key.clone(), Map::new(mmap)?);
-
-            let mmap = unsafe {
-                Mmap::map(&File::open(format!("postings_data_{}.bin", index_key))?)?
-            };
-            postings_data_files.insert(index_key.clone(), mmap);
-        }
+        for index_key in index_keys {
+            let mut field_stats = FieldStats::default();
 
-        let index_stats = serde_json::from_reader(io::BufReader::new(File::open("index_stats.json")?))?;
+            for segment in segments {
+                let (segment_total_length, segment_n_docs) = segment.get_field_length_stats(index_key);
+                field_stats.total_length += segment_total_length;
+                field_stats.n_docs += segment_n_docs;
+            }
 
-        Ok(Self { index_keys, lengths_maps, avg_lengths_map, postings_maps, postings_data_files, index_stats })
+            if field_stats.n_docs > 0 {
+                collection_stats.field_stats.insert(index_key.clone(), field_stats);
+            }
+        }
+
+        collection_stats
     }
 
-    /// Run a BM25F query on a multi-token query. Internally parallelized
+    /// Run a multi-token query, summing the scores of its tokens. Internally parallelized
     pub fn retrieval_multiple_tokens(&self,
                                      query_tokens: &Vec<String>,
-                                     field_k1_params: &HashMap<String, f64>,
-                                     field_b_params: &HashMap<String, f64>,
-                                     field_weights : &HashMap<String, f64>) -> Result<Vec<(String, f64)>> {
+                                     similarity: &dyn Similarity) -> Result<Vec<(String, f64)>> {
+        let doc_scores = self.get_multiple_tokens_scores(query_tokens, similarity)?;
+
+        // Only now are the external docids needed
+        Ok(self.get_ordered_results(doc_scores))
+    }
+
+    /// Run a multi-token query, only returning its k best results. Scores are the same as with
+    /// `retrieval_multiple_tokens`, but Block-Max WAND skips the documents (and whole postings blocks) which can't make
+    /// it into the top k, so that the skipped blocks are never decoded. Token statistics are read from the ones stored
+    /// at index time, so that they don't need decoding either, but in the segments with deletions
+    pub fn retrieval_top_k(&self,
+                           query_tokens: &[String],
+                           k: usize,
+                           similarity: &dyn Similarity) -> Result<Vec<(String, f64)>> {
+        // Token statistics are global, so they are gathered beforehand over all segments. Tokens without any live
+        // document are left out
+        let mut token_stats = HashMap::new();
+        for token in query_tokens {
+            if !token_stats.contains_key(token) {
+                token_stats.insert(token, self.get_token_stats(token, None)?);
+            }
+        }
+
+        // The top k is shared between segments, so that later segments start with the threshold reached by earlier ones
+        let mut top_docs = TopDocs::new(k);
+        for (segment_i, segment) in self.segments.iter().enumerate() {
+            let mut tokens = Vec::new();
+            for token in query_tokens {
+                let token_stats = &token_stats[token];
+                if token_stats.doc_frequency > 0 {
+                    let field_cursors = segment.get_postings_cursors(token)?;
+                    tokens.push(TokenCursors::new(field_cursors, token_stats, similarity, &self.collection_stats)?);
+                }
+            }
+
+            top_k::search_segment(segment_i, segment, tokens, &mut top_docs, similarity, &self.collection_stats)?;
+        }
+
+        Ok(top_docs.into_sorted_vec()
+            .into_iter()
+            .map(|(segment_doc_id, score)| (self.get_docid(segment_doc_id), score))
+            .collect())
+    }
+
+    /// Run a BM25F query on a multi-token query, adding a term proximity component (BM25TP) computed over the fields
+    /// storing positions, so that documents where the query tokens appear close together rank higher. BM25TP extends
+    /// BM25, so it takes the BM25F parameters, and weights the token pairs with the same idf as BM25F
+    pub fn retrieval_multiple_tokens_with_proximity(&self,
+                                                    query_tokens: &Vec<String>,
+                                                    similarity: &Bm25f) -> Result<Vec<(String, f64)>> {
+        let mut doc_scores = self.get_multiple_tokens_scores(query_tokens, similarity)?;
+
+        let mut distinct_tokens = query_tokens.clone();
+        distinct_tokens.sort();
+        distinct_tokens.dedup();
+
+        // Tokens without any live document can't be close to the others
+        let mut idfs = Vec::new();
+        let mut matched_tokens = Vec::new();
+        for token in distinct_tokens {
+            let token_stats = self.get_token_stats(&token, None)?;
+            if token_stats.doc_frequency > 0 {
+                idfs.push(similarity.idf(&token_stats, &self.collection_stats));
+                matched_tokens.push(token);
+            }
+        }
+
+        for (segment_i, segment) in self.segments.iter().enumerate() {
+            for index_key in &segment.index_stats.positional_fields {
+                let field_weight = similarity.weight(index_key);
+                let k1 = similarity.k1(index_key);
+                let b = similarity.b(index_key);
+
+                for (doc_id, pair_accumulators) in proximity::get_field_proximities(segment, index_key, &matched_tokens)? {
+                    if segment.is_deleted(doc_id) {
+                        continue;
+                    }
+
+                    let doc_len = segment.get_length(doc_id, index_key)? as f64;
+                    let k = k1 * ((1.0 - b) + b * (doc_len / self.collection_stats.field(index_key).avg_length()));
+
+                    // Every pair of tokens is weighted by the least informative of both
+                    let mut proximity_score = 0.0;
+                    for ((token_i, other_token_i), accumulator) in pair_accumulators {
+                        let idf = idfs[token_i].min(idfs[other_token_i]);
+                        proximity_score += idf * (accumulator * (k1 + 1.0)) / (k + accumulator);
+                    }
+
+                    *doc_scores.entry((segment_i, doc_id)).or_insert(0.0) += field_weight * proximity_score;
+                }
+            }
+        }
+
+        Ok(self.get_ordered_results(doc_scores))
+    }
+
+    fn get_multiple_tokens_scores(&self,
+                                  query_tokens: &Vec<String>,
+                                  similarity: &dyn Similarity) -> Result<HashMap<SegmentDocId, f64>> {
         let (jobs_send_channel, jobs_recv_channel) =
             bounded::<Option<String>>(query_tokens.len());
         let (results_send_channel, results_recv_channel) =
-            bounded::<Option<HashMap<String, f64>>>(query_tokens.len());
+            bounded::<Option<HashMap<SegmentDocId, f64>>>(query_tokens.len());
 
         for token in query_tokens {
             jobs_send_channel.send(Some(token.clone())).unwrap();
@@ -87,9 +227,7 @@ impl Retriever {
                         if let Some(token) = jobs_recv_channel_clone.recv().unwrap() {
                             let results = self.retrieval_single_token(
                                 &token,
-                                field_k1_params,
-                                field_b_params,
-                                field_weights,
+                                similarity,
                             ).unwrap();
 
                             results_send_channel_clone.send(Some(results)).unwrap();
@@ -105,7 +243,7 @@ impl Retriever {
 
             let progress_bar = ProgressBar::new(query_tokens.len() as u64);
             progress_bar.println("Retrieving results for each token...");
-            let mut results: Vec<HashMap<String, f64>> = Vec::new();
+            let mut results: Vec<HashMap<SegmentDocId, f64>> = Vec::new();
             for _ in query_tokens {
                 results.push(results_recv_channel.recv()
                     .unwrap()
@@ -127,151 +265,626 @@ impl Retriever {
 
             // TODO merge ordered
             println!("Merging results...");
-            let mut merged_results: HashMap<String, f64> = HashMap::new();
+            let mut merged_results: HashMap<SegmentDocId, f64> = HashMap::new();
             for map in results {
                 for (key, value) in map {
-                    *merged_results.entry(key.clone()).or_insert(0.0) += value;
+                    *merged_results.entry(key).or_insert(0.0) += value;
                 }
             }
 
-            let mut ordered_results: Vec<(String, f64)> = merged_results.into_iter().collect();
-            ordered_results.sort_by(|a, b| b.1.total_cmp(&a.1));
-
-            Ok(ordered_results)
+            Ok(merged_results)
         })
     }
 
-    /// Run a BM25F query on a single-token query
+    /// Run a single-token query. Results are keyed by the documents' internal ids, which can be
+    /// resolved with `get_docid`
     pub fn retrieval_single_token(&self,
                                   query_token: &str,
-                                  field_k1_params: &HashMap<String, f64>,
-                                  field_b_params: &HashMap<String, f64>,
-                                  field_weights : &HashMap<String, f64>) -> Result<HashMap<String, f64>> {
-        let matching_docids_postings = self.get_matching_docids_postings(query_token)?;
-        let lengths = self.get_lengths(&matching_docids_postings)?;
-        let mut weighted_avg_lengths = HashMap::new();
-        for (index_key ,avg_length) in self.get_avg_lengths(&matching_docids_postings) {
-            weighted_avg_lengths.insert(index_key.clone(), field_weights.get(&index_key).unwrap() * (avg_length as f64));
+                                  similarity: &dyn Similarity) -> Result<HashMap<SegmentDocId, f64>> {
+        self.get_token_scores(query_token, None, similarity)
+    }
+
+    /// Like `retrieval_single_token`, but only searching a field if one is given
+    fn get_token_scores(&self,
+                        query_token: &str,
+                        index_key: Option<&str>,
+                        similarity: &dyn Similarity) -> Result<HashMap<SegmentDocId, f64>> {
+        let doc_field_tfs = self.get_token_field_tfs(query_token, index_key)?;
+        let token_stats = self.get_token_stats(query_token, index_key)?;
+
+        self.get_match_scores(doc_field_tfs, &token_stats, similarity)
+    }
+
+    /// Frequency of a token in every field of the live documents containing it, only searching a field if one is given
+    fn get_token_field_tfs(&self, token: &str, index_key: Option<&str>) -> Result<Vec<DocFieldTfs<'_>>> {
+        let mut doc_field_tfs = Vec::new();
+
+        for (segment_i, segment) in self.segments.iter().enumerate() {
+            let mut field_cursors = segment.get_postings_cursors(token)?;
+            field_cursors.retain(|(cursor_index_key, _)| index_key.is_none_or(|index_key| index_key == *cursor_index_key));
+            let mut field_postings = field_cursors.iter_mut()
+                .map(|(_, cursor)| cursor.next())
+                .collect::<Result<Vec<_>>>()?;
+
+            // Visit the documents containing the token in any field in doc id order, so that postings are decoded
+            // lazily, and the fields of each document are gathered together
+            while let Some(doc_id) = field_postings.iter().flatten().map(|(doc_id, _)| *doc_id).min() {
+                let mut field_tfs = Vec::new();
+                for ((index_key, cursor), posting) in field_cursors.iter_mut().zip(field_postings.iter_mut()) {
+                    if let Some((posting_doc_id, tf)) = *posting {
+                        if posting_doc_id == doc_id {
+                            field_tfs.push((*index_key, tf as f64));
+                            *posting = cursor.next()?;
+                        }
+                    }
+                }
+
+                if !segment.is_deleted(doc_id) {
+                    doc_field_tfs.push(((segment_i, doc_id), field_tfs));
+                }
+            }
         }
 
-        let doc_frequency = self.get_doc_frequency(&matching_docids_postings);
+        Ok(doc_field_tfs)
+    }
 
-        let idf = (self.index_stats.n_docs as f64) / (doc_frequency as f64);
+    /// Scores of the live documents matching a token (or a phrase, or a union of tokens), given the frequency of the
+    /// match in every field containing it and the statistics of the match, which are global so that scores are
+    /// comparable between segments
+    fn get_match_scores(&self,
+                        doc_field_tfs: Vec<DocFieldTfs>,
+                        token_stats: &TokenStats,
+                        similarity: &dyn Similarity) -> Result<HashMap<SegmentDocId, f64>> {
+        let mut doc_scores = HashMap::new();
+        for ((segment_i, doc_id), field_tfs) in doc_field_tfs {
+            let field_matches = similarity::get_field_matches(&self.segments[segment_i], doc_id, &field_tfs)?;
+            doc_scores.insert((segment_i, doc_id), similarity.score(&field_matches, token_stats, &self.collection_stats));
+        }
 
-        let mut doc_scores: HashMap<String, f64> = HashMap::new();
+        Ok(doc_scores)
+    }
 
-        // Calculate scores per field, instead of per doc_id
-        for (index_key, postings) in matching_docids_postings {
-            let field_weight = field_weights.get(&index_key)
-                .unwrap_or_else(|| panic!("Field weight not found for {}, cannot perform retrieval!", &index_key));
+    /// Statistics of a match without stored ones (a phrase, or a union of tokens), counted over the documents matching
+    /// it
+    fn count_token_stats(doc_field_tfs: &[DocFieldTfs]) -> TokenStats {
+        let mut token_stats = TokenStats::default();
+        for (_, field_tfs) in doc_field_tfs {
+            token_stats.add_doc(field_tfs);
+        }
 
-            for (doc_id, tf) in postings {
-                let new_tf = field_weight * (tf as f64);
-                let weighted_doc_len = self.get_bm25f_doc_len(&doc_id, &lengths, field_weights);
-                let k1 = field_k1_params.get(&index_key).unwrap();
-                let b = field_b_params.get(&index_key).unwrap();
+        token_stats
+    }
 
-                let bm25f_field = (new_tf * (k1 + 1.0)) / (k1 * ((1.0 - b) + b * (weighted_doc_len / weighted_avg_lengths.get(&index_key).unwrap()) + new_tf));
+    /// Run a query for a phrase, where the frequency of the phrase in each field is scored like a term frequency.
+    /// With a slop, the phrase tokens must appear in order with at most `slop` other tokens between them, and spread
+    /// out matches count less than exact ones. Gaps between the positions of the phrase tokens (e.g. from removed
+    /// stopwords) must also be in the matches. Only the fields storing positions are searched
+    pub fn retrieval_phrase(&self,
+                            phrase_tokens: &[Token],
+                            slop: u32,
+                            similarity: &dyn Similarity) -> Result<Vec<(String, f64)>> {
+        let doc_scores = self.get_phrase_scores(phrase_tokens, slop, None, similarity)?;
+
+        Ok(self.get_ordered_results(doc_scores))
+    }
+
+    fn get_phrase_scores(&self,
+                         phrase_tokens: &[Token],
+                         slop: u32,
+                         index_key: Option<&str>,
+                         similarity: &dyn Similarity) -> Result<HashMap<SegmentDocId, f64>> {
+        let phrase_doc_field_tfs = self.get_phrase_field_tfs(phrase_tokens, slop, index_key)?;
+        let phrase_stats = Self::count_token_stats(&phrase_doc_field_tfs);
+
+        self.get_match_scores(phrase_doc_field_tfs, &phrase_stats, similarity)
+    }
+
+    /// Frequency of a phrase in every positional field of the live documents containing it, only searching a field if
+    /// one is given
+    fn get_phrase_field_tfs(&self, phrase_tokens: &[Token], slop: u32, index_key: Option<&str>) -> Result<Vec<DocFieldTfs<'_>>> {
+        let mut phrase_doc_field_tfs = Vec::new();
+
+        if phrase_tokens.is_empty() {
+            return Ok(phrase_doc_field_tfs);
+        }
+
+        for (segment_i, segment) in self.segments.iter().enumerate() {
+            // Doc id -> Phrase frequency of every field containing the phrase
+            let mut doc_field_frequencies: BTreeMap<DocId, Vec<(&str, f64)>> = BTreeMap::new();
+
+            let positional_fields = segment.index_stats.positional_fields.iter()
+                .filter(|positional_field| index_key.is_none_or(|index_key| index_key == *positional_field));
+            for index_key in positional_fields {
+                for (doc_id, phrase_frequency) in phrase::get_field_phrase_frequencies(segment, index_key, phrase_tokens, slop)? {
+                    doc_field_frequencies.entry(doc_id).or_default().push((index_key.as_str(), phrase_frequency));
+                }
+            }
 
-                if doc_scores.contains_key(&doc_id) {
-                    doc_scores.insert(doc_id.clone(), doc_scores.get(&doc_id).unwrap() + bm25f_field);
-                } else {
-                    doc_scores.insert(doc_id.clone(), bm25f_field);
+            for (doc_id, field_frequencies) in doc_field_frequencies {
+                if !segment.is_deleted(doc_id) {
+                    phrase_doc_field_tfs.push(((segment_i, doc_id), field_frequencies));
                 }
             }
         }
 
-        Ok(doc_scores
-            .into_iter()
-            .map(|(doc_id, score)| (doc_id.trim_end_matches('\0').to_string(), idf * score))
-            .collect())
+        Ok(phrase_doc_field_tfs)
+    }
+
+    /// Run a query tree, where boolean queries combine the documents matching their clauses with intersections
+    /// (required clauses), unions (optional ones) and differences (prohibited ones), and their scores are the sum of the
+    /// scores of the matching required and optional clauses
+    pub fn retrieval_query(&self,
+                           query: &Query,
+                           similarity: &dyn Similarity) -> Result<Vec<(String, f64)>> {
+        let doc_scores = self.get_query_scores(query, None, similarity)?;
+
+        Ok(self.get_ordered_results(doc_scores))
+    }
+
+    /// Scores of the documents matching a query, only searching a field if one is given
+    fn get_query_scores(&self,
+                        query: &Query,
+                        index_key: Option<&str>,
+                        similarity: &dyn Similarity) -> Result<HashMap<SegmentDocId, f64>> {
+        match query {
+            Query::Term(token) => {
+                self.get_token_scores(token, index_key, similarity)
+            }
+            Query::Phrase { tokens, slop } => {
+                self.get_phrase_scores(tokens, *slop, index_key, similarity)
+            }
+            Query::Fuzzy { token, max_distance, distance_penalty } => {
+                let expansions = self.get_fuzzy_expansions(token, *max_distance, *distance_penalty)?;
+                self.get_best_expansion_scores(&expansions, index_key, similarity)
+            }
+            Query::Wildcard { pattern, max_expansions } => {
+                let expanded_tokens = expansion::get_wildcard_matching_tokens(&self.segments, pattern, *max_expansions)?;
+                self.get_token_union_scores(&expanded_tokens, index_key, similarity)
+            }
+            Query::Regex(regex_query) => {
+                let expanded_tokens = self.get_regex_matching_tokens(regex_query)?;
+                self.get_token_union_scores(&expanded_tokens, index_key, similarity)
+            }
+            Query::Field { index_key, query } => {
+                self.get_query_scores(query, Some(index_key), similarity)
+            }
+            Query::Boost { query, boost } => {
+                let mut doc_scores = self.get_query_scores(query, index_key, similarity)?;
+                doc_scores.values_mut().for_each(|score| *score *= boost);
+
+                Ok(doc_scores)
+            }
+            Query::Boolean { clauses, minimum_should_match } => {
+                self.get_boolean_scores(clauses, *minimum_should_match, index_key, similarity)
+            }
+        }
     }
 
-    fn get_bm25f_doc_len(&self, doc_id: &str, lengths : &HashMap<String, HashMap<String, u64>>, field_weights : &HashMap<String, f64>) -> f64 {
-        let mut doc_len = 0.0;
+    fn get_boolean_scores(&self,
+                          clauses: &[Clause],
+                          minimum_should_match: usize,
+                          index_key: Option<&str>,
+                          similarity: &dyn Similarity) -> Result<HashMap<SegmentDocId, f64>> {
+        // Intersection of the required clauses, if any
+        let mut required_scores: Option<HashMap<SegmentDocId, f64>> = None;
+        for clause in clauses.iter().filter(|clause| clause.occur == Occur::Must) {
+            let clause_scores = self.get_query_scores(&clause.query, index_key, similarity)?;
+
+            required_scores = Some(match required_scores {
+                None => clause_scores,
+                Some(scores) => scores.into_iter()
+                    .filter_map(|(segment_doc_id, score)| {
+                        clause_scores.get(&segment_doc_id).map(|clause_score| (segment_doc_id, score + clause_score))
+                    })
+                    .collect(),
+            });
+
+            // No document can match the query anymore
+            if required_scores.as_ref().is_some_and(|scores| scores.is_empty()) {
+                return Ok(HashMap::new());
+            }
+        }
 
-        for (index_key, doc_lengths) in lengths {
-            if doc_lengths.contains_key(doc_id) {
-                let unweighted_doc_len = *doc_lengths.get(doc_id).unwrap() as f64;
-                let field_weight = *field_weights.get(index_key).unwrap();
+        // Union of the optional clauses, with the number of them matched by each document
+        let mut optional_scores: HashMap<SegmentDocId, (f64, usize)> = HashMap::new();
+        for clause in clauses.iter().filter(|clause| clause.occur == Occur::Should) {
+            for (segment_doc_id, score) in self.get_query_scores(&clause.query, index_key, similarity)? {
+                let (optional_score, n_matched_clauses) = optional_scores.entry(segment_doc_id).or_insert((0.0, 0));
+                *optional_score += score;
+                *n_matched_clauses += 1;
+            }
+        }
+
+        let mut doc_scores = match required_scores {
+            Some(mut required_scores) => {
+                required_scores.retain(|segment_doc_id, score| {
+                    let (optional_score, n_matched_clauses) = optional_scores.get(segment_doc_id).unwrap_or(&(0.0, 0));
+                    *score += optional_score;
+
+                    *n_matched_clauses >= minimum_should_match
+                });
+
+                required_scores
+            }
+            None => optional_scores.into_iter()
+                .filter(|(_, (_, n_matched_clauses))| *n_matched_clauses >= minimum_should_match.max(1))
+                .map(|(segment_doc_id, (score, _))| (segment_doc_id, score))
+                .collect(),
+        };
+
+        // Difference with the prohibited clauses
+        for clause in clauses.iter().filter(|clause| clause.occur == Occur::MustNot) {
+            if doc_scores.is_empty() {
+                break;
+            }
 
-                doc_len += field_weight * unweighted_doc_len;
+            for segment_doc_id in self.get_query_scores(&clause.query, index_key, similarity)?.keys() {
+                doc_scores.remove(segment_doc_id);
             }
         }
 
-        doc_len
+        Ok(doc_scores)
     }
 
-    /// Returns a Map of index_key -> Map of doc_id -> Tf for query_token
-    pub(crate) fn get_matching_docids_postings(&self, query_token: &str) -> Result<HashMap<String, BTreeMap<String, u64>>> {
-        let mut matching_docids = HashMap::new();
+    /// Breakdown of the score of a document for a query, as scored by `retrieval_query`, down to the statistics of the
+    /// matched tokens and the parameters of the similarity. Documents not matching the query are explained too, with
+    /// the clauses they don't match
+    pub fn explain(&self,
+                   query: &Query,
+                   docid: &str,
+                   similarity: &dyn Similarity) -> Result<Explanation> {
+        let segment_doc_id = self.get_live_segment_doc_id(docid)?;
 
-        for index_key in &self.index_keys {
-            let postings_fst = self.postings_maps.get(index_key).unwrap();
+        self.explain_query(query, None, segment_doc_id, similarity)
+    }
 
-            if let Some(start_pos) = postings_fst.get(query_token) {
-                let postings_file = self.postings_data_files.get(index_key).unwrap();
+    fn explain_query(&self,
+                     query: &Query,
+                     index_key: Option<&str>,
+                     segment_doc_id: SegmentDocId,
+                     similarity: &dyn Similarity) -> Result<Explanation> {
+        match query {
+            Query::Term(token) => {
+                self.explain_token(token, index_key, segment_doc_id, similarity)
+            }
+            Query::Phrase { tokens, slop } => {
+                let doc_field_tfs = self.get_phrase_field_tfs(tokens, *slop, index_key)?;
+                let phrase_stats = Self::count_token_stats(&doc_field_tfs);
+                let phrase = tokens.iter().map(|token| token.text.as_str()).collect::<Vec<_>>().join(" ");
+                self.explain_match(doc_field_tfs, &phrase_stats, segment_doc_id, format!("phrase {:?}~{}", phrase, slop), similarity)
+            }
+            Query::Fuzzy { token, max_distance, distance_penalty } => {
+                let description = format!("fuzzy token {:?}~{}", token, max_distance);
+
+                // Only the best expansion counts, like in `get_best_expansion_scores`
+                let mut best_expansion: Option<Explanation> = None;
+                for (expanded_token, penalty) in self.get_fuzzy_expansions(token, *max_distance, *distance_penalty)? {
+                    let expansion = self.explain_token(&expanded_token, index_key, segment_doc_id, similarity)?;
+
+                    if expansion.matched && best_expansion.as_ref().is_none_or(|best| penalty * expansion.value > best.value) {
+                        best_expansion = Some(Explanation::new(
+                            penalty * expansion.value,
+                            format!("best expansion {:?}, penalty * score", expanded_token),
+                            vec![Explanation::leaf(penalty, "distance penalty"), expansion],
+                        ));
+                    }
+                }
 
-                let postings_size: u64 = aux::read_value_from_mmap(postings_file, start_pos, start_pos+8)?;
-                let postings: BTreeMap<String, u64> = aux::read_value_from_mmap(postings_file, start_pos+8, start_pos+8+postings_size)?;
+                Ok(match best_expansion {
+                    Some(best_expansion) => best_expansion.wrap(description),
+                    None => Explanation::no_match(description, Vec::new()),
+                })
+            }
+            Query::Wildcard { pattern, max_expansions } => {
+                let expanded_tokens = expansion::get_wildcard_matching_tokens(&self.segments, pattern, *max_expansions)?;
+                self.explain_token_union(&expanded_tokens, index_key, segment_doc_id, format!("wildcard {:?}", pattern), similarity)
+            }
+            Query::Regex(regex_query) => {
+                let expanded_tokens = self.get_regex_matching_tokens(regex_query)?;
+                let description = format!("regex {:?}", regex_query.pattern());
+                self.explain_token_union(&expanded_tokens, index_key, segment_doc_id, description, similarity)
+            }
+            Query::Field { index_key, query } => {
+                Ok(self.explain_query(query, Some(index_key), segment_doc_id, similarity)?.wrap(format!("field {}", index_key)))
+            }
+            Query::Boost { query, boost } => {
+                let explanation = self.explain_query(query, index_key, segment_doc_id, similarity)?;
 
-                matching_docids.insert(index_key.clone(),
-                                       // Map of doc_id -> Tf
-                                       postings);
+                Ok(match explanation.matched {
+                    true => Explanation::new(boost * explanation.value, "boost * score", vec![Explanation::leaf(*boost, "boost"), explanation]),
+                    false => Explanation::no_match("boosted query", vec![explanation]),
+                })
+            }
+            Query::Boolean { clauses, minimum_should_match } => {
+                self.explain_boolean(clauses, *minimum_should_match, index_key, segment_doc_id, similarity)
             }
         }
+    }
+
+    /// Explanation of a document's match for a token, like `get_token_scores`
+    fn explain_token(&self,
+                     token: &str,
+                     index_key: Option<&str>,
+                     segment_doc_id: SegmentDocId,
+                     similarity: &dyn Similarity) -> Result<Explanation> {
+        let doc_field_tfs = self.get_token_field_tfs(token, index_key)?;
+        let token_stats = self.get_token_stats(token, index_key)?;
 
-        Ok(matching_docids)
+        self.explain_match(doc_field_tfs, &token_stats, segment_doc_id, format!("term {:?}", token), similarity)
     }
 
-    fn get_lengths(&self, matching_docids: &HashMap<String, BTreeMap<String, u64>>) -> Result<HashMap<String, HashMap<String, u64>>> {
-        let mut lengths: HashMap<String, HashMap<String, u64>> = HashMap::new();
+    /// Explanation of a document's match for a union of tokens, like `get_token_union_scores`
+    fn explain_token_union(&self,
+                           tokens: &BTreeSet<String>,
+                           index_key: Option<&str>,
+                           segment_doc_id: SegmentDocId,
+                           description: String,
+                           similarity: &dyn Similarity) -> Result<Explanation> {
+        let doc_field_tfs = self.get_token_union_field_tfs(tokens, index_key)?;
+        let union_stats = Self::count_token_stats(&doc_field_tfs);
+        let expansions = tokens.iter().map(|token| format!("{:?}", token)).collect::<Vec<_>>().join(", ");
+
+        self.explain_match(doc_field_tfs, &union_stats, segment_doc_id, format!("{}, expanded to {}", description, expansions), similarity)
+    }
+
+    /// Explanation of a document's match for a token (or a phrase, or a union of tokens), given the frequency of the
+    /// match in every field of the documents containing it and its statistics, like `get_match_scores`
+    fn explain_match(&self,
+                     doc_field_tfs: Vec<DocFieldTfs>,
+                     token_stats: &TokenStats,
+                     segment_doc_id: SegmentDocId,
+                     description: String,
+                     similarity: &dyn Similarity) -> Result<Explanation> {
+
+        match doc_field_tfs.iter().find(|(match_segment_doc_id, _)| *match_segment_doc_id == segment_doc_id) {
+            Some(((segment_i, doc_id), field_tfs)) => {
+                let field_matches = similarity::get_field_matches(&self.segments[*segment_i], *doc_id, field_tfs)?;
+
+                Ok(similarity.explain(&field_matches, token_stats, &self.collection_stats).wrap(description))
+            }
+            None => Ok(Explanation::no_match(description, Vec::new())),
+        }
+    }
 
-        for (index_key, postings) in matching_docids {
-            for docid in postings.keys() {
-                if let Some(length) = self.get_docid_length(docid, index_key) {
-                    if lengths.contains_key(index_key) {
-                        lengths.get_mut(index_key).unwrap()
-                            .insert((*docid.clone()).to_string(), length);
+    fn explain_boolean(&self,
+                       clauses: &[Clause],
+                       minimum_should_match: usize,
+                       index_key: Option<&str>,
+                       segment_doc_id: SegmentDocId,
+                       similarity: &dyn Similarity) -> Result<Explanation> {
+        let mut details = Vec::new();
+        let mut score = 0.0;
+        let mut has_required_clauses = false;
+        let mut n_missed_required_clauses = 0;
+        let mut n_matched_optional_clauses = 0;
+        let mut n_matched_prohibited_clauses = 0;
+
+        for clause in clauses {
+            let explanation = self.explain_query(&clause.query, index_key, segment_doc_id, similarity)?;
+
+            match clause.occur {
+                Occur::Must => {
+                    has_required_clauses = true;
+                    if explanation.matched {
+                        score += explanation.value;
                     } else {
-                        let mut internal_map = HashMap::new();
-                        internal_map.insert(docid.clone(), length);
-                        lengths.insert(index_key.clone(), internal_map);
+                        n_missed_required_clauses += 1;
+                    }
+                    details.push(explanation.wrap("required clause"));
+                }
+                Occur::Should => {
+                    if explanation.matched {
+                        score += explanation.value;
+                        n_matched_optional_clauses += 1;
+                    }
+                    details.push(explanation.wrap("optional clause"));
+                }
+                Occur::MustNot => {
+                    if explanation.matched {
+                        n_matched_prohibited_clauses += 1;
                     }
-                } else {
-                    return Err(anyhow!(format!("Couldn't find the length for docid {}", docid)));
+                    // Prohibited clauses don't add to the score, whether they match or not
+                    details.push(Explanation { value: 0.0, ..explanation.wrap("prohibited clause") });
                 }
             }
         }
 
-        Ok(lengths)
+        // Without required clauses, at least one optional clause must match
+        let minimum_should_match = if has_required_clauses { minimum_should_match } else { minimum_should_match.max(1) };
+
+        Ok(if n_missed_required_clauses > 0 {
+            Explanation::no_match(format!("{} required clauses not matched", n_missed_required_clauses), details)
+        } else if n_matched_optional_clauses < minimum_should_match {
+            Explanation::no_match(
+                format!("{} optional clauses matched, out of the {} needed", n_matched_optional_clauses, minimum_should_match),
+                details,
+            )
+        } else if n_matched_prohibited_clauses > 0 {
+            Explanation::no_match(format!("{} prohibited clauses matched", n_matched_prohibited_clauses), details)
+        } else {
+            Explanation::new(score, "sum of the matching clauses", details)
+        })
     }
 
-    fn get_avg_lengths(&self, matching_docids: &HashMap<String, BTreeMap<String, u64>>) -> HashMap<String, u64> {
-        let mut avg_lengths = HashMap::new();
+    /// Run a fuzzy query, where every query token is expanded to the index tokens within `max_distance` edits
+    /// of it (with a Levenshtein automaton over the postings FSTs). Expanded tokens are scored like regular ones, times
+    /// `distance_penalty` to the power of their distance, and only the best expansion of each query token counts
+    pub fn retrieval_fuzzy(&self,
+                           query_tokens: &[String],
+                           max_distance: u32,
+                           distance_penalty: f64,
+                           similarity: &dyn Similarity) -> Result<Vec<(String, f64)>> {
+        let mut doc_scores: HashMap<SegmentDocId, f64> = HashMap::new();
+
+        for query_token in query_tokens {
+            let expansions = self.get_fuzzy_expansions(query_token, max_distance, distance_penalty)?;
+            let token_scores = self.get_best_expansion_scores(&expansions, None, similarity)?;
+
+            for (segment_doc_id, score) in token_scores {
+                *doc_scores.entry(segment_doc_id).or_insert(0.0) += score;
+            }
+        }
+
+        Ok(self.get_ordered_results(doc_scores))
+    }
+
+    /// Index tokens within `max_distance` edits of a fuzzy query token, along with the penalty of their distance
+    fn get_fuzzy_expansions(&self, query_token: &str, max_distance: u32, distance_penalty: f64) -> Result<Vec<(String, f64)>> {
+        let automaton = Levenshtein::new(query_token, max_distance)?;
+
+        Ok(expansion::get_matching_tokens(&self.segments, &automaton, usize::MAX)?
+            .into_iter()
+            .map(|expanded_token| {
+                let distance = expansion::get_edit_distance(query_token, &expanded_token);
+                (expanded_token, distance_penalty.powi(distance as i32))
+            })
+            .collect())
+    }
 
-        for index_key in matching_docids.keys() {
-            avg_lengths.insert(index_key.clone(), self.avg_lengths_map.get(index_key).unwrap());
+    /// Penalised scores of the best expansion of a query token in every document, only searching a field if one is
+    /// given
+    fn get_best_expansion_scores(&self,
+                                 expansions: &[(String, f64)],
+                                 index_key: Option<&str>,
+                                 similarity: &dyn Similarity) -> Result<HashMap<SegmentDocId, f64>> {
+        let mut token_scores: HashMap<SegmentDocId, f64> = HashMap::new();
+
+        for (expanded_token, penalty) in expansions {
+            let expanded_token_scores = self.get_token_scores(
+                expanded_token,
+                index_key,
+                similarity,
+            )?;
+
+            for (segment_doc_id, score) in expanded_token_scores {
+                let token_score = token_scores.entry(segment_doc_id).or_insert(0.0);
+                *token_score = token_score.max(penalty * score);
+            }
         }
 
-        avg_lengths
+        Ok(token_scores)
+    }
+
+    /// Run a query for a wildcard pattern, where `*` matches any sequence of characters and `?` a single one
+    /// (e.g. `foo*`, `*bar` or `f?o`). The pattern is expanded to at most `max_expansions` index tokens, which are
+    /// scored together as a single token, so that a document's score doesn't grow with the number of matched tokens
+    pub fn retrieval_wildcard(&self,
+                              pattern: &str,
+                              max_expansions: usize,
+                              similarity: &dyn Similarity) -> Result<Vec<(String, f64)>> {
+        let expanded_tokens = expansion::get_wildcard_matching_tokens(&self.segments, pattern, max_expansions)?;
+
+        let doc_scores = self.get_token_union_scores(&expanded_tokens, None, similarity)?;
+
+        Ok(self.get_ordered_results(doc_scores))
     }
 
-    fn get_docid_length(&self, docid: &str, index_key: &str) -> Option<u64> {
-        let lengths_fst = self.lengths_maps.get(index_key).unwrap();
+    /// Run a query for a regular expression, scoring the index tokens matching the whole pattern together as a
+    /// single token, like `retrieval_wildcard`
+    pub fn retrieval_regex(&self,
+                           query: &RegexQuery,
+                           similarity: &dyn Similarity) -> Result<Vec<(String, f64)>> {
+        let expanded_tokens = self.get_regex_matching_tokens(query)?;
 
-        lengths_fst.get(docid)
+        let doc_scores = self.get_token_union_scores(&expanded_tokens, None, similarity)?;
+
+        Ok(self.get_ordered_results(doc_scores))
     }
 
-    fn get_doc_frequency(&self, matching_docids: &HashMap<String, BTreeMap<String, u64>>) -> u64 {
-        let mut docs = HashSet::new();
+    fn get_regex_matching_tokens(&self, query: &RegexQuery) -> Result<BTreeSet<String>> {
+        expansion::get_matching_tokens(&self.segments, query.automaton(), query.max_expansions())
+    }
 
-        for postings_map in matching_docids.values() {
-            for doc_id in postings_map.keys() {
-                docs.insert(doc_id.clone());
+    /// Scores of the documents containing any of the tokens, as if they were a single token: the frequency of
+    /// the union in a field is the sum of the tokens' frequencies, and its document frequency is the number of
+    /// documents containing any of them. Only searches a field if one is given
+    fn get_token_union_scores(&self,
+                              tokens: &BTreeSet<String>,
+                              index_key: Option<&str>,
+                              similarity: &dyn Similarity) -> Result<HashMap<SegmentDocId, f64>> {
+        let union_doc_field_tfs = self.get_token_union_field_tfs(tokens, index_key)?;
+        let union_stats = Self::count_token_stats(&union_doc_field_tfs);
+
+        self.get_match_scores(union_doc_field_tfs, &union_stats, similarity)
+    }
+
+    /// Frequency of the union of the tokens in every field of the live documents containing any of them, only
+    /// searching a field if one is given
+    fn get_token_union_field_tfs(&self, tokens: &BTreeSet<String>, index_key: Option<&str>) -> Result<Vec<DocFieldTfs<'_>>> {
+        let mut union_doc_field_tfs = Vec::new();
+
+        for (segment_i, segment) in self.segments.iter().enumerate() {
+            // Doc id -> Field -> Frequency of the union of the tokens
+            let mut doc_field_tfs: BTreeMap<DocId, HashMap<&str, f64>> = BTreeMap::new();
+
+            for token in tokens {
+                let mut field_cursors = segment.get_postings_cursors(token)?;
+                field_cursors.retain(|(cursor_index_key, _)| index_key.is_none_or(|index_key| index_key == *cursor_index_key));
+                for (index_key, mut cursor) in field_cursors {
+                    while let Some((doc_id, tf)) = cursor.next()? {
+                        *doc_field_tfs.entry(doc_id)
+                            .or_default()
+                            .entry(index_key)
+                            .or_insert(0.0) += tf as f64;
+                    }
+                }
             }
+
+            for (doc_id, field_tfs) in doc_field_tfs {
+                if !segment.is_deleted(doc_id) {
+                    union_doc_field_tfs.push(((segment_i, doc_id), field_tfs.into_iter().collect()));
+                }
+            }
+        }
+
+        Ok(union_doc_field_tfs)
+    }
+
+    /// Original text of the stored fields of a document (see `Indexer::store_fields`), without going back to the
+    /// source documents
+    pub fn get_document(&self, docid: &str) -> Result<HashMap<String, String>> {
+        let (segment_i, doc_id) = self.get_live_segment_doc_id(docid)?;
+
+        self.segments[segment_i].get_stored_doc(doc_id)
+    }
+
+    /// Segment and internal doc id of the live copy of a document
+    fn get_live_segment_doc_id(&self, docid: &str) -> Result<SegmentDocId> {
+        // Updated documents are deleted from their previous segment, so only one segment has a live copy
+        self.segments.iter()
+            .enumerate()
+            .find_map(|(segment_i, segment)| {
+                segment.get_doc_id(docid)
+                    .filter(|doc_id| !segment.is_deleted(*doc_id))
+                    .map(|doc_id| (segment_i, doc_id))
+            })
+            .ok_or_else(|| anyhow!(format!("Couldn't find a live document with docid {}", docid)))
+    }
+
+    /// External docid of a document
+    pub fn get_docid(&self, (segment_i, doc_id): SegmentDocId) -> String {
+        self.segments[segment_i].get_docid(doc_id)
+    }
+
+    /// Statistics of a token over the live documents of all segments, only counting a field if one is given. They are
+    /// read from the ones stored in every segment without deletions, without decoding the token's postings
+    fn get_token_stats(&self, token: &str, index_key: Option<&str>) -> Result<TokenStats> {
+        let mut token_stats = TokenStats::default();
+        for segment in &self.segments {
+            token_stats.add(&segment.get_token_stats(token, index_key)?);
         }
 
-        docs.len() as u64
+        Ok(token_stats)
     }
-}
\ No newline at end of file
+
+    /// Resolves the external docids of the results, sorted by decreasing score
+    fn get_ordered_results(&self, doc_scores: HashMap<SegmentDocId, f64>) -> Vec<(String, f64)> {
+        let mut ordered_results: Vec<(String, f64)> = doc_scores
+            .into_iter()
+            .map(|(segment_doc_id, score)| (self.get_docid(segment_doc_id), score))
+            .collect();
+        ordered_results.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        ordered_results
+    }
+}
@@ -0,0 +1,139 @@
+// Phrase matching over positional postings. The postings of the phrase tokens are intersected by leapfrogging their
+// cursors, and the positions of every common document are checked for occurrences of the phrase. Exact occurrences
+// count as 1, and sloppy ones (with `distance` other tokens in between) as 1 / (1 + distance)
+//
+// The phrase tokens are matched at the offsets of their positions in the phrase, so that the gaps left by the tokens
+// removed by the analyzer (e.g. "quick fox" from "quick the fox") must also be in the documents
+
+use std::collections::HashMap;
+use anyhow::Result;
+use crate::indexing::docids::DocId;
+use crate::indexing::postings_codec::Position;
+use crate::retrieval::segment::Segment;
+use crate::tokenizer::Token;
+
+/// Returns the phrase frequency of every document of the segment containing the phrase in a field, including deleted
+/// documents
+pub fn get_field_phrase_frequencies(segment: &Segment,
+                                    index_key: &str,
+                                    phrase_tokens: &[Token],
+                                    slop: u32) -> Result<HashMap<DocId, f64>> {
+    let mut phrase_frequencies = HashMap::new();
+
+    let offsets: Vec<Position> = phrase_tokens.iter()
+        .map(|token| token.position - phrase_tokens[0].position)
+        .collect();
+    let mut cursors = Vec::new();
+    for token in phrase_tokens {
+        match segment.get_field_postings_cursor(index_key, &token.text)? {
+            Some(cursor) => cursors.push(cursor),
+            None => return Ok(phrase_frequencies),
+        }
+    }
+
+    let mut target = 0;
+    'documents: loop {
+        let Some((candidate, _)) = cursors[0].advance(target)? else {
+            break;
+        };
+
+        // Move every cursor to the candidate, restarting from a later candidate if one of them skips past it
+        for cursor in cursors.iter_mut().skip(1) {
+            match cursor.advance(candidate)? {
+                None => break 'documents,
+                Some((doc_id, _)) if doc_id > candidate => {
+                    target = doc_id;
+                    continue 'documents;
+                }
+                Some(_) => {}
+            }
+        }
+
+        let positions: Vec<&[Position]> = cursors.iter().map(|cursor| cursor.positions()).collect();
+        let phrase_frequency = get_phrase_frequency(&positions, &offsets, slop);
+        if phrase_frequency > 0.0 {
+            phrase_frequencies.insert(candidate, phrase_frequency);
+        }
+
+        target = candidate + 1;
+    }
+
+    Ok(phrase_frequencies)
+}
+
+/// Frequency of the phrase in a document, given the positions of each phrase token in it and their offsets in the
+/// phrase
+fn get_phrase_frequency(positions: &[&[Position]], offsets: &[Position], slop: u32) -> f64 {
+    let mut phrase_frequency = 0.0;
+
+    for start in positions[0] {
+        // The closest following position of every next token (at least as far as its offset from the previous one)
+        // gives the shortest occurrence from this start
+        let mut previous = *start;
+        for (token_positions, gap) in positions[1..].iter().zip(offsets.windows(2).map(|pair| pair[1] - pair[0])) {
+            let next_i = token_positions.partition_point(|position| *position < previous + gap);
+            match token_positions.get(next_i) {
+                Some(position) => previous = *position,
+                // Later starts can't have an occurrence either
+                None => return phrase_frequency,
+            }
+        }
+
+        let distance = previous - start - offsets[offsets.len() - 1];
+        if distance <= slop {
+            phrase_frequency += 1.0 / (1.0 + distance as f64);
+        }
+    }
+
+    phrase_frequency
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_phrase_frequency() {
+        // "a b" in "a b x a b b"
+        let positions: [&[Position]; 2] = [&[0, 3], &[1, 4, 5]];
+
+        assert_eq!(get_phrase_frequency(&positions, &[0, 1], 0), 2.0);
+        // In the reverse order, "b a" has no occurrence
+        assert_eq!(get_phrase_frequency(&[positions[1], positions[0]], &[0, 1], 0), 0.0);
+    }
+
+    #[test]
+    fn sloppy_phrase_frequency() {
+        // "a b" in "a x x b a b"
+        let positions: [&[Position]; 2] = [&[0, 4], &[3, 5]];
+
+        assert_eq!(get_phrase_frequency(&positions, &[0, 1], 0), 1.0);
+        assert_eq!(get_phrase_frequency(&positions, &[0, 1], 1), 1.0);
+        assert_eq!(get_phrase_frequency(&positions, &[0, 1], 2), 1.0 + 1.0 / 3.0);
+    }
+
+    #[test]
+    fn phrase_frequency_with_gaps() {
+        // "a _ b" (with a removed stopword) in "a b x a x b", where the first "a b" is too close to match
+        let positions: [&[Position]; 2] = [&[0, 3], &[1, 5]];
+
+        assert_eq!(get_phrase_frequency(&positions, &[0, 2], 0), 1.0);
+        assert_eq!(get_phrase_frequency(&positions, &[0, 2], 2), 1.0);
+        assert_eq!(get_phrase_frequency(&positions, &[0, 2], 3), 1.25);
+    }
+
+    #[test]
+    fn phrase_frequency_of_repeated_tokens() {
+        // "a b a" in "a b a b a", where both occurrences share the middle "a"
+        let a_positions: &[Position] = &[0, 2, 4];
+        let b_positions: &[Position] = &[1, 3];
+        assert_eq!(get_phrase_frequency(&[a_positions, b_positions, a_positions], &[0, 1, 2], 0), 2.0);
+
+        // "a a" in "a a a", and in "a x a" where a single "a" can't match both tokens
+        let a_positions: &[Position] = &[0, 1, 2];
+        assert_eq!(get_phrase_frequency(&[a_positions, a_positions], &[0, 1], 0), 2.0);
+        let a_positions: &[Position] = &[0, 2];
+        assert_eq!(get_phrase_frequency(&[a_positions, a_positions], &[0, 1], 0), 0.0);
+        assert_eq!(get_phrase_frequency(&[a_positions, a_positions], &[0, 1], 1), 0.5);
+    }
+}
@@ -0,0 +1,110 @@
+// Term proximity accumulators, as in BM25TP (Rasolofo & Savoy). Every pair of occurrences of two different query
+// tokens at most PROXIMITY_WINDOW positions apart adds 1 / distance^2 to the accumulator of that pair of tokens, which
+// is later scored with a BM25-like saturation, so documents where the query tokens appear close together rank higher.
+// Occurrences at the same position (e.g. n-grams of the same token) don't say anything about proximity, so they are
+// ignored
+
+use std::collections::HashMap;
+use anyhow::Result;
+use crate::indexing::docids::DocId;
+use crate::indexing::postings_codec::Position;
+use crate::retrieval::segment::Segment;
+
+const PROXIMITY_WINDOW: Position = 5;
+
+// Pair of query token indices, the smallest one first
+pub type TokenPair = (usize, usize);
+
+/// Returns the proximity accumulators of every document of the segment containing at least two of the (distinct)
+/// query tokens close together in a field, including deleted documents
+pub fn get_field_proximities(segment: &Segment,
+                             index_key: &str,
+                             query_tokens: &[String]) -> Result<HashMap<DocId, HashMap<TokenPair, f64>>> {
+    // Doc id -> Occurrences of the query tokens, as (position, token index)
+    let mut doc_occurrences: HashMap<DocId, Vec<(Position, usize)>> = HashMap::new();
+
+    for (token_i, token) in query_tokens.iter().enumerate() {
+        let Some(mut cursor) = segment.get_field_postings_cursor(index_key, token)? else {
+            continue;
+        };
+
+        while let Some((doc_id, _)) = cursor.next()? {
+            doc_occurrences.entry(doc_id)
+                .or_default()
+                .extend(cursor.positions().iter().map(|position| (*position, token_i)));
+        }
+    }
+
+    let mut proximities = HashMap::new();
+    for (doc_id, mut occurrences) in doc_occurrences {
+        occurrences.sort_unstable();
+
+        let pair_accumulators = get_pair_accumulators(&occurrences);
+        if !pair_accumulators.is_empty() {
+            proximities.insert(doc_id, pair_accumulators);
+        }
+    }
+
+    Ok(proximities)
+}
+
+fn get_pair_accumulators(occurrences: &[(Position, usize)]) -> HashMap<TokenPair, f64> {
+    let mut pair_accumulators = HashMap::new();
+
+    for (occurrence_i, (position, token_i)) in occurrences.iter().enumerate() {
+        for (next_position, next_token_i) in &occurrences[occurrence_i + 1..] {
+            let distance = next_position - position;
+            if distance > PROXIMITY_WINDOW {
+                break;
+            }
+
+            if distance > 0 && next_token_i != token_i {
+                let pair = (*token_i.min(next_token_i), *token_i.max(next_token_i));
+                *pair_accumulators.entry(pair).or_insert(0.0) += 1.0 / ((distance * distance) as f64);
+            }
+        }
+    }
+
+    pair_accumulators
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_index::{bm25f, body_docs, TestIndex};
+
+    #[test]
+    fn stacked_occurrences_are_ignored() {
+        // The first two occurrences are at the same position, like the n-grams of a token
+        let pair_accumulators = get_pair_accumulators(&[(3, 0), (3, 1), (5, 1)]);
+
+        assert_eq!(pair_accumulators, HashMap::from([((0, 1), 0.25)]));
+    }
+
+    #[test]
+    fn closer_tokens_rank_higher() {
+        let index = TestIndex::new("proximity");
+        // Same lengths and frequencies, so only the distance between the query tokens tells the documents apart
+        let mut indexer = index.indexer(&["body"]);
+        indexer.store_positions("body");
+        indexer.index(body_docs(&[
+            ("far", "rust a b c d e f search"),
+            ("near", "rust search a b c d e f"),
+            ("middle", "rust a b search c d e f"),
+        ])).unwrap();
+
+        let retriever = index.retriever(&["body"]);
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+        let query_tokens = vec!["rust".to_string(), "search".to_string(), "missing".to_string()];
+        let results = retriever.retrieval_multiple_tokens_with_proximity(&query_tokens, &bm25f).unwrap();
+        let plain_results = retriever.retrieval_multiple_tokens(&query_tokens, &bm25f).unwrap();
+
+        let docids: Vec<&str> = results.iter().map(|(docid, _)| docid.as_str()).collect();
+        assert_eq!(docids, ["near", "middle", "far"]);
+        assert!(results.iter().all(|(_, score)| score.is_finite()));
+
+        // Beyond the proximity window, only the regular score is left
+        let plain_score = plain_results.iter().find(|(docid, _)| docid == "far").unwrap().1;
+        assert!((results[2].1 - plain_score).abs() < 1e-12, "{} != {}", results[2].1, plain_score);
+    }
+}
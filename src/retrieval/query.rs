@@ -0,0 +1,173 @@
+// Query tree run by `Retriever::retrieval_query`, either built directly or parsed from a query string by `QueryParser`.
+// Leaves are scored with BM25F like the single-purpose retrieval functions, and boolean queries combine the documents
+// matched by their clauses: required clauses are intersected, optional ones are unioned, and prohibited ones are
+// subtracted, e.g. `+rust -java compiler` is
+//
+//     Query::Boolean {
+//         clauses: vec![
+//             Clause::new(Occur::Must, Query::Term("rust".to_string())),
+//             Clause::new(Occur::MustNot, Query::Term("java".to_string())),
+//             Clause::new(Occur::Should, Query::Term("compiler".to_string())),
+//         ],
+//         minimum_should_match: 0,
+//     }
+
+use crate::retrieval::regex::RegexQuery;
+use crate::tokenizer::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occur {
+    // The document must match the clause, which adds to its score
+    Must,
+    // The document may match the clause, which adds to its score
+    Should,
+    // The document must not match the clause
+    MustNot,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Term(String),
+    // Tokens as analyzed from the phrase, whose positions keep the gaps of the removed ones (e.g. stopwords)
+    Phrase {
+        tokens: Vec<Token>,
+        slop: u32,
+    },
+    // Like `Retriever::retrieval_fuzzy` for a single token
+    Fuzzy {
+        token: String,
+        max_distance: u32,
+        distance_penalty: f64,
+    },
+    // Like `Retriever::retrieval_wildcard`
+    Wildcard {
+        pattern: String,
+        max_expansions: usize,
+    },
+    // Like `Retriever::retrieval_regex`
+    Regex(Box<RegexQuery>),
+    // Only searches a field, instead of all of them
+    Field {
+        index_key: String,
+        query: Box<Query>,
+    },
+    // Multiplies the scores of a query
+    Boost {
+        query: Box<Query>,
+        boost: f64,
+    },
+    // Without required clauses, documents must match at least one optional clause (or `minimum_should_match` of them,
+    // if higher). Queries with only prohibited clauses match no documents
+    Boolean {
+        clauses: Vec<Clause>,
+        minimum_should_match: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub occur: Occur,
+    pub query: Query,
+}
+
+impl Clause {
+    pub fn new(occur: Occur, query: Query) -> Self {
+        Self { occur, query }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retrieval::{Bm25f, Retriever};
+    use crate::test_index::{bm25f, body_docs, sorted_docids, TestIndex};
+    use std::collections::HashMap;
+
+    fn term(token: &str) -> Query {
+        Query::Term(token.to_string())
+    }
+
+    fn boolean(clauses: &[(Occur, &str)], minimum_should_match: usize) -> Query {
+        Query::Boolean {
+            clauses: clauses.iter().map(|(occur, token)| Clause::new(*occur, term(token))).collect(),
+            minimum_should_match,
+        }
+    }
+
+    fn create_index(index_name: &str) -> (TestIndex, Retriever) {
+        let index = TestIndex::new(index_name);
+        index.indexer(&["body"]).index(body_docs(&[
+            ("d1", "rust search engine"),
+            ("d2", "rust search"),
+            ("d3", "rust"),
+            ("d4", "search engine"),
+            ("d5", "java"),
+        ])).unwrap();
+        let retriever = index.retriever(&["body"]);
+
+        (index, retriever)
+    }
+
+    fn scores(retriever: &Retriever, query: &Query, bm25f: &Bm25f) -> HashMap<String, f64> {
+        retriever.retrieval_query(query, bm25f).unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn prohibited_clauses_alone_match_nothing() {
+        let (_index, retriever) = create_index("boolean_must_not");
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+        let docids = |query: &Query| sorted_docids(retriever.retrieval_query(query, &bm25f).unwrap());
+
+        assert!(docids(&boolean(&[(Occur::MustNot, "java")], 0)).is_empty());
+        assert!(docids(&boolean(&[(Occur::MustNot, "java"), (Occur::MustNot, "rust")], 0)).is_empty());
+        assert_eq!(docids(&boolean(&[(Occur::Should, "search"), (Occur::MustNot, "engine")], 0)), ["d2"]);
+        assert_eq!(docids(&boolean(&[(Occur::Must, "rust"), (Occur::MustNot, "search")], 0)), ["d3"]);
+    }
+
+    #[test]
+    fn minimum_should_match_counts_the_optional_clauses() {
+        let (_index, retriever) = create_index("boolean_minimum_should_match");
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+        let docids = |query: &Query| sorted_docids(retriever.retrieval_query(query, &bm25f).unwrap());
+        let optional_clauses = [(Occur::Should, "rust"), (Occur::Should, "search"), (Occur::Should, "engine")];
+
+        // Without required clauses, at least one optional clause must match
+        assert_eq!(docids(&boolean(&optional_clauses, 0)), ["d1", "d2", "d3", "d4"]);
+        assert_eq!(docids(&boolean(&optional_clauses, 1)), ["d1", "d2", "d3", "d4"]);
+        assert_eq!(docids(&boolean(&optional_clauses, 2)), ["d1", "d2", "d4"]);
+        assert_eq!(docids(&boolean(&optional_clauses, 3)), ["d1"]);
+        assert!(docids(&boolean(&optional_clauses, 4)).is_empty());
+
+        // With required clauses, optional ones only need to match if there is a minimum
+        let clauses = [(Occur::Must, "rust"), (Occur::Should, "search"), (Occur::Should, "engine")];
+        assert_eq!(docids(&boolean(&clauses, 0)), ["d1", "d2", "d3"]);
+        assert_eq!(docids(&boolean(&clauses, 1)), ["d1", "d2"]);
+        assert_eq!(docids(&boolean(&clauses, 2)), ["d1"]);
+    }
+
+    #[test]
+    fn matching_clauses_add_up() {
+        let (_index, retriever) = create_index("boolean_scores");
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+        let rust_scores = scores(&retriever, &term("rust"), &bm25f);
+        let search_scores = scores(&retriever, &term("search"), &bm25f);
+        let engine_scores = scores(&retriever, &term("engine"), &bm25f);
+
+        let boolean_scores = scores(&retriever, &boolean(&[(Occur::Must, "rust"), (Occur::Should, "search"), (Occur::Should, "engine")], 0), &bm25f);
+        let expected_scores = [
+            ("d1", rust_scores["d1"] + search_scores["d1"] + engine_scores["d1"]),
+            ("d2", rust_scores["d2"] + search_scores["d2"]),
+            ("d3", rust_scores["d3"]),
+        ];
+        assert_eq!(boolean_scores.len(), expected_scores.len());
+        for (docid, expected_score) in expected_scores {
+            assert!((boolean_scores[docid] - expected_score).abs() < 1e-12, "{}: {} != {}", docid, boolean_scores[docid], expected_score);
+        }
+
+        // Prohibited clauses remove documents, without changing the scores of the others
+        let prohibited_scores = scores(&retriever, &boolean(&[(Occur::Must, "rust"), (Occur::Should, "search"), (Occur::MustNot, "engine")], 0), &bm25f);
+        assert_eq!(prohibited_scores.len(), 2);
+        assert!((prohibited_scores["d2"] - (rust_scores["d2"] + search_scores["d2"])).abs() < 1e-12);
+        assert_eq!(prohibited_scores["d3"], rust_scores["d3"]);
+    }
+}
@@ -0,0 +1,572 @@
+// Parser of query strings into query trees, e.g. `+rust -java title:"borrow checker"~2 compiler^2 (async OR tokio~1)`
+//
+// - `term` and `"a phrase"` are matched in every field, and `field:term`, `field:"a phrase"` or `field:(...)` only in
+//   one of them, given by its document field name
+// - `+` marks a required clause, and `-` (or `NOT`) a prohibited one. Unmarked clauses are optional
+// - `a AND b` requires both sides, and binds tighter than `a OR b`, which is the same as `a b`
+// - `term~1` is a fuzzy term (`term~` allowing 2 edits), `"a phrase"~2` a sloppy phrase, and `^2` boosts anything
+//
+// Terms and phrases are analyzed by the analyzer of the field they search, as done at indexing time, and terms left
+// empty by it are dropped. Terms analyzed into several tokens require all of them. Unscoped terms and phrases which
+// are analyzed differently by some fields are split into field-scoped queries, one per field
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use crate::retrieval::query::{Clause, Occur, Query};
+use crate::tokenizer::{Analyzer, Token};
+
+// Fuzzy terms can't be expanded any further, as Levenshtein automata grow quickly with the distance
+const MAX_FUZZY_DISTANCE: u32 = 2;
+// Every edit of a fuzzy term's expansions halves their score
+const FUZZY_DISTANCE_PENALTY: f64 = 0.5;
+// Groups and field-scoped queries are parsed recursively, so their nesting is bounded to keep the stack from overflowing
+const MAX_NESTING_DEPTH: usize = 32;
+
+/// Error found while parsing a query string, at a character position
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl QueryParseError {
+    fn new(position: usize, message: &str) -> Self {
+        Self { position, message: message.to_string() }
+    }
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lexeme {
+    Word(String),
+    Phrase(String),
+    // Field name, followed by `:`
+    Field(String),
+    Plus,
+    Minus,
+    And,
+    Or,
+    Not,
+    LeftParenthesis,
+    RightParenthesis,
+    // `~`, with the number following it, if any
+    Tilde(Option<u32>),
+    // `^`, with the number following it
+    Caret(f64),
+}
+
+#[derive(Debug)]
+pub struct QueryParser {
+    field_keys: HashMap<String, String>, // Document field name -> Index field name
+    analyzers: BTreeMap<String, Analyzer>, // Index field name -> Analyzer
+}
+
+impl QueryParser {
+    /// Fields without an analyzer use the default one, which should be the ones recorded in the index (see
+    /// `Retriever::analyzers`)
+    pub fn new(field_keys: HashMap<String, String>, analyzers: &HashMap<String, Analyzer>) -> Self {
+        let analyzers = field_keys.values()
+            .map(|index_key| (index_key.clone(), analyzers.get(index_key).cloned().unwrap_or_default()))
+            .collect();
+
+        Self { field_keys, analyzers }
+    }
+
+    pub fn parse(&self, query_string: &str) -> Result<Query, QueryParseError> {
+        let chars: Vec<char> = query_string.chars().collect();
+        let mut parser = Parser {
+            field_keys: &self.field_keys,
+            analyzers: &self.analyzers,
+            index_key: None,
+            lexemes: lex(&chars)?,
+            lexeme_i: 0,
+            end_position: chars.len(),
+            depth: 0,
+        };
+
+        let clauses = parser.parse_disjunction()?;
+        if let Some((_, position)) = parser.peek() {
+            return Err(QueryParseError::new(*position, "Unexpected ')'"));
+        }
+
+        // An empty query matches no documents
+        Ok(clauses_query(clauses).unwrap_or(Query::Boolean { clauses: Vec::new(), minimum_should_match: 0 }))
+    }
+}
+
+fn is_special_char(c: char) -> bool {
+    matches!(c, '(' | ')' | '"' | ':' | '^' | '~')
+}
+
+/// Number following a `~` or `^`, which ends at the next whitespace or special character
+fn lex_number(chars: &[char], char_i: &mut usize) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.get(*char_i).filter(|c| !c.is_whitespace() && !is_special_char(**c)) {
+        number.push(*c);
+        *char_i += 1;
+    }
+
+    number
+}
+
+/// Splits a query string in lexemes, along with their character positions
+fn lex(chars: &[char]) -> Result<Vec<(Lexeme, usize)>, QueryParseError> {
+    let mut lexemes = Vec::new();
+
+    let mut char_i = 0;
+    while char_i < chars.len() {
+        let position = char_i;
+        let c = chars[char_i];
+        char_i += 1;
+
+        let lexeme = match c {
+            _ if c.is_whitespace() => continue,
+            '(' => Lexeme::LeftParenthesis,
+            ')' => Lexeme::RightParenthesis,
+            '+' => Lexeme::Plus,
+            '-' => Lexeme::Minus,
+            ':' => return Err(QueryParseError::new(position, "Expected a field name before ':'")),
+            '~' => {
+                let number = lex_number(chars, &mut char_i);
+                if number.is_empty() {
+                    Lexeme::Tilde(None)
+                } else {
+                    let number = number.parse()
+                        .map_err(|_| QueryParseError::new(position + 1, "Expected a whole number after '~'"))?;
+                    Lexeme::Tilde(Some(number))
+                }
+            }
+            '^' => {
+                let boost = lex_number(chars, &mut char_i).parse::<f64>()
+                    .ok()
+                    .filter(|boost| boost.is_finite() && *boost >= 0.0)
+                    .ok_or_else(|| QueryParseError::new(position + 1, "Expected a positive number after '^'"))?;
+                Lexeme::Caret(boost)
+            }
+            '"' => {
+                let mut phrase = String::new();
+                loop {
+                    match chars.get(char_i) {
+                        None => return Err(QueryParseError::new(position, "Unterminated phrase")),
+                        Some('"') => break,
+                        Some('\\') if char_i + 1 < chars.len() => {
+                            phrase.push(chars[char_i + 1]);
+                            char_i += 1;
+                        }
+                        Some(phrase_char) => phrase.push(*phrase_char),
+                    }
+                    char_i += 1;
+                }
+                char_i += 1;
+
+                Lexeme::Phrase(phrase)
+            }
+            _ => {
+                // `+` and `-` are only operators at the start of a word, so that e.g. `e-mail` is a single word
+                let mut word = String::new();
+                char_i -= 1;
+                while let Some(word_char) = chars.get(char_i).filter(|c| !c.is_whitespace() && !is_special_char(**c)) {
+                    match word_char {
+                        '\\' if char_i + 1 < chars.len() => {
+                            word.push(chars[char_i + 1]);
+                            char_i += 1;
+                        }
+                        _ => word.push(*word_char),
+                    }
+                    char_i += 1;
+                }
+
+                if chars.get(char_i) == Some(&':') {
+                    char_i += 1;
+                    Lexeme::Field(word)
+                } else {
+                    match word.as_str() {
+                        "AND" => Lexeme::And,
+                        "OR" => Lexeme::Or,
+                        "NOT" => Lexeme::Not,
+                        _ => Lexeme::Word(word),
+                    }
+                }
+            }
+        };
+
+        lexemes.push((lexeme, position));
+    }
+
+    Ok(lexemes)
+}
+
+/// Query matching the documents that match a list of clauses, if any
+fn clauses_query(mut clauses: Vec<Clause>) -> Option<Query> {
+    if clauses.len() == 1 && clauses[0].occur != Occur::MustNot {
+        return clauses.pop().map(|clause| clause.query);
+    }
+
+    if clauses.is_empty() {
+        None
+    } else {
+        Some(Query::Boolean { clauses, minimum_should_match: 0 })
+    }
+}
+
+/// Query for the tokens of a word, which must all match
+fn word_query(tokens: Vec<Token>, max_distance: Option<u32>) -> Option<Query> {
+    let clauses = tokens.into_iter()
+        .map(|token| token.text)
+        .filter(|token| !token.is_empty())
+        .map(|token| match max_distance {
+            None => Query::Term(token),
+            Some(max_distance) => Query::Fuzzy { token, max_distance, distance_penalty: FUZZY_DISTANCE_PENALTY },
+        })
+        .map(|query| Clause::new(Occur::Must, query))
+        .collect();
+
+    clauses_query(clauses)
+}
+
+fn phrase_query(mut tokens: Vec<Token>, slop: u32) -> Option<Query> {
+    match tokens.len() {
+        0 => None,
+        1 => tokens.pop().map(|token| Query::Term(token.text)),
+        _ => Some(Query::Phrase { tokens, slop }),
+    }
+}
+
+struct Parser<'a> {
+    field_keys: &'a HashMap<String, String>,
+    analyzers: &'a BTreeMap<String, Analyzer>,
+    // Field of the query being parsed, if scoped to one
+    index_key: Option<String>,
+    lexemes: Vec<(Lexeme, usize)>,
+    lexeme_i: usize,
+    end_position: usize,
+    // Number of groups and field-scoped queries the parser is in
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&(Lexeme, usize)> {
+        self.lexemes.get(self.lexeme_i)
+    }
+
+    fn next(&mut self) -> Option<(Lexeme, usize)> {
+        let lexeme = self.lexemes.get(self.lexeme_i).cloned();
+        self.lexeme_i += 1;
+
+        lexeme
+    }
+
+    /// Enters a group or field-scoped query starting at a position, failing if nested too deep
+    fn enter(&mut self, position: usize) -> Result<(), QueryParseError> {
+        if self.depth == MAX_NESTING_DEPTH {
+            return Err(QueryParseError::new(position, &format!("Queries can be nested at most {} levels deep", MAX_NESTING_DEPTH)));
+        }
+        self.depth += 1;
+
+        Ok(())
+    }
+
+    /// Query for the tokens a text is analyzed into, by the analyzer of the current field or of every field
+    fn analyzed_query(&self, text: &str, tokens_query: impl Fn(Vec<Token>) -> Option<Query>) -> Option<Query> {
+        let index_keys: Vec<&String> = match &self.index_key {
+            Some(index_key) => vec![index_key],
+            None => self.analyzers.keys().collect(),
+        };
+
+        // Tokens -> Fields analyzing the text into them
+        let mut tokens_index_keys: BTreeMap<Vec<Token>, Vec<&String>> = BTreeMap::new();
+        for index_key in index_keys {
+            tokens_index_keys.entry(self.analyzers[index_key].analyze(text)).or_default().push(index_key);
+        }
+
+        if tokens_index_keys.len() <= 1 {
+            return tokens_index_keys.into_keys().next().and_then(tokens_query);
+        }
+
+        let clauses = tokens_index_keys.into_iter()
+            .flat_map(|(tokens, index_keys)| {
+                index_keys.into_iter().map(move |index_key| (tokens.clone(), index_key))
+            })
+            .filter_map(|(tokens, index_key)| {
+                tokens_query(tokens).map(|query| {
+                    Clause::new(Occur::Should, Query::Field { index_key: index_key.clone(), query: Box::new(query) })
+                })
+            })
+            .collect();
+
+        clauses_query(clauses)
+    }
+
+    /// Clauses separated by `OR` (or nothing), up to the end of the query or of the group
+    fn parse_disjunction(&mut self) -> Result<Vec<Clause>, QueryParseError> {
+        let mut clauses = Vec::new();
+        let mut has_operand = false;
+        let mut operator_position = None;
+
+        loop {
+            match self.peek() {
+                None | Some((Lexeme::RightParenthesis, _)) => break,
+                Some((Lexeme::Or, position)) => {
+                    if !has_operand {
+                        return Err(QueryParseError::new(*position, "Unexpected OR"));
+                    }
+                    has_operand = false;
+                    operator_position = Some(*position);
+                    self.next();
+                }
+                _ => {
+                    clauses.extend(self.parse_conjunction()?);
+                    has_operand = true;
+                    operator_position = None;
+                }
+            }
+        }
+
+        match operator_position {
+            Some(position) => Err(QueryParseError::new(position, "Expected a query after OR")),
+            None => Ok(clauses),
+        }
+    }
+
+    /// Clauses separated by `AND`, which are all required (unless prohibited)
+    fn parse_conjunction(&mut self) -> Result<Option<Clause>, QueryParseError> {
+        let mut operands = vec![self.parse_unary()?];
+        while let Some((Lexeme::And, _)) = self.peek() {
+            self.next();
+            operands.push(self.parse_unary()?);
+        }
+
+        if operands.len() == 1 {
+            return Ok(operands.pop().flatten());
+        }
+
+        let clauses: Vec<Clause> = operands.into_iter()
+            .flatten()
+            .map(|clause| match clause.occur {
+                Occur::Should => Clause::new(Occur::Must, clause.query),
+                _ => clause,
+            })
+            .collect();
+
+        Ok(clauses_query(clauses).map(|query| Clause::new(Occur::Should, query)))
+    }
+
+    /// A query, optionally marked as required or prohibited
+    fn parse_unary(&mut self) -> Result<Option<Clause>, QueryParseError> {
+        let occur = match self.peek() {
+            Some((Lexeme::Plus, _)) => Occur::Must,
+            Some((Lexeme::Minus, _)) | Some((Lexeme::Not, _)) => Occur::MustNot,
+            _ => Occur::Should,
+        };
+        if occur != Occur::Should {
+            self.next();
+        }
+
+        Ok(self.parse_primary()?.map(|query| Clause::new(occur, query)))
+    }
+
+    /// A term, phrase, field-scoped query or group, with its modifiers
+    fn parse_primary(&mut self) -> Result<Option<Query>, QueryParseError> {
+        let Some((lexeme, position)) = self.next() else {
+            return Err(QueryParseError::new(self.end_position, "Expected a query"));
+        };
+
+        let query = match lexeme {
+            Lexeme::Word(word) => {
+                let max_distance = self.parse_fuzziness()?;
+                self.analyzed_query(&word, |tokens| word_query(tokens, max_distance))
+            }
+            Lexeme::Phrase(phrase) => {
+                let slop = match self.peek() {
+                    Some((Lexeme::Tilde(slop), _)) => {
+                        let slop = slop.unwrap_or(0);
+                        self.next();
+                        slop
+                    }
+                    _ => 0,
+                };
+
+                self.analyzed_query(&phrase, |tokens| phrase_query(tokens, slop))
+            }
+            Lexeme::Field(field) => {
+                let index_key = self.field_keys.get(&field)
+                    .ok_or_else(|| QueryParseError::new(position, &format!("Unknown field {}", field)))?
+                    .clone();
+
+                // The field's query has its own modifiers, and is analyzed by the field's analyzer
+                self.enter(position)?;
+                let parent_index_key = self.index_key.replace(index_key.clone());
+                let query = self.parse_primary()?;
+                self.index_key = parent_index_key;
+                self.depth -= 1;
+
+                return Ok(query.map(|query| Query::Field { index_key, query: Box::new(query) }));
+            }
+            Lexeme::LeftParenthesis => {
+                self.enter(position)?;
+                let clauses = self.parse_disjunction()?;
+                if self.next().is_none() {
+                    return Err(QueryParseError::new(position, "Unclosed '('"));
+                }
+                self.depth -= 1;
+
+                clauses_query(clauses)
+            }
+            Lexeme::RightParenthesis => return Err(QueryParseError::new(position, "Unexpected ')'")),
+            Lexeme::And => return Err(QueryParseError::new(position, "Unexpected AND")),
+            Lexeme::Or => return Err(QueryParseError::new(position, "Unexpected OR")),
+            Lexeme::Plus | Lexeme::Minus | Lexeme::Not => {
+                return Err(QueryParseError::new(position, "Expected a query after +, - or NOT"));
+            }
+            Lexeme::Tilde(_) | Lexeme::Caret(_) => {
+                return Err(QueryParseError::new(position, "Expected a term or phrase before ~ or ^"));
+            }
+        };
+
+        match self.peek() {
+            Some((Lexeme::Tilde(_), position)) => {
+                Err(QueryParseError::new(*position, "Unexpected ~, only terms and phrases can be fuzzy or sloppy"))
+            }
+            Some((Lexeme::Caret(boost), _)) => {
+                let boost = *boost;
+                self.next();
+
+                Ok(query.map(|query| Query::Boost { query: Box::new(query), boost }))
+            }
+            _ => Ok(query),
+        }
+    }
+
+    /// Maximum edit distance of a term, if followed by `~`
+    fn parse_fuzziness(&mut self) -> Result<Option<u32>, QueryParseError> {
+        let Some((Lexeme::Tilde(max_distance), position)) = self.peek() else {
+            return Ok(None);
+        };
+
+        let max_distance = max_distance.unwrap_or(MAX_FUZZY_DISTANCE);
+        if max_distance > MAX_FUZZY_DISTANCE {
+            return Err(QueryParseError::new(*position, &format!("Fuzzy terms allow at most {} edits", MAX_FUZZY_DISTANCE)));
+        }
+        self.next();
+
+        Ok(Some(max_distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser() -> QueryParser {
+        let field_keys = HashMap::from([("title".to_string(), "title".to_string()), ("body".to_string(), "body".to_string())]);
+
+        QueryParser::new(field_keys, &HashMap::new())
+    }
+
+    fn term(token: &str) -> Query {
+        Query::Term(token.to_string())
+    }
+
+    fn boolean(clauses: Vec<(Occur, Query)>) -> Query {
+        Query::Boolean {
+            clauses: clauses.into_iter().map(|(occur, query)| Clause::new(occur, query)).collect(),
+            minimum_should_match: 0,
+        }
+    }
+
+    fn error(position: usize, message: &str) -> QueryParseError {
+        QueryParseError::new(position, message)
+    }
+
+    #[test]
+    fn parses_terms_and_phrases() {
+        assert_eq!(parser().parse("Rust"), Ok(term("rust")));
+        assert_eq!(parser().parse("title:rust"), Ok(Query::Field { index_key: "title".to_string(), query: Box::new(term("rust")) }));
+        assert_eq!(parser().parse("\"borrow checker\"~2"), Ok(Query::Phrase {
+            tokens: vec![Token::new("borrow".to_string(), 0), Token::new("checker".to_string(), 1)],
+            slop: 2,
+        }));
+        assert_eq!(parser().parse("\"rust\""), Ok(term("rust")));
+        assert_eq!(parser().parse(""), Ok(boolean(Vec::new())));
+    }
+
+    #[test]
+    fn parses_required_and_prohibited_clauses() {
+        assert_eq!(parser().parse("+rust -java NOT python compiler"), Ok(boolean(vec![
+            (Occur::Must, term("rust")),
+            (Occur::MustNot, term("java")),
+            (Occur::MustNot, term("python")),
+            (Occur::Should, term("compiler")),
+        ])));
+        assert_eq!(parser().parse("-java"), Ok(boolean(vec![(Occur::MustNot, term("java"))])));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expected = boolean(vec![
+            (Occur::Should, term("a")),
+            (Occur::Should, boolean(vec![(Occur::Must, term("b")), (Occur::Must, term("c"))])),
+        ]);
+
+        assert_eq!(parser().parse("a OR b AND c"), Ok(expected.clone()));
+        assert_eq!(parser().parse("a b AND c"), Ok(expected));
+        assert_eq!(parser().parse("a AND -b"), Ok(boolean(vec![(Occur::Must, term("a")), (Occur::MustNot, term("b"))])));
+    }
+
+    #[test]
+    fn parentheses_group_clauses() {
+        assert_eq!(parser().parse("(a OR b) AND c"), Ok(boolean(vec![
+            (Occur::Must, boolean(vec![(Occur::Should, term("a")), (Occur::Should, term("b"))])),
+            (Occur::Must, term("c")),
+        ])));
+        assert_eq!(parser().parse("body:(a b)"), Ok(Query::Field {
+            index_key: "body".to_string(),
+            query: Box::new(boolean(vec![(Occur::Should, term("a")), (Occur::Should, term("b"))])),
+        }));
+    }
+
+    #[test]
+    fn parses_boosts_and_fuzziness() {
+        assert_eq!(parser().parse("rust^2.5"), Ok(Query::Boost { query: Box::new(term("rust")), boost: 2.5 }));
+        assert_eq!(parser().parse("rust~1"), Ok(Query::Fuzzy { token: "rust".to_string(), max_distance: 1, distance_penalty: 0.5 }));
+        assert_eq!(parser().parse("rust~^2"), Ok(Query::Boost {
+            query: Box::new(Query::Fuzzy { token: "rust".to_string(), max_distance: 2, distance_penalty: 0.5 }),
+            boost: 2.0,
+        }));
+    }
+
+    #[test]
+    fn errors_are_positioned() {
+        assert_eq!(parser().parse("rust \"borrow checker"), Err(error(5, "Unterminated phrase")));
+        assert_eq!(parser().parse("a (b (c)"), Err(error(2, "Unclosed '('")));
+        assert_eq!(parser().parse("a b)"), Err(error(3, "Unexpected ')'")));
+        assert_eq!(parser().parse("rust OR"), Err(error(5, "Expected a query after OR")));
+        assert_eq!(parser().parse("OR rust"), Err(error(0, "Unexpected OR")));
+        assert_eq!(parser().parse("rust AND"), Err(error(8, "Expected a query")));
+        assert_eq!(parser().parse("rust + -java"), Err(error(7, "Expected a query after +, - or NOT")));
+        assert_eq!(parser().parse("rust^x"), Err(error(5, "Expected a positive number after '^'")));
+        assert_eq!(parser().parse("rust^-1"), Err(error(5, "Expected a positive number after '^'")));
+        assert_eq!(parser().parse("rust~3"), Err(error(4, "Fuzzy terms allow at most 2 edits")));
+        assert_eq!(parser().parse("author:rust"), Err(error(0, "Unknown field author")));
+    }
+
+    #[test]
+    fn nesting_depth_is_bounded() {
+        let query_string = format!("{}rust{}", "(".repeat(MAX_NESTING_DEPTH), ")".repeat(MAX_NESTING_DEPTH));
+        assert_eq!(parser().parse(&query_string), Ok(term("rust")));
+
+        let message = format!("Queries can be nested at most {} levels deep", MAX_NESTING_DEPTH);
+        let query_string = format!("a {}rust", "(".repeat(100_000));
+        assert_eq!(parser().parse(&query_string), Err(error(2 + MAX_NESTING_DEPTH, &message)));
+
+        let query_string = format!("{}rust", "title:".repeat(100_000));
+        assert_eq!(parser().parse(&query_string), Err(error(6 * MAX_NESTING_DEPTH, &message)));
+    }
+}
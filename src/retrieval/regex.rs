@@ -0,0 +1,109 @@
+// Regular expression term queries. The pattern is compiled into a DFA which, like the Levenshtein and wildcard
+// automata, is run over the postings FSTs, so only the index tokens matching the whole pattern are visited. Both the
+// DFA and the number of matched tokens are bounded, since some patterns (e.g. `.*a.{20}`) blow up on determinization,
+// and others (e.g. `.*`) match the whole vocabulary
+
+use anyhow::{anyhow, Context, Result};
+use regex_automata::dense::{Builder, DenseDFA};
+
+// With 16-bit state ids, the determinization itself fails once the DFA has too many states
+type RegexAutomaton = DenseDFA<Vec<u16>, u16>;
+
+#[derive(Debug, Clone)]
+pub struct RegexQuery {
+    pattern: String,
+    automaton: RegexAutomaton,
+    max_expansions: usize,
+}
+
+impl RegexQuery {
+    /// Compiles a pattern matched against the index tokens, failing if its automaton would take more than
+    /// `max_automaton_size` bytes. At most `max_expansions` matching tokens are scored
+    pub fn new(pattern: &str, max_automaton_size: usize, max_expansions: usize) -> Result<Self> {
+        let automaton = Builder::new()
+            .anchored(true)
+            .premultiply(false)
+            .build_with_size::<u16>(pattern)
+            .with_context(|| format!("Error compiling the regex {}:", pattern))?;
+
+        if automaton.memory_usage() > max_automaton_size {
+            return Err(anyhow!(format!(
+                "The automaton of the regex {} takes {} bytes, over the limit of {}",
+                pattern,
+                automaton.memory_usage(),
+                max_automaton_size
+            )));
+        }
+
+        Ok(Self { pattern: pattern.to_string(), automaton, max_expansions })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn automaton(&self) -> &RegexAutomaton {
+        &self.automaton
+    }
+
+    pub fn max_expansions(&self) -> usize {
+        self.max_expansions
+    }
+}
+
+// Queries compiled from the same pattern match the same tokens
+impl PartialEq for RegexQuery {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.max_expansions == other.max_expansions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retrieval::{Clause, Occur, Query};
+    use crate::test_index::{bm25f, doc, sorted_docids, TestIndex};
+
+    #[test]
+    fn automaton_size_is_bounded() {
+        assert!(RegexQuery::new("rust.*", 1024 * 1024, 10).is_ok());
+
+        let error = RegexQuery::new("rust.*", 16, 10).unwrap_err();
+        assert!(error.to_string().contains("over the limit of 16"), "{}", error);
+
+        // Blows up on determinization, past what 16-bit state ids can hold
+        assert!(RegexQuery::new(".*a.{20}", usize::MAX, 10).is_err());
+    }
+
+    #[test]
+    fn regex_queries_are_truncated_to_max_expansions() {
+        let index = TestIndex::new("regex_query");
+        let docs = [("d1", "", "rust"), ("d2", "", "rusty"), ("d3", "", "rustacean"), ("d4", "", "rusted java"), ("d5", "rustic", "python")];
+        index.indexer(&["title", "body"]).index(docs.iter().map(|(docid, title, body)| doc(docid, &[("title", title), ("body", body)]))).unwrap();
+
+        let retriever = index.retriever(&["title", "body"]);
+        let bm25f = bm25f(&[("title", 0.75, 2.0), ("body", 0.75, 1.0)]);
+        let regex = |pattern: &str, max_expansions: usize| Query::Regex(Box::new(RegexQuery::new(pattern, 1024 * 1024, max_expansions).unwrap()));
+        let docids = |query: &Query| sorted_docids(retriever.retrieval_query(query, &bm25f).unwrap());
+
+        // The first tokens in lexicographical order are "rust" and "rustacean"
+        assert_eq!(docids(&regex("rust.*", 2)), ["d1", "d3"]);
+        assert_eq!(docids(&regex("rust.*", 10)), ["d1", "d2", "d3", "d4", "d5"]);
+        assert_eq!(docids(&Query::Field { index_key: "body".to_string(), query: Box::new(regex("rust.*", 10)) }), ["d1", "d2", "d3", "d4"]);
+
+        let boolean_query = Query::Boolean {
+            clauses: vec![Clause::new(Occur::Must, regex("rust.*", 10)), Clause::new(Occur::MustNot, Query::Term("java".to_string()))],
+            minimum_should_match: 0,
+        };
+        assert_eq!(docids(&boolean_query), ["d1", "d2", "d3", "d5"]);
+
+        let explanation = retriever.explain(&regex("rust.*", 2), "d3", &bm25f).unwrap();
+        let boolean_explanation = retriever.explain(&boolean_query, "d4", &bm25f).unwrap();
+        let results = retriever.retrieval_query(&regex("rust.*", 2), &bm25f).unwrap();
+
+        assert!(explanation.matched);
+        assert!(explanation.description.contains("\"rustacean\""), "{}", explanation.description);
+        assert_eq!(explanation.value, results.iter().find(|(docid, _)| docid == "d3").unwrap().1);
+        assert!(!boolean_explanation.matched);
+    }
+}
@@ -0,0 +1,413 @@
+// Read-only view of a single index segment, with its mmapped postings, lengths, docids, stored fields and stats.
+// Deleted documents are filtered out of the postings, and excluded from the segment's statistics
+//
+// Token statistics are read from the ones stored at index time (the document and total frequencies of every field in
+// the postings headers, and the number of documents containing the token in any field in their own FST) instead of
+// decoding the postings. They include the deleted documents, so segments with deletions count them from the postings
+// instead, until they are merged and their tombstones dropped
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io;
+use anyhow::{anyhow, Result};
+use fst::{Automaton, IntoStreamer, Map, Streamer};
+use memmap::Mmap;
+use crate::indexing;
+use crate::index_directory::SegmentDirectory;
+use crate::indexing::deletions::DeletedDocs;
+use crate::indexing::docids::{DocId, DOCID_RECORD_LENGTH};
+use crate::indexing::postings_codec::PostingsCodec;
+use crate::indexing::postings_cursor::PostingsCursor;
+use crate::indexing::stored_fields::{StoredDoc, StoredFields};
+use crate::retrieval::similarity::{TokenFieldStats, TokenStats};
+
+#[derive(Debug)]
+pub struct Segment {
+    lengths_maps: HashMap<String, Map<Mmap>>,
+    avg_lengths_map: Map<Mmap>,
+    postings_maps: HashMap<String, Map<Mmap>>,
+    // Missing for segments written before the reversed tokens were indexed
+    reversed_postings_maps: HashMap<String, Map<Mmap>>,
+
+    postings_data_files: HashMap<String, Mmap>,
+    docids_map: Map<Mmap>,
+    docids_data_file: Mmap,
+    // Missing for segments written before the document frequencies were stored
+    doc_frequencies_map: Option<Map<Mmap>>,
+    // Missing for segments written before the fields were stored
+    stored_fields: Option<StoredFields>,
+
+    pub index_stats: indexing::stats::IndexStats,
+    deleted_docs: DeletedDocs,
+    postings_codec: PostingsCodec,
+}
+
+impl Segment {
+    pub fn open(index_keys: &[String], segment_directory: &SegmentDirectory) -> Result<Self> {
+        let mut lengths_maps = HashMap::new();
+        let mut postings_maps = HashMap::new();
+        let mut reversed_postings_maps = HashMap::new();
+        let mut postings_data_files = HashMap::new();
+
+        let avg_lengths_map = unsafe {
+            Map::new(Mmap::map(&File::open(segment_directory.avg_lengths_index_path())?)?)?
+        };
+
+        let docids_map = unsafe {
+            Map::new(Mmap::map(&File::open(segment_directory.docids_index_path())?)?)?
+        };
+
+        let docids_data_file = unsafe {
+            Mmap::map(&File::open(segment_directory.docids_data_path())?)?
+        };
+
+        let doc_frequencies_index_path = segment_directory.doc_frequencies_index_path();
+        let doc_frequencies_map = match doc_frequencies_index_path.exists() {
+            true => Some(unsafe { Map::new(Mmap::map(&File::open(doc_frequencies_index_path)?)?)? }),
+            false => None,
+        };
+
+        let stored_fields = StoredFields::open(segment_directory)?;
+
+        for index_key in index_keys {
+            let mmap = unsafe {
+                Mmap::map(&File::open(segment_directory.lengths_index_path(index_key))?)?
+            };
+            lengths_maps.insert(index_key.clone(), Map::new(mmap)?);
+
+            // Fields without any token in this segment have an empty postings data file, which can't be mmapped
+            let postings_data_file = File::open(segment_directory.postings_data_path(index_key))?;
+            if postings_data_file.metadata()?.len() == 0 {
+                continue;
+            }
+
+            let mmap = unsafe {
+                Mmap::map(&File::open(segment_directory.postings_index_path(index_key))?)?
+            };
+            postings_maps.insert(index_key.clone(), Map::new(mmap)?);
+
+            let reversed_postings_index_path = segment_directory.reversed_postings_index_path(index_key);
+            if reversed_postings_index_path.exists() {
+                let mmap = unsafe {
+                    Mmap::map(&File::open(reversed_postings_index_path)?)?
+                };
+                reversed_postings_maps.insert(index_key.clone(), Map::new(mmap)?);
+            }
+
+            let mmap = unsafe {
+                Mmap::map(&postings_data_file)?
+            };
+            postings_data_files.insert(index_key.clone(), mmap);
+        }
+
+        let index_stats: indexing::stats::IndexStats =
+            serde_json::from_reader(io::BufReader::new(File::open(segment_directory.stats_path())?))?;
+        let deleted_docs = DeletedDocs::load(segment_directory)?;
+        let postings_codec = PostingsCodec::from_version(index_stats.postings_codec_version)?;
+
+        Ok(Self {
+            lengths_maps,
+            avg_lengths_map,
+            postings_maps,
+            reversed_postings_maps,
+            postings_data_files,
+            docids_map,
+            docids_data_file,
+            doc_frequencies_map,
+            stored_fields,
+            index_stats,
+            deleted_docs,
+            postings_codec,
+        })
+    }
+
+    /// Number of documents of the segment, including the deleted ones
+    pub fn n_docs(&self) -> usize {
+        self.index_stats.n_docs
+    }
+
+    /// Number of documents of the segment which haven't been deleted
+    pub fn n_live_docs(&self) -> usize {
+        self.index_stats.n_docs - self.deleted_docs.len()
+    }
+
+    /// External docid of an internal doc id
+    pub fn get_docid(&self, doc_id: DocId) -> String {
+        let start = (doc_id as usize) * DOCID_RECORD_LENGTH;
+        let record = &self.docids_data_file[start..start + DOCID_RECORD_LENGTH];
+
+        String::from_utf8_lossy(record).trim_end_matches('\0').to_string()
+    }
+
+    /// Internal doc id of an external docid, if the segment contains it (even if deleted)
+    pub fn get_doc_id(&self, docid: &str) -> Option<DocId> {
+        self.docids_map.get(docid).map(|doc_id| doc_id as DocId)
+    }
+
+    /// Original text of the stored fields of a document, which is empty if the segment doesn't store any
+    pub fn get_stored_doc(&self, doc_id: DocId) -> Result<StoredDoc> {
+        match &self.stored_fields {
+            Some(stored_fields) => stored_fields.get_doc(doc_id),
+            None => Ok(StoredDoc::new()),
+        }
+    }
+
+    /// The segment's stored fields, if it has any
+    pub fn get_stored_fields(&self) -> Option<&StoredFields> {
+        self.stored_fields.as_ref()
+    }
+
+    /// Total length and number of live documents containing a field in this segment
+    pub fn get_field_length_stats(&self, index_key: &str) -> (f64, u64) {
+        let mut n_docs = *self.index_stats.field_n_docs.get(index_key).unwrap_or(&0);
+        if n_docs == 0 {
+            return (0.0, 0);
+        }
+
+        // The average lengths are stored as the raw bits of the f64
+        let avg_length = f64::from_bits(self.avg_lengths_map.get(index_key).unwrap_or(0));
+        let mut total_length = avg_length * (n_docs as f64);
+
+        for doc_id in self.deleted_docs.iter() {
+            if let Some(length) = self.get_doc_id_length(*doc_id, index_key) {
+                total_length -= length as f64;
+                n_docs -= 1;
+            }
+        }
+
+        (total_length, n_docs)
+    }
+
+    /// Statistics of a token over the live documents of the segment, only counting a field if one is given
+    pub fn get_token_stats(&self, token: &str, index_key: Option<&str>) -> Result<TokenStats> {
+        let mut field_cursors = self.get_postings_cursors(token)?;
+        field_cursors.retain(|(cursor_index_key, _)| index_key.is_none_or(|index_key| index_key == *cursor_index_key));
+
+        // The stored statistics can only be used if they are the live ones
+        let has_stored_stats = self.doc_frequencies_map.is_some()
+            && self.deleted_docs.len() == 0
+            && field_cursors.iter().all(|(_, cursor)| cursor.collection_frequency().is_some());
+        if !has_stored_stats {
+            return self.count_token_stats(field_cursors);
+        }
+
+        let mut token_stats = TokenStats::default();
+        for (index_key, cursor) in &field_cursors {
+            token_stats.field_stats.insert(index_key.to_string(), TokenFieldStats {
+                doc_frequency: cursor.n_postings() as u64,
+                collection_frequency: cursor.collection_frequency().unwrap_or(0) as f64,
+            });
+        }
+
+        // With a single field, its document frequency is the one over any field
+        token_stats.doc_frequency = match index_key {
+            Some(_) => token_stats.field_stats.values().map(|token_field_stats| token_field_stats.doc_frequency).sum(),
+            None => self.doc_frequencies_map.as_ref().and_then(|map| map.get(token)).unwrap_or(0),
+        };
+
+        Ok(token_stats)
+    }
+
+    /// Counts the statistics of a token from its postings in every field, skipping the deleted documents
+    fn count_token_stats(&self, mut field_cursors: Vec<(&str, PostingsCursor<'_>)>) -> Result<TokenStats> {
+        let mut field_postings = field_cursors.iter_mut()
+            .map(|(_, cursor)| cursor.next())
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut token_stats = TokenStats::default();
+        while let Some(doc_id) = field_postings.iter().flatten().map(|(doc_id, _)| *doc_id).min() {
+            let mut field_tfs = Vec::new();
+            for ((index_key, cursor), posting) in field_cursors.iter_mut().zip(field_postings.iter_mut()) {
+                if let Some((posting_doc_id, tf)) = *posting {
+                    if posting_doc_id == doc_id {
+                        field_tfs.push((*index_key, tf as f64));
+                        *posting = cursor.next()?;
+                    }
+                }
+            }
+
+            if !self.is_deleted(doc_id) {
+                token_stats.add_doc(&field_tfs);
+            }
+        }
+
+        Ok(token_stats)
+    }
+
+    /// Returns a postings cursor for query_token in every field containing it. Cursors include deleted documents,
+    /// which must be skipped with `is_deleted`
+    pub fn get_postings_cursors(&self, query_token: &str) -> Result<Vec<(&str, PostingsCursor<'_>)>> {
+        let mut postings_cursors = Vec::new();
+
+        for (index_key, postings_fst) in &self.postings_maps {
+            if let Some(start_pos) = postings_fst.get(query_token) {
+                postings_cursors.push((index_key.as_str(), self.read_postings(index_key, start_pos)?));
+            }
+        }
+
+        Ok(postings_cursors)
+    }
+
+    /// Returns a postings cursor for token in a field, if the field contains it
+    pub fn get_field_postings_cursor(&self, index_key: &str, token: &str) -> Result<Option<PostingsCursor<'_>>> {
+        match self.postings_maps.get(index_key).and_then(|postings_fst| postings_fst.get(token)) {
+            Some(start_pos) => Ok(Some(self.read_postings(index_key, start_pos)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The first `max_tokens` tokens (in lexicographical order) of any field matched by the automaton
+    pub fn get_matching_tokens<A: Automaton>(&self, automaton: &A, max_tokens: usize) -> Result<BTreeSet<String>> {
+        Self::search_tokens(self.postings_maps.values(), automaton, max_tokens)
+    }
+
+    /// Whether every field has an FST of its reversed tokens
+    pub fn has_reversed_tokens(&self) -> bool {
+        self.postings_maps.keys().all(|index_key| self.reversed_postings_maps.contains_key(index_key))
+    }
+
+    /// Like `get_matching_tokens`, but the automaton is run over the reversed tokens (and their order)
+    pub fn get_matching_reversed_tokens<A: Automaton>(&self, automaton: &A, max_tokens: usize) -> Result<BTreeSet<String>> {
+        let reversed_tokens = Self::search_tokens(self.reversed_postings_maps.values(), automaton, max_tokens)?;
+
+        Ok(reversed_tokens.into_iter()
+            .map(|reversed_token| reversed_token.chars().rev().collect())
+            .collect())
+    }
+
+    fn search_tokens<'a, A: Automaton>(postings_fsts: impl Iterator<Item = &'a Map<Mmap>>,
+                                       automaton: &A,
+                                       max_tokens: usize) -> Result<BTreeSet<String>> {
+        let mut matching_tokens = BTreeSet::new();
+
+        for postings_fst in postings_fsts {
+            // Every field's tokens are streamed in order, so the first ones of the union are within the first ones of
+            // each field
+            let mut stream = postings_fst.search(automaton).into_stream();
+            let mut n_field_tokens = 0;
+            while let Some((token, _)) = stream.next() {
+                if n_field_tokens == max_tokens {
+                    break;
+                }
+
+                matching_tokens.insert(String::from_utf8(token.to_vec())?);
+                n_field_tokens += 1;
+            }
+        }
+
+        Ok(matching_tokens.into_iter().take(max_tokens).collect())
+    }
+
+    /// Opens a cursor over the postings stored at a position of a field's postings data file
+    pub fn read_postings(&self, index_key: &str, start_pos: u64) -> Result<PostingsCursor<'_>> {
+        let postings_file = self.postings_data_files.get(index_key).unwrap();
+
+        self.postings_codec.cursor(&postings_file[start_pos as usize..], self.has_positions(index_key))
+    }
+
+    /// Whether the field's postings store the token positions in this segment
+    pub fn has_positions(&self, index_key: &str) -> bool {
+        self.index_stats.positional_fields.contains(index_key)
+    }
+
+    /// The FST mapping a field's tokens to their postings position, if the field has any token in this segment
+    pub fn get_postings_map(&self, index_key: &str) -> Option<&Map<Mmap>> {
+        self.postings_maps.get(index_key)
+    }
+
+    /// The FST mapping doc ids (as big-endian bytes) to their length in a field
+    pub fn get_lengths_map(&self, index_key: &str) -> &Map<Mmap> {
+        self.lengths_maps.get(index_key).unwrap()
+    }
+
+    pub fn is_deleted(&self, doc_id: DocId) -> bool {
+        self.deleted_docs.contains(doc_id)
+    }
+
+    /// Length of a document's field, failing if the document doesn't have it
+    pub fn get_length(&self, doc_id: DocId, index_key: &str) -> Result<u64> {
+        self.get_doc_id_length(doc_id, index_key)
+            .ok_or_else(|| anyhow!(format!("Couldn't find the length for docid {}", self.get_docid(doc_id))))
+    }
+
+    fn get_doc_id_length(&self, doc_id: DocId, index_key: &str) -> Option<u64> {
+        let lengths_fst = self.lengths_maps.get(index_key).unwrap();
+
+        lengths_fst.get(doc_id.to_be_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index_directory::Manifest;
+    use crate::test_index::{assert_same_results, bm25f, doc, TestIndex};
+
+    const INDEX_KEYS: [&str; 2] = ["title", "body"];
+
+    const DOCS: [(&str, &str, &str); 4] = [
+        ("d1", "rust", "rust search engine"),
+        ("d2", "search", "search search rust index"),
+        ("d3", "python search", "python"),
+        ("d4", "java", "java index"),
+    ];
+
+    fn index_docs(index: &TestIndex, docs: &[(&str, &str, &str)]) {
+        let docs = docs.iter().map(|(docid, title, body)| doc(docid, &[("title", title), ("body", body)]));
+        index.indexer(&INDEX_KEYS).index(docs).unwrap();
+    }
+
+    fn field_token_stats(token_stats: &TokenStats, index_key: &str) -> (u64, f64) {
+        let token_field_stats = token_stats.field(index_key);
+        (token_field_stats.doc_frequency, token_field_stats.collection_frequency)
+    }
+
+    #[test]
+    fn statistics_are_per_segment_and_combined_across_segments() {
+        let index = TestIndex::new("segment_stats");
+        index_docs(&index, &DOCS[..1]);
+        index_docs(&index, &DOCS[1..3]);
+        index_docs(&index, &DOCS[3..]);
+        let single_segment_index = TestIndex::new("segment_stats_single");
+        index_docs(&single_segment_index, &DOCS);
+
+        let index_keys: Vec<String> = INDEX_KEYS.iter().map(|index_key| index_key.to_string()).collect();
+        let segments: Vec<Segment> = Manifest::load(&index.directory).unwrap().segments.iter()
+            .map(|segment_info| Segment::open(&index_keys, &index.directory.segment(&segment_info.name)).unwrap())
+            .collect();
+
+        // Every segment only knows about its own documents
+        let n_docs: Vec<usize> = segments.iter().map(|segment| segment.n_docs()).collect();
+        let title_length_stats: Vec<(f64, u64)> = segments.iter().map(|segment| segment.get_field_length_stats("title")).collect();
+        let body_length_stats: Vec<(f64, u64)> = segments.iter().map(|segment| segment.get_field_length_stats("body")).collect();
+        let search_stats: Vec<TokenStats> = segments.iter().map(|segment| segment.get_token_stats("search", None).unwrap()).collect();
+        assert_eq!(n_docs, [1, 2, 1]);
+        assert_eq!(title_length_stats, [(1.0, 1), (3.0, 2), (1.0, 1)]);
+        assert_eq!(body_length_stats, [(3.0, 1), (5.0, 2), (2.0, 1)]);
+        let search_doc_frequencies: Vec<u64> = search_stats.iter().map(|token_stats| token_stats.doc_frequency).collect();
+        assert_eq!(search_doc_frequencies, [1, 2, 0]);
+        assert_eq!(field_token_stats(&search_stats[1], "title"), (2, 2.0));
+        assert_eq!(field_token_stats(&search_stats[1], "body"), (1, 2.0));
+
+        // The retriever adds them up into the statistics of the whole collection
+        let retriever = index.retriever(&INDEX_KEYS);
+        let collection_stats = &retriever.collection_stats;
+        let retriever_search_stats = retriever.get_token_stats("search", None).unwrap();
+        let retriever_search_title_stats = retriever.get_token_stats("search", Some("title")).unwrap();
+        assert_eq!(collection_stats.n_docs, 4);
+        assert_eq!(collection_stats.field("title").total_length, 5.0);
+        assert_eq!(collection_stats.field("body").total_length, 10.0);
+        assert_eq!(retriever_search_stats.doc_frequency, 3);
+        assert_eq!(field_token_stats(&retriever_search_stats, "title"), (2, 2.0));
+        assert_eq!(field_token_stats(&retriever_search_stats, "body"), (2, 3.0));
+        assert_eq!(retriever_search_title_stats.doc_frequency, 2);
+
+        // So that the scores are those of a single segment with every document
+        let bm25f = bm25f(&[("title", 0.75, 2.0), ("body", 0.5, 1.0)]);
+        let query_tokens = vec!["rust".to_string(), "search".to_string()];
+        let single_segment_retriever = single_segment_index.retriever(&INDEX_KEYS);
+        let expected_results = single_segment_retriever.retrieval_multiple_tokens(&query_tokens, &bm25f).unwrap();
+        assert_same_results(&retriever.retrieval_multiple_tokens(&query_tokens, &bm25f).unwrap(), &expected_results);
+        assert_same_results(&retriever.retrieval_top_k(&query_tokens, 2, &bm25f).unwrap(), &expected_results[..2]);
+    }
+}
@@ -0,0 +1,798 @@
+// Scoring models used by the retriever. A model scores a document for a query token (or a phrase, or a union of
+// tokens) given the frequency of the token and the length of every field of the document containing it, along with
+// statistics of the token and of every field over the live documents of all segments. The scores of the query tokens
+// are then summed, so every model is free to combine the fields of a document differently: BM25F combines them before
+// saturating the frequency, while the other models score each field on its own and sum their weighted scores
+
+use std::collections::HashMap;
+use anyhow::Result;
+use crate::indexing::docids::DocId;
+use crate::retrieval::explanation::Explanation;
+use crate::retrieval::segment::Segment;
+
+/// Statistics of a field over the live documents of all segments
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FieldStats {
+    pub n_docs: u64,
+    pub total_length: f64,
+}
+
+impl FieldStats {
+    pub fn avg_length(&self) -> f64 {
+        self.total_length / (self.n_docs as f64)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CollectionStats {
+    pub n_docs: usize,
+    pub field_stats: HashMap<String, FieldStats>,
+}
+
+impl CollectionStats {
+    /// Statistics of a field, empty if no live document contains it
+    pub fn field(&self, index_key: &str) -> FieldStats {
+        self.field_stats.get(index_key).copied().unwrap_or_default()
+    }
+}
+
+/// Statistics of a token in a field over the live documents of all segments
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokenFieldStats {
+    pub doc_frequency: u64,
+    // Total frequency of the token in the field
+    pub collection_frequency: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TokenStats {
+    // Number of live documents containing the token in any field
+    pub doc_frequency: u64,
+    pub field_stats: HashMap<String, TokenFieldStats>,
+}
+
+impl TokenStats {
+    /// Counts a live document, given the frequency of the token in every field containing it
+    pub fn add_doc(&mut self, field_tfs: &[(&str, f64)]) {
+        self.doc_frequency += 1;
+
+        for (index_key, tf) in field_tfs {
+            let token_field_stats = self.field_stats.entry(index_key.to_string()).or_default();
+            token_field_stats.doc_frequency += 1;
+            token_field_stats.collection_frequency += tf;
+        }
+    }
+
+    /// Adds the statistics of the token over other documents (e.g. another segment's)
+    pub fn add(&mut self, token_stats: &TokenStats) {
+        self.doc_frequency += token_stats.doc_frequency;
+
+        for (index_key, token_field_stats) in &token_stats.field_stats {
+            let field_stats = self.field_stats.entry(index_key.clone()).or_default();
+            field_stats.doc_frequency += token_field_stats.doc_frequency;
+            field_stats.collection_frequency += token_field_stats.collection_frequency;
+        }
+    }
+
+    /// Statistics of the token in a field, empty if no live document contains it there
+    pub fn field(&self, index_key: &str) -> TokenFieldStats {
+        self.field_stats.get(index_key).copied().unwrap_or_default()
+    }
+}
+
+/// A field of a document containing the token
+#[derive(Debug, Clone, Copy)]
+pub struct FieldMatch<'a> {
+    pub index_key: &'a str,
+    pub tf: f64,
+    pub length: f64,
+}
+
+/// Looks up the lengths of the fields of a document containing the token
+pub fn get_field_matches<'a>(segment: &Segment, doc_id: DocId, field_tfs: &[(&'a str, f64)]) -> Result<Vec<FieldMatch<'a>>> {
+    field_tfs.iter()
+        .map(|(index_key, tf)| Ok(FieldMatch { index_key, tf: *tf, length: segment.get_length(doc_id, index_key)? as f64 }))
+        .collect()
+}
+
+pub trait Similarity: Sync {
+    /// Score of a single field of a document containing the token. It must not decrease with the frequency of the
+    /// token nor increase with the length of the field, so that the scores of whole postings blocks can be bounded
+    fn field_score(&self,
+                   index_key: &str,
+                   tf: f64,
+                   length: f64,
+                   token_stats: &TokenStats,
+                   collection_stats: &CollectionStats) -> f64;
+
+    /// Score of a document containing the token, which must not be higher than the sum of its field scores. Defaults to
+    /// that sum
+    fn score(&self, field_matches: &[FieldMatch], token_stats: &TokenStats, collection_stats: &CollectionStats) -> f64 {
+        field_matches.iter()
+            .map(|field_match| {
+                self.field_score(field_match.index_key, field_match.tf, field_match.length, token_stats, collection_stats)
+            })
+            .sum()
+    }
+
+    /// Breakdown of `score`. Defaults to the field scores, with the frequency of the token and the length of each field
+    fn explain(&self, field_matches: &[FieldMatch], token_stats: &TokenStats, collection_stats: &CollectionStats) -> Explanation {
+        let field_explanations = field_matches.iter()
+            .map(|field_match| {
+                let field_score = self.field_score(field_match.index_key, field_match.tf, field_match.length, token_stats, collection_stats);
+
+                Explanation::new(field_score, format!("score of field {}", field_match.index_key), vec![
+                    Explanation::leaf(field_match.tf, "tf"),
+                    Explanation::leaf(field_match.length, "field length"),
+                ])
+            })
+            .collect();
+
+        Explanation::new(self.score(field_matches, token_stats, collection_stats), "sum of the field scores", field_explanations)
+    }
+}
+
+fn get_field_param(field_params: &HashMap<String, f64>, index_key: &str, param_name: &str) -> f64 {
+    *field_params.get(index_key)
+        .unwrap_or_else(|| panic!("Field {} not found for {}, cannot perform retrieval!", param_name, index_key))
+}
+
+#[derive(Debug, Clone)]
+enum Bm25fMode {
+    // The weighted frequencies of the token in the fields share the weighted length of the document (over the fields
+    // containing the token), every field is saturated with its own k1, and the idf is the ratio of documents
+    // containing the token in any field
+    Original { field_k1_params: HashMap<String, f64> },
+    // Robertson & Zaragoza's formulation: the frequency of the token in every field is normalized by the field's length,
+    // the weighted sum of the normalized frequencies is saturated once, with a single k1, and the idf is the
+    // Robertson-Sparck Jones weight, shifted like in Lucene so that it is never negative
+    Standard { k1: f64 },
+}
+
+/// BM25F, either in the original formulation of this crate (`new`) or in the standard one (`standard`)
+#[derive(Debug, Clone)]
+pub struct Bm25f {
+    mode: Bm25fMode,
+    field_b_params: HashMap<String, f64>,
+    field_weights: HashMap<String, f64>,
+}
+
+impl Bm25f {
+    pub fn new(field_k1_params: HashMap<String, f64>,
+               field_b_params: HashMap<String, f64>,
+               field_weights: HashMap<String, f64>) -> Self {
+        Self { mode: Bm25fMode::Original { field_k1_params }, field_b_params, field_weights }
+    }
+
+    pub fn standard(k1: f64, field_b_params: HashMap<String, f64>, field_weights: HashMap<String, f64>) -> Self {
+        Self { mode: Bm25fMode::Standard { k1 }, field_b_params, field_weights }
+    }
+
+    /// Saturation parameter of a field, the same for all of them in the standard formulation
+    pub fn k1(&self, index_key: &str) -> f64 {
+        match &self.mode {
+            Bm25fMode::Original { field_k1_params } => get_field_param(field_k1_params, index_key, "k1"),
+            Bm25fMode::Standard { k1 } => *k1,
+        }
+    }
+
+    pub fn b(&self, index_key: &str) -> f64 {
+        get_field_param(&self.field_b_params, index_key, "b")
+    }
+
+    pub fn weight(&self, index_key: &str) -> f64 {
+        get_field_param(&self.field_weights, index_key, "weight")
+    }
+
+    /// Inverse document frequency of the token, which is infinite in the original formulation if no document
+    /// contains it
+    pub fn idf(&self, token_stats: &TokenStats, collection_stats: &CollectionStats) -> f64 {
+        let n_docs = collection_stats.n_docs as f64;
+        let doc_frequency = token_stats.doc_frequency as f64;
+
+        match self.mode {
+            Bm25fMode::Original { .. } => n_docs / doc_frequency,
+            Bm25fMode::Standard { .. } => (1.0 + (n_docs - doc_frequency + 0.5) / (doc_frequency + 0.5)).ln(),
+        }
+    }
+
+    /// Weighted frequency of the token in a field, normalized by the field's length (standard formulation)
+    fn normalized_tf(&self, index_key: &str, tf: f64, length: f64, collection_stats: &CollectionStats) -> f64 {
+        let b = self.b(index_key);
+        let length_norm = (1.0 - b) + b * (length / collection_stats.field(index_key).avg_length());
+
+        self.weight(index_key) * tf / length_norm
+    }
+
+    fn saturate(k1: f64, normalized_tf: f64) -> f64 {
+        normalized_tf / (k1 + normalized_tf)
+    }
+
+    /// Score (without the idf) of a field, given the weighted length of the document
+    fn weighted_field_score(&self, index_key: &str, tf: f64, weighted_doc_len: f64, collection_stats: &CollectionStats) -> f64 {
+        let field_weight = self.weight(index_key);
+        let weighted_avg_length = field_weight * collection_stats.field(index_key).avg_length();
+
+        let new_tf = field_weight * tf;
+        let k1 = self.k1(index_key);
+        let b = self.b(index_key);
+
+        (new_tf * (k1 + 1.0)) / (k1 * ((1.0 - b) + b * (weighted_doc_len / weighted_avg_length) + new_tf))
+    }
+}
+
+impl Similarity for Bm25f {
+    // In the original formulation, the weighted length of the document is at least the one of the field. In the
+    // standard one, the saturation of a sum is at most the sum of the saturations
+    fn field_score(&self,
+                   index_key: &str,
+                   tf: f64,
+                   length: f64,
+                   token_stats: &TokenStats,
+                   collection_stats: &CollectionStats) -> f64 {
+        let score = match self.mode {
+            Bm25fMode::Original { .. } => {
+                let weighted_doc_len = self.weight(index_key) * length;
+                self.weighted_field_score(index_key, tf, weighted_doc_len, collection_stats)
+            }
+            Bm25fMode::Standard { k1 } => Self::saturate(k1, self.normalized_tf(index_key, tf, length, collection_stats)),
+        };
+
+        self.idf(token_stats, collection_stats) * score
+    }
+
+    fn score(&self, field_matches: &[FieldMatch], token_stats: &TokenStats, collection_stats: &CollectionStats) -> f64 {
+        let score = match self.mode {
+            Bm25fMode::Original { .. } => {
+                let weighted_doc_len: f64 = field_matches.iter()
+                    .map(|field_match| self.weight(field_match.index_key) * field_match.length)
+                    .sum();
+
+                let mut score = 0.0;
+                for field_match in field_matches {
+                    score += self.weighted_field_score(field_match.index_key, field_match.tf, weighted_doc_len, collection_stats);
+                }
+
+                score
+            }
+            Bm25fMode::Standard { k1 } => {
+                let normalized_tf: f64 = field_matches.iter()
+                    .map(|field_match| self.normalized_tf(field_match.index_key, field_match.tf, field_match.length, collection_stats))
+                    .sum();
+
+                Self::saturate(k1, normalized_tf)
+            }
+        };
+
+        self.idf(token_stats, collection_stats) * score
+    }
+
+    fn explain(&self, field_matches: &[FieldMatch], token_stats: &TokenStats, collection_stats: &CollectionStats) -> Explanation {
+        let score = self.score(field_matches, token_stats, collection_stats);
+        let idf = self.explain_idf(token_stats, collection_stats);
+
+        match self.mode {
+            Bm25fMode::Original { .. } => {
+                let weighted_doc_len: f64 = field_matches.iter()
+                    .map(|field_match| self.weight(field_match.index_key) * field_match.length)
+                    .sum();
+
+                let field_explanations = field_matches.iter()
+                    .map(|field_match| {
+                        let field_score = self.weighted_field_score(field_match.index_key, field_match.tf, weighted_doc_len, collection_stats);
+
+                        let mut details = self.explain_field_params(field_match, collection_stats);
+                        details.push(Explanation::leaf(self.k1(field_match.index_key), "k1"));
+                        details.push(Explanation::leaf(weighted_doc_len, "weighted document length, over the fields containing the token"));
+                        details.push(idf.clone());
+
+                        Explanation::new(
+                            idf.value * field_score,
+                            format!("contribution of field {}, idf * (w * tf * (k1 + 1)) / (k1 * ((1 - b) + b * weighted document length / (w * average length)) + w * tf)", field_match.index_key),
+                            details,
+                        )
+                    })
+                    .collect();
+
+                Explanation::new(score, "BM25F, sum of the field contributions", field_explanations)
+            }
+            Bm25fMode::Standard { k1 } => {
+                let mut normalized_tf = 0.0;
+                let mut details = vec![Explanation::leaf(k1, "k1")];
+                for field_match in field_matches {
+                    let field_normalized_tf = self.normalized_tf(field_match.index_key, field_match.tf, field_match.length, collection_stats);
+                    normalized_tf += field_normalized_tf;
+
+                    details.push(Explanation::new(
+                        field_normalized_tf,
+                        format!("contribution of field {} to the normalized tf, w * tf / ((1 - b) + b * field length / average length)", field_match.index_key),
+                        self.explain_field_params(field_match, collection_stats),
+                    ));
+                }
+
+                let saturation = Explanation::new(
+                    Self::saturate(k1, normalized_tf),
+                    format!("normalized tf {} saturated, tf / (k1 + tf)", normalized_tf),
+                    details,
+                );
+
+                Explanation::new(score, "BM25F (standard), idf * saturated normalized tf", vec![idf, saturation])
+            }
+        }
+    }
+}
+
+impl Bm25f {
+    fn explain_idf(&self, token_stats: &TokenStats, collection_stats: &CollectionStats) -> Explanation {
+        let formula = match self.mode {
+            Bm25fMode::Original { .. } => "N / df",
+            Bm25fMode::Standard { .. } => "ln(1 + (N - df + 0.5) / (df + 0.5))",
+        };
+
+        Explanation::new(self.idf(token_stats, collection_stats), format!("idf, {}", formula), vec![
+            Explanation::leaf(collection_stats.n_docs as f64, "number of documents (N)"),
+            Explanation::leaf(token_stats.doc_frequency as f64, "number of documents containing the token (df)"),
+        ])
+    }
+
+    /// Frequency, weight, length, average length and b of a field
+    fn explain_field_params(&self, field_match: &FieldMatch, collection_stats: &CollectionStats) -> Vec<Explanation> {
+        vec![
+            Explanation::leaf(field_match.tf, "tf"),
+            Explanation::leaf(self.weight(field_match.index_key), "field weight (w)"),
+            Explanation::leaf(field_match.length, "field length"),
+            Explanation::leaf(collection_stats.field(field_match.index_key).avg_length(), "average field length"),
+            Explanation::leaf(self.b(field_match.index_key), "b"),
+        ]
+    }
+}
+
+/// Classic BM25 (with the Robertson-Sparck Jones idf) on every field, with its own document frequencies and average
+/// length, and a weighted sum of the field scores
+#[derive(Debug, Clone)]
+pub struct Bm25 {
+    field_k1_params: HashMap<String, f64>,
+    field_b_params: HashMap<String, f64>,
+    field_weights: HashMap<String, f64>,
+}
+
+impl Bm25 {
+    pub fn new(field_k1_params: HashMap<String, f64>,
+               field_b_params: HashMap<String, f64>,
+               field_weights: HashMap<String, f64>) -> Self {
+        Self { field_k1_params, field_b_params, field_weights }
+    }
+}
+
+impl Similarity for Bm25 {
+    fn field_score(&self,
+                   index_key: &str,
+                   tf: f64,
+                   length: f64,
+                   token_stats: &TokenStats,
+                   collection_stats: &CollectionStats) -> f64 {
+        let field_stats = collection_stats.field(index_key);
+        let doc_frequency = token_stats.field(index_key).doc_frequency as f64;
+        let idf = (1.0 + ((field_stats.n_docs as f64) - doc_frequency + 0.5) / (doc_frequency + 0.5)).ln();
+
+        let k1 = get_field_param(&self.field_k1_params, index_key, "k1");
+        let b = get_field_param(&self.field_b_params, index_key, "b");
+        let norm = k1 * ((1.0 - b) + b * (length / field_stats.avg_length()));
+
+        get_field_param(&self.field_weights, index_key, "weight") * idf * (tf * (k1 + 1.0)) / (tf + norm)
+    }
+}
+
+/// TF-IDF on every field, the cosine similarity between the query vector, weighted by the smoothed idfs, and the field
+/// vector, weighted by the square root frequencies, and a weighted sum of the field scores. The norm of the field vector
+/// is then exactly the square root of the field length, while the query vector's norm is the same for every document,
+/// so it is left out
+#[derive(Debug, Clone)]
+pub struct TfIdf {
+    field_weights: HashMap<String, f64>,
+}
+
+impl TfIdf {
+    pub fn new(field_weights: HashMap<String, f64>) -> Self {
+        Self { field_weights }
+    }
+}
+
+impl Similarity for TfIdf {
+    fn field_score(&self,
+                   index_key: &str,
+                   tf: f64,
+                   length: f64,
+                   token_stats: &TokenStats,
+                   collection_stats: &CollectionStats) -> f64 {
+        let n_docs = collection_stats.field(index_key).n_docs as f64;
+        let doc_frequency = token_stats.field(index_key).doc_frequency as f64;
+        let idf = 1.0 + ((n_docs + 1.0) / (doc_frequency + 1.0)).ln();
+
+        get_field_param(&self.field_weights, index_key, "weight") * idf * tf.sqrt() / length.sqrt()
+    }
+}
+
+/// Probability of a token in a field over the whole collection, smoothed so that it is never 0
+fn get_collection_probability(index_key: &str, token_stats: &TokenStats, collection_stats: &CollectionStats) -> f64 {
+    (token_stats.field(index_key).collection_frequency + 1.0) / (collection_stats.field(index_key).total_length + 1.0)
+}
+
+/// Query likelihood with Dirichlet smoothing on every field, and a weighted sum of the field scores. Only the fields
+/// containing the token are scored, so like in Lucene, field scores are floored at 0 instead of penalising the documents
+/// matching less tokens
+#[derive(Debug, Clone)]
+pub struct LmDirichlet {
+    mu: f64,
+    field_weights: HashMap<String, f64>,
+}
+
+impl LmDirichlet {
+    pub fn new(mu: f64, field_weights: HashMap<String, f64>) -> Self {
+        Self { mu, field_weights }
+    }
+}
+
+impl Similarity for LmDirichlet {
+    fn field_score(&self,
+                   index_key: &str,
+                   tf: f64,
+                   length: f64,
+                   token_stats: &TokenStats,
+                   collection_stats: &CollectionStats) -> f64 {
+        let collection_probability = get_collection_probability(index_key, token_stats, collection_stats);
+        let score = (1.0 + tf / (self.mu * collection_probability)).ln() + (self.mu / (length + self.mu)).ln();
+
+        get_field_param(&self.field_weights, index_key, "weight") * score.max(0.0)
+    }
+}
+
+/// Query likelihood with Jelinek-Mercer smoothing on every field, where `lambda` is the weight of the collection
+/// probability, and a weighted sum of the field scores
+#[derive(Debug, Clone)]
+pub struct LmJelinekMercer {
+    lambda: f64,
+    field_weights: HashMap<String, f64>,
+}
+
+impl LmJelinekMercer {
+    pub fn new(lambda: f64, field_weights: HashMap<String, f64>) -> Self {
+        Self { lambda, field_weights }
+    }
+}
+
+impl Similarity for LmJelinekMercer {
+    fn field_score(&self,
+                   index_key: &str,
+                   tf: f64,
+                   length: f64,
+                   token_stats: &TokenStats,
+                   collection_stats: &CollectionStats) -> f64 {
+        let collection_probability = get_collection_probability(index_key, token_stats, collection_stats);
+        let score = (1.0 + ((1.0 - self.lambda) * tf / length) / (self.lambda * collection_probability)).ln();
+
+        get_field_param(&self.field_weights, index_key, "weight") * score
+    }
+}
+
+/// Divergence from randomness InL2 on every field: inverse document frequency basic model, Laplace after-effect, and
+/// the frequency normalized to the average field length (normalization 2, with parameter `c`), and a weighted sum of
+/// the field scores
+#[derive(Debug, Clone)]
+pub struct DfrInL2 {
+    c: f64,
+    field_weights: HashMap<String, f64>,
+}
+
+impl DfrInL2 {
+    pub fn new(c: f64, field_weights: HashMap<String, f64>) -> Self {
+        Self { c, field_weights }
+    }
+}
+
+impl Similarity for DfrInL2 {
+    fn field_score(&self,
+                   index_key: &str,
+                   tf: f64,
+                   length: f64,
+                   token_stats: &TokenStats,
+                   collection_stats: &CollectionStats) -> f64 {
+        let field_stats = collection_stats.field(index_key);
+        let normalized_tf = tf * (1.0 + self.c * field_stats.avg_length() / length).log2();
+
+        let doc_frequency = token_stats.field(index_key).doc_frequency as f64;
+        let idf = ((field_stats.n_docs as f64 + 1.0) / (doc_frequency + 0.5)).log2();
+
+        // The after-effect is tfn / (tfn + 1), written so that it is still defined for the infinite frequencies of the
+        // bounds of blocks without stored maxima
+        get_field_param(&self.field_weights, index_key, "weight") * idf / (1.0 + 1.0 / normalized_tf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexing::Indexer;
+    use crate::retrieval::Retriever;
+    use crate::test_index::{bm25f, doc, TestIndex};
+
+    // Hand-computed collection of 4 documents, with a title (weight 2, b 0.75) and a body (weight 1, b 0.5):
+    //
+    //     d1: title "rust" (1 token),          body "rust search engine" (3 tokens)
+    //     d2: title "search" (1 token),        body "search search rust index" (4 tokens)
+    //     d3: title "python search" (2 tokens), body "python" (1 token)
+    //     d4: title "java" (1 token),          body "java index" (2 tokens)
+    //
+    // The average lengths are 5/4 = 1.25 (title) and 10/4 = 2.5 (body), and k1 is 1.2
+    const DOCS: [(&str, &str, &str); 4] = [
+        ("d1", "rust", "rust search engine"),
+        ("d2", "search", "search search rust index"),
+        ("d3", "python search", "python"),
+        ("d4", "java", "java index"),
+    ];
+
+    const INDEX_KEYS: [&str; 2] = ["title", "body"];
+
+    fn standard_bm25f() -> Bm25f {
+        bm25f(&[("title", 0.75, 2.0), ("body", 0.5, 1.0)])
+    }
+
+    fn collection_stats() -> CollectionStats {
+        CollectionStats {
+            n_docs: 4,
+            field_stats: HashMap::from([
+                ("title".to_string(), FieldStats { n_docs: 4, total_length: 5.0 }),
+                ("body".to_string(), FieldStats { n_docs: 4, total_length: 10.0 }),
+            ]),
+        }
+    }
+
+    fn token_stats(doc_frequency: u64) -> TokenStats {
+        TokenStats { doc_frequency, field_stats: HashMap::new() }
+    }
+
+    /// Statistics of a token in the given fields, as their document and collection frequencies
+    fn field_token_stats(field_stats: &[(&str, u64, f64)]) -> TokenStats {
+        let field_stats = field_stats.iter()
+            .map(|(index_key, doc_frequency, collection_frequency)| {
+                (index_key.to_string(), TokenFieldStats { doc_frequency: *doc_frequency, collection_frequency: *collection_frequency })
+            })
+            .collect();
+
+        TokenStats { doc_frequency: 0, field_stats }
+    }
+
+    fn field_match(index_key: &str, tf: f64, length: f64) -> FieldMatch<'_> {
+        FieldMatch { index_key, tf, length }
+    }
+
+    // "rust" is in 2 documents: idf = ln(1 + (4 - 2 + 0.5) / (2 + 0.5)) = ln(2)
+    //
+    // d1: title 2 * 1 / (0.25 + 0.75 * 1 / 1.25) = 40/17, body 1 / (0.5 + 0.5 * 3 / 2.5) = 10/11, so the normalized
+    //     frequency is 610/187, and the score ln(2) * (610/187) / (1.2 + 610/187) = ln(2) * 1525/2086
+    // d2: body 1 / (0.5 + 0.5 * 4 / 2.5) = 10/13, so the score is ln(2) * (10/13) / (1.2 + 10/13) = ln(2) * 25/64
+    fn rust_scores() -> [f64; 2] {
+        [2f64.ln() * 1525.0 / 2086.0, 2f64.ln() * 25.0 / 64.0]
+    }
+
+    // "search" is in 3 documents: idf = ln(1 + (4 - 3 + 0.5) / (3 + 0.5)) = ln(10/7)
+    //
+    // d1: body 10/11, so the score is ln(10/7) * (10/11) / (1.2 + 10/11) = ln(10/7) * 25/58
+    // d2: title 40/17, body 2 / 1.3 = 20/13, so the normalized frequency is 860/221, and the score
+    //     ln(10/7) * (860/221) / (1.2 + 860/221) = ln(10/7) * 2150/2813
+    // d3: title 2 * 1 / (0.25 + 0.75 * 2 / 1.25) = 40/29, so the score is ln(10/7) * (40/29) / (1.2 + 40/29)
+    //     = ln(10/7) * 100/187
+    fn search_scores() -> [f64; 3] {
+        let idf = (10f64 / 7.0).ln();
+        [idf * 25.0 / 58.0, idf * 2150.0 / 2813.0, idf * 100.0 / 187.0]
+    }
+
+    fn assert_close(score: f64, expected_score: f64) {
+        assert!((score - expected_score).abs() < 1e-12, "{} != {}", score, expected_score);
+    }
+
+    #[test]
+    fn standard_bm25f_golden_scores() {
+        let bm25f = standard_bm25f();
+        let collection_stats = collection_stats();
+
+        let [rust_d1, rust_d2] = rust_scores();
+        let rust_stats = token_stats(2);
+        assert_close(bm25f.score(&[field_match("title", 1.0, 1.0), field_match("body", 1.0, 3.0)], &rust_stats, &collection_stats), rust_d1);
+        assert_close(bm25f.score(&[field_match("body", 1.0, 4.0)], &rust_stats, &collection_stats), rust_d2);
+
+        let [search_d1, search_d2, search_d3] = search_scores();
+        let search_stats = token_stats(3);
+        assert_close(bm25f.score(&[field_match("body", 1.0, 3.0)], &search_stats, &collection_stats), search_d1);
+        assert_close(bm25f.score(&[field_match("title", 1.0, 1.0), field_match("body", 2.0, 4.0)], &search_stats, &collection_stats), search_d2);
+        assert_close(bm25f.score(&[field_match("title", 1.0, 2.0)], &search_stats, &collection_stats), search_d3);
+    }
+
+    // The models below are checked against the same collection statistics, on a token with the given statistics in
+    // the title (4 documents, average length 1.25) and the body (4 documents, total length 10, average length 2.5)
+
+    #[test]
+    fn bm25_golden_scores() {
+        let field_params = |title: f64, body: f64| HashMap::from([("title".to_string(), title), ("body".to_string(), body)]);
+        let bm25 = Bm25::new(field_params(1.2, 1.2), field_params(0.75, 0.75), field_params(1.0, 2.0));
+        let stats = field_token_stats(&[("title", 2, 2.0), ("body", 1, 2.0)]);
+
+        // Body, with df 1: idf = ln(1 + (4 - 1 + 0.5) / (1 + 0.5)) = ln(10/3), and with tf 2 and length 5 the norm is
+        // 1.2 * (0.25 + 0.75 * 5 / 2.5) = 2.1, so the score is 2 * ln(10/3) * 2 * 2.2 / (2 + 2.1) = ln(10/3) * 88/41
+        let body_score = (10f64 / 3.0).ln() * 88.0 / 41.0;
+        assert_close(bm25.field_score("body", 2.0, 5.0, &stats, &collection_stats()), body_score);
+
+        // Title, with df 2: idf = ln(1 + 2.5 / 2.5) = ln(2), and with tf 1 and length 1 the norm is
+        // 1.2 * (0.25 + 0.75 / 1.25) = 1.02, so the score is ln(2) * 2.2 / 2.02 = ln(2) * 110/101
+        let title_score = 2f64.ln() * 110.0 / 101.0;
+        let field_matches = [field_match("title", 1.0, 1.0), field_match("body", 2.0, 5.0)];
+        assert_close(bm25.score(&field_matches, &stats, &collection_stats()), title_score + body_score);
+    }
+
+    #[test]
+    fn tf_idf_golden_scores() {
+        let tf_idf = TfIdf::new(HashMap::from([("body".to_string(), 2.0)]));
+        let stats = field_token_stats(&[("body", 1, 4.0)]);
+
+        // idf = 1 + ln((4 + 1) / (1 + 1)) = 1 + ln(5/2), and the field vector's norm is the square root of its length,
+        // so with tf 4 and length 16 the score is 2 * idf * 2 / 4 = idf
+        let idf = 1.0 + 2.5f64.ln();
+        assert_close(tf_idf.field_score("body", 4.0, 16.0, &stats, &collection_stats()), idf);
+        // The cosine of a field made of the token alone is 1, times the idf and the weight
+        assert_close(tf_idf.field_score("body", 9.0, 9.0, &stats, &collection_stats()), 2.0 * idf);
+    }
+
+    #[test]
+    fn lm_dirichlet_golden_scores() {
+        let lm_dirichlet = LmDirichlet::new(10.0, HashMap::from([("body".to_string(), 1.0)]));
+        let stats = field_token_stats(&[("body", 1, 1.0)]);
+
+        // The collection probability is (1 + 1) / (10 + 1) = 2/11, so with tf 2 and length 5 the score is
+        // ln(1 + 2 / (10 * 2/11)) + ln(10 / (5 + 10)) = ln(2.1 * 2/3) = ln(7/5)
+        assert_close(lm_dirichlet.field_score("body", 2.0, 5.0, &stats, &collection_stats()), 1.4f64.ln());
+        // With tf 1 and length 40, ln(2.1) + ln(10 / 50) = ln(0.42) is negative, so it is floored at 0
+        assert_eq!(lm_dirichlet.field_score("body", 1.0, 40.0, &stats, &collection_stats()), 0.0);
+    }
+
+    #[test]
+    fn lm_jelinek_mercer_golden_scores() {
+        let lm_jelinek_mercer = LmJelinekMercer::new(0.5, HashMap::from([("body".to_string(), 3.0)]));
+        let stats = field_token_stats(&[("body", 1, 1.0)]);
+
+        // The collection probability is 2/11, so with tf 2 and length 4 the score is
+        // 3 * ln(1 + (0.5 * 2 / 4) / (0.5 * 2/11)) = 3 * ln(1 + 2.75) = 3 * ln(15/4)
+        assert_close(lm_jelinek_mercer.field_score("body", 2.0, 4.0, &stats, &collection_stats()), 3.0 * 3.75f64.ln());
+    }
+
+    #[test]
+    fn dfr_in_l2_golden_scores() {
+        let dfr_in_l2 = DfrInL2::new(2.0, HashMap::from([("body".to_string(), 2.0)]));
+        let stats = field_token_stats(&[("body", 2, 5.0)]);
+
+        // With length 5, tfn = tf * log2(1 + 2 * 2.5 / 5) = tf, and idf = log2((4 + 1) / (2 + 0.5)) = 1, so with tf 3 the
+        // score is 2 * 1 * 3 / (3 + 1) = 3/2
+        assert_close(dfr_in_l2.field_score("body", 3.0, 5.0, &stats, &collection_stats()), 1.5);
+    }
+
+    #[test]
+    fn standard_bm25f_field_scores_bound_the_score() {
+        let bm25f = standard_bm25f();
+        let collection_stats = collection_stats();
+        let search_stats = token_stats(3);
+
+        let field_matches = [field_match("title", 1.0, 1.0), field_match("body", 2.0, 4.0)];
+        let field_scores: f64 = field_matches.iter()
+            .map(|field_match| bm25f.field_score(field_match.index_key, field_match.tf, field_match.length, &search_stats, &collection_stats))
+            .sum();
+
+        assert!(bm25f.score(&field_matches, &search_stats, &collection_stats) <= field_scores);
+    }
+
+    fn index_docs(indexer: &mut Indexer, docs: &[(&str, &str, &str)]) {
+        indexer.index(docs.iter().map(|(docid, title, body)| doc(docid, &[("title", title), ("body", body)]))).unwrap();
+    }
+
+    // Exhaustive and top 2 results of a query
+    type QueryResults = (Vec<(String, f64)>, Vec<(String, f64)>);
+
+    /// Exhaustive and top 2 results of "rust search"
+    fn golden_query_results(retriever: &Retriever) -> QueryResults {
+        let query_tokens = vec!["rust".to_string(), "search".to_string()];
+        let results = retriever.retrieval_multiple_tokens(&query_tokens, &standard_bm25f()).unwrap();
+        let top_k_results = retriever.retrieval_top_k(&query_tokens, 2, &standard_bm25f()).unwrap();
+
+        (results, top_k_results)
+    }
+
+    fn assert_golden_results((results, top_k_results): &QueryResults) {
+        let [rust_d1, rust_d2] = rust_scores();
+        let [search_d1, search_d2, search_d3] = search_scores();
+        let expected_results = [("d1", rust_d1 + search_d1), ("d2", rust_d2 + search_d2), ("d3", search_d3)];
+
+        assert_eq!(results.len(), expected_results.len());
+        for ((doc_id, score), (expected_doc_id, expected_score)) in results.iter().zip(expected_results) {
+            assert_eq!(doc_id, expected_doc_id);
+            assert_close(*score, expected_score);
+        }
+
+        assert_eq!(top_k_results.len(), 2);
+        for ((doc_id, score), (expected_doc_id, expected_score)) in top_k_results.iter().zip(expected_results) {
+            assert_eq!(doc_id, expected_doc_id);
+            assert_close(*score, expected_score);
+        }
+    }
+
+    #[test]
+    fn standard_bm25f_retrieval_golden_scores() {
+        let index = TestIndex::new("bm25f_golden");
+        index_docs(&mut index.indexer(&INDEX_KEYS), &DOCS);
+
+        let results = golden_query_results(&index.retriever(&INDEX_KEYS));
+
+        assert_golden_results(&results);
+    }
+
+    fn assert_token_stats(retriever: &Retriever, token: &str, doc_frequency: u64, field_stats: &[(&str, u64, f64)]) {
+        let token_stats = retriever.get_token_stats(token, None).unwrap();
+        assert_eq!(token_stats.doc_frequency, doc_frequency, "document frequency of {}", token);
+
+        for index_key in ["title", "body"] {
+            let (doc_frequency, collection_frequency) = field_stats.iter()
+                .find(|(field_index_key, _, _)| *field_index_key == index_key)
+                .map_or((0, 0.0), |(_, doc_frequency, collection_frequency)| (*doc_frequency, *collection_frequency));
+            assert_eq!(token_stats.field(index_key).doc_frequency, doc_frequency, "{} document frequency of {}", index_key, token);
+            assert_eq!(token_stats.field(index_key).collection_frequency, collection_frequency, "{} collection frequency of {}", index_key, token);
+        }
+    }
+
+    #[test]
+    fn standard_bm25f_retrieval_after_deletes_and_updates() {
+        let index = TestIndex::new("bm25f_deletes");
+        let mut indexer = index.indexer(&INDEX_KEYS);
+        // An older version of d4, and an extra document, both changing the statistics of "rust", "search", "engine"
+        // and "index", and the only one with "golang"
+        index_docs(&mut indexer, &[DOCS[0], DOCS[1], DOCS[2], ("d4", "rust", "rust search engine index")]);
+        index_docs(&mut indexer, &[("d5", "search engine", "rust golang")]);
+
+        let retriever_before = index.retriever(&INDEX_KEYS);
+        let collection_stats_before = retriever_before.collection_stats.clone();
+        assert_token_stats(&retriever_before, "rust", 4, &[("title", 2, 2.0), ("body", 4, 4.0)]);
+        assert_token_stats(&retriever_before, "golang", 1, &[("body", 1, 1.0)]);
+
+        let deleted = indexer.delete("d5").unwrap();
+        let deleted_again = indexer.delete("d5").unwrap();
+        let deleted_missing = indexer.delete("d6").unwrap();
+        indexer.update("d4", HashMap::from([("title".to_string(), "java".to_string()), ("body".to_string(), "java index".to_string())])).unwrap();
+
+        let retriever_after = index.retriever(&INDEX_KEYS);
+        let results = golden_query_results(&retriever_after);
+        let golang_tokens = vec!["golang".to_string()];
+        let golang_results = retriever_after.retrieval_multiple_tokens(&golang_tokens, &standard_bm25f()).unwrap();
+        let golang_top_k_results = retriever_after.retrieval_top_k(&golang_tokens, 2, &standard_bm25f()).unwrap();
+
+        assert!(deleted);
+        assert!(!deleted_again);
+        assert!(!deleted_missing);
+
+        assert_eq!(collection_stats_before.n_docs, 5);
+        assert_eq!(collection_stats_before.field("title").total_length, 7.0);
+        assert_eq!(collection_stats_before.field("body").total_length, 14.0);
+
+        // The deleted documents and the old version of d4 no longer count in the average lengths, nor in the token
+        // statistics, which are exactly the ones of the live documents
+        let collection_stats_after = retriever_after.collection_stats.clone();
+        assert_eq!(collection_stats_after.n_docs, 4);
+        assert_eq!(collection_stats_after.field("title").total_length, 5.0);
+        assert_eq!(collection_stats_after.field("body").total_length, 10.0);
+        assert_token_stats(&retriever_after, "rust", 2, &[("title", 1, 1.0), ("body", 2, 2.0)]);
+        assert_token_stats(&retriever_after, "search", 3, &[("title", 2, 2.0), ("body", 2, 3.0)]);
+        assert_token_stats(&retriever_after, "engine", 1, &[("body", 1, 1.0)]);
+        assert_token_stats(&retriever_after, "index", 2, &[("body", 2, 2.0)]);
+        assert_token_stats(&retriever_after, "java", 1, &[("title", 1, 1.0), ("body", 1, 1.0)]);
+        assert_token_stats(&retriever_after, "golang", 0, &[]);
+
+        assert_golden_results(&results);
+        assert!(golang_results.is_empty());
+        assert!(golang_top_k_results.is_empty());
+    }
+}
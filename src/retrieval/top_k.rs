@@ -0,0 +1,280 @@
+// Top-k retrieval with Block-Max WAND (Ding & Suel) dynamic pruning. Every query token gets an upper bound of its score,
+// the sum of the field scores of the maximum tf and minimum field length of its postings blocks (which bound them for
+// any `Similarity`), and the tokens' cursors are kept
+// sorted by their current doc id. Documents are only scored once the bounds of the tokens up to them could beat the
+// current k-th best score, and whole blocks are skipped when their own bounds can't
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use anyhow::Result;
+use crate::indexing::docids::DocId;
+use crate::indexing::postings_cursor::PostingsCursor;
+use crate::retrieval::SegmentDocId;
+use crate::retrieval::segment::Segment;
+use crate::retrieval::similarity::{self, CollectionStats, Similarity, TokenStats};
+
+type Tf = u64;
+
+/// A query token, with its cursors over every field of a segment
+pub struct TokenCursors<'a> {
+    field_cursors: Vec<(&'a str, PostingsCursor<'a>)>,
+    field_postings: Vec<Option<(DocId, Tf)>>,
+    token_stats: &'a TokenStats,
+    // Upper bound of the token's score in any document of the segment
+    max_score: f64,
+}
+
+impl<'a> TokenCursors<'a> {
+    /// Positions the cursors on their first postings
+    pub fn new(mut field_cursors: Vec<(&'a str, PostingsCursor<'a>)>,
+               token_stats: &'a TokenStats,
+               similarity: &dyn Similarity,
+               collection_stats: &CollectionStats) -> Result<Self> {
+        let field_postings = field_cursors.iter_mut()
+            .map(|(_, cursor)| cursor.next())
+            .collect::<Result<Vec<_>>>()?;
+
+        let max_score = field_cursors.iter()
+            .map(|(index_key, cursor)| {
+                cursor.block_maxes()
+                    .map(|(max_tf, min_length)| {
+                        similarity.field_score(index_key, max_tf as f64, min_length as f64, token_stats, collection_stats)
+                    })
+                    .fold(0.0, f64::max)
+            })
+            .sum();
+
+        Ok(Self { field_cursors, field_postings, token_stats, max_score })
+    }
+
+    /// Lowest doc id the cursors are on, if they aren't exhausted
+    fn doc_id(&self) -> Option<DocId> {
+        self.field_postings.iter().flatten().map(|(doc_id, _)| *doc_id).min()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<()> {
+        for ((_, cursor), posting) in self.field_cursors.iter_mut().zip(self.field_postings.iter_mut()) {
+            if posting.is_some_and(|(doc_id, _)| doc_id < target) {
+                *posting = cursor.advance(target)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Frequency of the token in every field of the document the cursors are on
+    fn field_tfs(&self, doc_id: DocId) -> Vec<(&str, f64)> {
+        self.field_cursors.iter()
+            .zip(self.field_postings.iter())
+            .filter_map(|((index_key, _), posting)| match posting {
+                Some((posting_doc_id, tf)) if *posting_doc_id == doc_id => Some((*index_key, *tf as f64)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Upper bound of the token's score in the documents from the target up to the returned doc id (included)
+    fn block_max_score(&self,
+                       target: DocId,
+                       similarity: &dyn Similarity,
+                       collection_stats: &CollectionStats) -> (f64, DocId) {
+        let mut max_score = 0.0;
+        let mut last_doc_id = DocId::MAX;
+
+        for (index_key, cursor) in &self.field_cursors {
+            if let Some((block_last_doc_id, max_tf, min_length)) = cursor.block_max(target) {
+                max_score += similarity.field_score(index_key, max_tf as f64, min_length as f64, self.token_stats, collection_stats);
+                last_doc_id = last_doc_id.min(block_last_doc_id);
+            }
+        }
+
+        (max_score, last_doc_id)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ScoredDoc {
+    score: f64,
+    segment_doc_id: SegmentDocId,
+}
+
+impl PartialEq for ScoredDoc {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScoredDoc {}
+
+impl PartialOrd for ScoredDoc {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed, so that the binary heap keeps the worst result on top
+impl Ord for ScoredDoc {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.total_cmp(&self.score).then_with(|| self.segment_doc_id.cmp(&other.segment_doc_id))
+    }
+}
+
+/// The k best scored documents found so far
+pub struct TopDocs {
+    k: usize,
+    heap: BinaryHeap<ScoredDoc>,
+}
+
+impl TopDocs {
+    pub fn new(k: usize) -> Self {
+        Self { k, heap: BinaryHeap::with_capacity(k + 1) }
+    }
+
+    /// Score a document must beat to make it into the top k
+    fn threshold(&self) -> f64 {
+        match self.heap.peek() {
+            Some(worst) if self.heap.len() == self.k => worst.score,
+            _ if self.k == 0 => f64::INFINITY,
+            _ => f64::NEG_INFINITY,
+        }
+    }
+
+    fn push(&mut self, segment_doc_id: SegmentDocId, score: f64) {
+        if score > self.threshold() {
+            self.heap.push(ScoredDoc { score, segment_doc_id });
+            if self.heap.len() > self.k {
+                self.heap.pop();
+            }
+        }
+    }
+
+    /// Best documents, sorted by decreasing score
+    pub fn into_sorted_vec(self) -> Vec<(SegmentDocId, f64)> {
+        self.heap.into_sorted_vec()
+            .into_iter()
+            .map(|scored_doc| (scored_doc.segment_doc_id, scored_doc.score))
+            .collect()
+    }
+}
+
+/// Adds the best live documents of a segment to the top k
+pub fn search_segment(segment_i: usize,
+                      segment: &Segment,
+                      mut tokens: Vec<TokenCursors>,
+                      top_docs: &mut TopDocs,
+                      similarity: &dyn Similarity,
+                      collection_stats: &CollectionStats) -> Result<()> {
+    loop {
+        tokens.retain(|token| token.doc_id().is_some());
+        tokens.sort_by_key(|token| token.doc_id());
+        let threshold = top_docs.threshold();
+
+        // The first token whose bound, added to the ones of the tokens before it, could beat the threshold. Documents
+        // before its current one can only contain the previous tokens, so they can't make it into the top k
+        let mut max_score = 0.0;
+        let Some(mut pivot) = tokens.iter().position(|token| {
+            max_score += token.max_score;
+            max_score > threshold
+        }) else {
+            break;
+        };
+        let pivot_doc_id = tokens[pivot].doc_id().unwrap();
+        while tokens.get(pivot + 1).is_some_and(|token| token.doc_id() == Some(pivot_doc_id)) {
+            pivot += 1;
+        }
+
+        // Tighter bound, over the blocks of the pivot's document, which holds until the first of them ends (or the
+        // next token starts)
+        let mut block_max_score = 0.0;
+        let mut next_doc_id = tokens.get(pivot + 1).and_then(|token| token.doc_id()).unwrap_or(DocId::MAX);
+        for token in &tokens[..=pivot] {
+            let (token_block_max_score, last_doc_id) = token.block_max_score(pivot_doc_id, similarity, collection_stats);
+            block_max_score += token_block_max_score;
+            next_doc_id = next_doc_id.min(last_doc_id.saturating_add(1));
+        }
+
+        if block_max_score <= threshold {
+            for token in &mut tokens[..=pivot] {
+                token.advance(next_doc_id)?;
+            }
+        } else if tokens[0].doc_id() == Some(pivot_doc_id) {
+            if !segment.is_deleted(pivot_doc_id) {
+                let mut score = 0.0;
+                for token in &tokens[..=pivot] {
+                    let field_matches = similarity::get_field_matches(segment, pivot_doc_id, &token.field_tfs(pivot_doc_id))?;
+                    score += similarity.score(&field_matches, token.token_stats, collection_stats);
+                }
+                top_docs.push((segment_i, pivot_doc_id), score);
+            }
+
+            for token in &mut tokens[..=pivot] {
+                token.advance(pivot_doc_id + 1)?;
+            }
+        } else {
+            for token in &mut tokens[..pivot] {
+                token.advance(pivot_doc_id)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use crate::test_index::{bm25f, doc, TestIndex};
+
+    const N_SEGMENTS: usize = 3;
+    const N_SEGMENT_DOCS: usize = 400;
+
+    // Skewed towards the first words, so that their postings span several blocks in every segment
+    fn random_text(rng: &mut StdRng, max_length: usize) -> String {
+        (0..rng.gen_range(1..=max_length))
+            .map(|_| format!("w{}", (rng.gen::<f64>().powi(3) * 30.0) as usize))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn top_k_matches_exhaustive_retrieval() {
+        let index = TestIndex::new("top_k_exhaustive");
+        let mut indexer = index.indexer(&["title", "body"]);
+        let mut rng = StdRng::seed_from_u64(42);
+        for segment_i in 0..N_SEGMENTS {
+            let docs: Vec<_> = (0..N_SEGMENT_DOCS)
+                .map(|doc_i| {
+                    let title = random_text(&mut rng, 4);
+                    let body = random_text(&mut rng, 40);
+                    doc(&format!("d{}_{}", segment_i, doc_i), &[("title", &title), ("body", &body)])
+                })
+                .collect();
+            indexer.index(docs.into_iter()).unwrap();
+        }
+
+        let mut deleted_docids = Vec::new();
+        for segment_i in 0..N_SEGMENTS {
+            for doc_i in (segment_i..N_SEGMENT_DOCS).step_by(7) {
+                let docid = format!("d{}_{}", segment_i, doc_i);
+                assert!(indexer.delete(&docid).unwrap());
+                deleted_docids.push(docid);
+            }
+        }
+
+        let retriever = index.retriever(&["title", "body"]);
+        let bm25f = bm25f(&[("title", 0.75, 2.0), ("body", 0.5, 1.0)]);
+        let query_tokens: Vec<String> = ["w0", "w1", "w5", "w20", "missing"].iter().map(|token| token.to_string()).collect();
+        let results = retriever.retrieval_multiple_tokens(&query_tokens, &bm25f).unwrap();
+        let top_k_results = retriever.retrieval_top_k(&query_tokens, 10, &bm25f).unwrap();
+
+        assert_eq!(top_k_results.len(), 10);
+        let scores: HashMap<&str, f64> = results.iter().map(|(docid, score)| (docid.as_str(), *score)).collect();
+        for ((docid, score), (_, expected_score)) in top_k_results.iter().zip(&results) {
+            assert!(!deleted_docids.contains(docid), "deleted document {} was retrieved", docid);
+            assert!((score - expected_score).abs() < 1e-9, "{} != {}", score, expected_score);
+            assert!((score - scores[docid.as_str()]).abs() < 1e-9, "{} != {}", score, scores[docid.as_str()]);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+// Automaton for wildcard patterns over the postings FSTs, where `*` matches any sequence of characters and `?` matches
+// a single character. FSTs are walked byte by byte, so the pattern is simulated as an NFA over the UTF-8 bytes of the
+// tokens, where `?` consumes all the bytes of a character
+
+use fst::Automaton;
+
+#[derive(Debug, Clone, PartialEq)]
+enum PatternElement {
+    Byte(u8),
+    AnyChar,
+    AnySequence,
+}
+
+#[derive(Debug, Clone)]
+pub struct WildcardAutomaton {
+    elements: Vec<PatternElement>,
+}
+
+// Pattern element the NFA is at, and the number of continuation bytes of the current character left to consume
+type Thread = (usize, usize);
+
+impl WildcardAutomaton {
+    pub fn new(pattern: &str) -> Self {
+        let mut elements = Vec::new();
+
+        for pattern_char in pattern.chars() {
+            match pattern_char {
+                '*' => elements.push(PatternElement::AnySequence),
+                '?' => elements.push(PatternElement::AnyChar),
+                _ => {
+                    let mut char_bytes = [0; 4];
+                    for byte in pattern_char.encode_utf8(&mut char_bytes).bytes() {
+                        elements.push(PatternElement::Byte(byte));
+                    }
+                }
+            }
+        }
+
+        Self { elements }
+    }
+
+    /// Whether the pattern contains any wildcard
+    pub fn is_wildcard(pattern: &str) -> bool {
+        pattern.contains(['*', '?'])
+    }
+
+    /// Adds a thread at an element, along with the ones reached by matching its `*` with an empty sequence
+    fn add_thread(&self, threads: &mut Vec<Thread>, element_i: usize) {
+        threads.push((element_i, 0));
+
+        if self.elements.get(element_i) == Some(&PatternElement::AnySequence) {
+            self.add_thread(threads, element_i + 1);
+        }
+    }
+}
+
+/// Number of bytes of the UTF-8 character starting with this byte
+fn utf8_char_length(byte: u8) -> usize {
+    match byte {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    }
+}
+
+impl Automaton for WildcardAutomaton {
+    type State = Vec<Thread>;
+
+    fn start(&self) -> Self::State {
+        let mut threads = Vec::new();
+        self.add_thread(&mut threads, 0);
+
+        threads
+    }
+
+    fn is_match(&self, state: &Self::State) -> bool {
+        state.contains(&(self.elements.len(), 0))
+    }
+
+    fn can_match(&self, state: &Self::State) -> bool {
+        !state.is_empty()
+    }
+
+    fn accept(&self, state: &Self::State, byte: u8) -> Self::State {
+        let mut threads = Vec::new();
+
+        for (element_i, remaining_bytes) in state {
+            if *remaining_bytes > 0 {
+                // In the middle of a character matched by `?`
+                if remaining_bytes - 1 == 0 {
+                    self.add_thread(&mut threads, element_i + 1);
+                } else {
+                    threads.push((*element_i, remaining_bytes - 1));
+                }
+                continue;
+            }
+
+            match self.elements.get(*element_i) {
+                Some(PatternElement::Byte(pattern_byte)) if *pattern_byte == byte => {
+                    self.add_thread(&mut threads, element_i + 1);
+                }
+                Some(PatternElement::AnyChar) => {
+                    match utf8_char_length(byte) {
+                        1 => self.add_thread(&mut threads, element_i + 1),
+                        char_length => threads.push((*element_i, char_length - 1)),
+                    }
+                }
+                Some(PatternElement::AnySequence) => self.add_thread(&mut threads, *element_i),
+                _ => {}
+            }
+        }
+
+        threads.sort_unstable();
+        threads.dedup();
+
+        threads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fst::{IntoStreamer, Set, Streamer};
+    use super::*;
+    use crate::index_directory::Manifest;
+    use crate::retrieval::expansion;
+    use crate::retrieval::segment::Segment;
+    use crate::retrieval::Query;
+    use crate::test_index::{bm25f, body_docs, sorted_docids, TestIndex};
+    use std::collections::BTreeSet;
+
+    fn matching_tokens(pattern: &str, tokens: &[&str]) -> Vec<String> {
+        let set = Set::from_iter(tokens).unwrap();
+        let mut stream = set.search(WildcardAutomaton::new(pattern)).into_stream();
+
+        let mut matching_tokens = Vec::new();
+        while let Some(token) = stream.next() {
+            matching_tokens.push(String::from_utf8(token.to_vec()).unwrap());
+        }
+
+        matching_tokens
+    }
+
+    #[test]
+    fn wildcards_match_characters() {
+        // In byte order, as FSTs need them
+        let tokens = ["bar", "br", "bär", "foobar", "rust"];
+
+        assert_eq!(matching_tokens("*bar", &tokens), ["bar", "foobar"]);
+        assert_eq!(matching_tokens("b?r", &tokens), ["bar", "bär"]);
+        assert_eq!(matching_tokens("b*r", &tokens), ["bar", "br", "bär"]);
+        assert_eq!(matching_tokens("rust", &tokens), ["rust"]);
+    }
+
+    #[test]
+    fn wildcard_queries_are_truncated_to_max_expansions() {
+        let index = TestIndex::new("wildcard_query");
+        index.indexer(&["body"]).index(body_docs(&[("d1", "rust"), ("d2", "rusty"), ("d3", "rustacean"), ("d4", "trust")])).unwrap();
+
+        let retriever = index.retriever(&["body"]);
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+        let docids = |pattern: &str, max_expansions: usize| {
+            let query = Query::Wildcard { pattern: pattern.to_string(), max_expansions };
+            sorted_docids(retriever.retrieval_query(&query, &bm25f).unwrap())
+        };
+
+        let prefix_docids = docids("rust*", 2);
+        let suffix_docids = docids("*rust", 10);
+        let all_docids = docids("*rust*", 10);
+        let explanation = retriever.explain(&Query::Wildcard { pattern: "rust*".to_string(), max_expansions: 2 }, "d2", &bm25f).unwrap();
+
+        // The first tokens in lexicographical order are "rust" and "rustacean"
+        assert_eq!(prefix_docids, ["d1", "d3"]);
+        assert_eq!(suffix_docids, ["d1", "d4"]);
+        assert_eq!(all_docids, ["d1", "d2", "d3", "d4"]);
+        assert!(!explanation.matched);
+    }
+
+    const DOCS: [(&str, &str); 6] = [("d1", "rust"), ("d2", "rusty"), ("d3", "trust"), ("d4", "bust"), ("d5", "rest"), ("d6", "java")];
+
+    fn tokens(tokens: &[&str]) -> BTreeSet<String> {
+        tokens.iter().map(|token| token.to_string()).collect()
+    }
+
+    fn open_segments(index: &TestIndex) -> Vec<Segment> {
+        Manifest::load(&index.directory).unwrap().segments.iter()
+            .map(|segment_info| Segment::open(&["body".to_string()], &index.directory.segment(&segment_info.name)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn prefix_suffix_and_single_character_wildcards() {
+        let index = TestIndex::new("wildcard_kinds");
+        index.indexer(&["body"]).index(body_docs(&DOCS)).unwrap();
+
+        let retriever = index.retriever(&["body"]);
+        let bm25f = bm25f(&[("body", 0.75, 1.0)]);
+        let docids = |pattern: &str| sorted_docids(retriever.retrieval_wildcard(pattern, 10, &bm25f).unwrap());
+
+        // A prefix
+        assert_eq!(docids("rust*"), ["d1", "d2"]);
+        // Leading wildcards, over the reversed tokens
+        assert_eq!(docids("*ust"), ["d1", "d3", "d4"]);
+        assert_eq!(docids("?ust"), ["d1", "d4"]);
+        // Wildcards in the middle or at both ends, over the tokens
+        assert_eq!(docids("r?st"), ["d1", "d5"]);
+        assert_eq!(docids("*us*"), ["d1", "d2", "d3", "d4"]);
+        assert_eq!(docids("*"), ["d1", "d2", "d3", "d4", "d5", "d6"]);
+        // No wildcard at all
+        assert_eq!(docids("rust"), ["d1"]);
+        assert!(docids("*python").is_empty());
+    }
+
+    #[test]
+    fn leading_wildcards_are_matched_over_the_reversed_tokens() {
+        let index = TestIndex::new("wildcard_reversed");
+        index.indexer(&["body"]).index(body_docs(&DOCS)).unwrap();
+        let segments = open_segments(&index);
+
+        assert!(segments[0].has_reversed_tokens());
+        let reversed_tokens = segments[0].get_matching_reversed_tokens(&WildcardAutomaton::new("tsu*"), usize::MAX).unwrap();
+        assert_eq!(reversed_tokens, tokens(&["bust", "rust", "trust"]));
+        assert_eq!(segments[0].get_matching_reversed_tokens(&WildcardAutomaton::new("tsu*"), 2).unwrap().len(), 2);
+
+        // The same tokens as running the pattern over every token
+        let scanned_tokens = expansion::get_matching_tokens(&segments, &WildcardAutomaton::new("*ust"), usize::MAX).unwrap();
+        assert_eq!(expansion::get_wildcard_matching_tokens(&segments, "*ust", usize::MAX).unwrap(), scanned_tokens);
+        assert_eq!(expansion::get_wildcard_matching_tokens(&segments, "?ust", usize::MAX).unwrap(), tokens(&["bust", "rust"]));
+        assert_eq!(expansion::get_wildcard_matching_tokens(&segments, "*ust", 2).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn segments_without_reversed_tokens_are_scanned() {
+        let index = TestIndex::new("wildcard_unreversed");
+        let mut indexer = index.indexer(&["body"]);
+        indexer.index(body_docs(&DOCS[..3])).unwrap();
+        indexer.index(body_docs(&DOCS[3..])).unwrap();
+
+        // Like a segment written before the reversed tokens were indexed
+        let segment_name = Manifest::load(&index.directory).unwrap().segments[0].name.clone();
+        std::fs::remove_file(index.directory.segment(&segment_name).reversed_postings_index_path("body")).unwrap();
+        let segments = open_segments(&index);
+
+        assert!(!segments[0].has_reversed_tokens());
+        assert!(segments[1].has_reversed_tokens());
+        assert_eq!(expansion::get_wildcard_matching_tokens(&segments, "*ust", usize::MAX).unwrap(), tokens(&["bust", "rust", "trust"]));
+    }
+}
@@ -0,0 +1,93 @@
+// Temporary indexes for the tests. Every test index lives in its own directory under the system's temporary directory,
+// named after the test and the process, so that tests can run in parallel, and is removed when dropped, even if the
+// test fails
+
+use crate::index_directory::IndexDirectory;
+use crate::indexing::Indexer;
+use crate::retrieval::{Bm25f, Retriever};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub type Doc = Option<(String, HashMap<String, String>)>;
+
+pub struct TestIndex {
+    path: PathBuf,
+    pub directory: IndexDirectory,
+}
+
+impl TestIndex {
+    /// Creates an empty index, replacing the one of a previous run of the test if it was left behind
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        let directory = IndexDirectory::create(&path).unwrap();
+
+        Self { path, directory }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Indexer of the given fields, each one read from the document field of the same name
+    pub fn indexer(&self, index_keys: &[&str]) -> Indexer {
+        let field_keys = index_keys.iter()
+            .map(|index_key| (index_key.to_string(), index_key.to_string()))
+            .collect();
+
+        Indexer::new(field_keys, self.directory.clone())
+    }
+
+    pub fn retriever(&self, index_keys: &[&str]) -> Retriever {
+        Retriever::new(index_keys.iter().map(|index_key| index_key.to_string()).collect(), &self.directory).unwrap()
+    }
+}
+
+impl Drop for TestIndex {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A document with the given fields, as read by `Indexer::index`
+pub fn doc(docid: &str, fields: &[(&str, &str)]) -> Doc {
+    let fields = fields.iter()
+        .map(|(index_key, text)| (index_key.to_string(), text.to_string()))
+        .collect();
+
+    Some((docid.to_string(), fields))
+}
+
+/// Documents with a single "body" field
+pub fn body_docs(docs: &[(&str, &str)]) -> impl Iterator<Item = Doc> {
+    docs.iter()
+        .map(|(docid, body)| doc(docid, &[("body", body)]))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+/// Standard BM25F with k1 1.2, and the given b and weight of every field
+pub fn bm25f(field_params: &[(&str, f64, f64)]) -> Bm25f {
+    let field_b_params = field_params.iter().map(|(index_key, b, _)| (index_key.to_string(), *b)).collect();
+    let field_weights = field_params.iter().map(|(index_key, _, weight)| (index_key.to_string(), *weight)).collect();
+
+    Bm25f::standard(1.2, field_b_params, field_weights)
+}
+
+/// Docids of the results, sorted, for the tests which don't depend on the ranking
+pub fn sorted_docids(results: Vec<(String, f64)>) -> Vec<String> {
+    let mut docids: Vec<String> = results.into_iter().map(|(docid, _)| docid).collect();
+    docids.sort();
+
+    docids
+}
+
+/// Checks that two rankings have the same documents in the same order, with the same scores up to rounding
+pub fn assert_same_results(results: &[(String, f64)], expected_results: &[(String, f64)]) {
+    assert_eq!(results.len(), expected_results.len(), "{:?} != {:?}", results, expected_results);
+    for ((docid, score), (expected_docid, expected_score)) in results.iter().zip(expected_results) {
+        assert_eq!(docid, expected_docid);
+        assert!((score - expected_score).abs() < 1e-12, "{} != {}", score, expected_score);
+    }
+}
@@ -0,0 +1,71 @@
+// Token filters normalizing the text of every token
+
+use crate::tokenizer::{Token, TokenFilter};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LowercaseFilter;
+
+impl TokenFilter for LowercaseFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens.into_iter()
+            .map(|token| Token::new(token.text.to_lowercase(), token.position))
+            .collect()
+    }
+}
+
+/// Removes every character which isn't alphanumeric. Tokens left empty are kept, so that fields keep their length
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AlphanumericFilter;
+
+impl TokenFilter for AlphanumericFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens.into_iter()
+            .map(|token| Token::new(token.text.chars().filter(|c| c.is_alphanumeric()).collect(), token.position))
+            .collect()
+    }
+}
+
+/// Folds the letters with diacritics into their ASCII base letters (e.g. "café" into "cafe"), and the letters without a
+/// decomposition into their usual ASCII spelling (e.g. "straße" into "strasse"). Other characters are kept
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AsciiFoldingFilter;
+
+impl AsciiFoldingFilter {
+    fn fold(text: &str) -> String {
+        let mut folded = String::with_capacity(text.len());
+        // The canonical decomposition splits the accented letters into their base letter and combining marks, and the
+        // composition afterwards restores the rest of the decomposed characters (e.g. Hangul syllables)
+        for c in text.nfd().filter(|c| !is_combining_mark(*c)).nfc() {
+            match c {
+                'ß' => folded.push_str("ss"),
+                'æ' => folded.push_str("ae"),
+                'Æ' => folded.push_str("AE"),
+                'œ' => folded.push_str("oe"),
+                'Œ' => folded.push_str("OE"),
+                'þ' => folded.push_str("th"),
+                'Þ' => folded.push_str("TH"),
+                'ø' => folded.push('o'),
+                'Ø' => folded.push('O'),
+                'đ' | 'ð' => folded.push('d'),
+                'Đ' | 'Ð' => folded.push('D'),
+                'ł' => folded.push('l'),
+                'Ł' => folded.push('L'),
+                'ı' => folded.push('i'),
+                c => folded.push(c),
+            }
+        }
+
+        folded
+    }
+}
+
+impl TokenFilter for AsciiFoldingFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens.into_iter()
+            .map(|token| Token::new(Self::fold(&token.text), token.position))
+            .collect()
+    }
+}
@@ -1,29 +1,250 @@
-// Simple tokenizer implementation that will remove any non-alphabetic and non-alphanumeric characters from the text, and
-// then split the text by whitespace
+// Text analysis, turning the text of a field (or a query) into the tokens that are indexed (or searched). An `Analyzer`
+// is a tokenizer splitting the text into tokens, followed by a chain of token filters transforming them, e.g. the
+// default one splits the text by whitespace, lowercases the tokens and removes their non-alphanumeric characters, while
+// multilingual text is better split by the Unicode word boundaries and folded to ASCII, and CJK text into bigrams
+//
+// Analyzers are configured per field at indexing time and recorded in the index manifest, so that queries are analyzed
+// with exactly the same pipeline as the field they search. Their tokenizers and filters are serializable for this
+// reason, and adding a new one means implementing `Tokenizer` or `TokenFilter` for it, and adding it to
+// `TokenizerConfig` or `TokenFilterConfig`
 
-#[derive(Debug)]
-pub struct Token<'a> {
-    text: &'a str,
+mod filters;
+mod ngrams;
+mod stemmers;
+mod stopwords;
+mod unicode;
+mod whitespace;
+
+pub use self::filters::{AlphanumericFilter, AsciiFoldingFilter, LowercaseFilter};
+pub use self::ngrams::{CjkBigramFilter, NGramFilter};
+pub use self::stemmers::StemmerFilter;
+pub use self::stopwords::StopwordFilter;
+pub use self::unicode::UnicodeTokenizer;
+pub use self::whitespace::WhitespaceTokenizer;
+
+use crate::indexing::postings_codec::Position;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Token {
+    pub text: String,
+    // Position of the token in the field. Filters removing tokens keep the positions of the remaining ones, so that
+    // phrase queries don't match across the removed tokens
+    pub position: Position,
+}
+
+impl Token {
+    pub fn new(text: String, position: Position) -> Self {
+        Self { text, position }
+    }
+}
+
+/// Language of the text, for the filters that depend on it (e.g. stemmers and stopwords)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Language {
+    English,
+    French,
+    German,
+    Italian,
+    Spanish,
+    Swedish,
+}
+
+pub trait Tokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token>;
+}
+
+pub trait TokenFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TokenizerConfig {
+    Whitespace(WhitespaceTokenizer),
+    Unicode(UnicodeTokenizer),
+}
+
+impl TokenizerConfig {
+    fn tokenizer(&self) -> &dyn Tokenizer {
+        match self {
+            Self::Whitespace(tokenizer) => tokenizer,
+            Self::Unicode(tokenizer) => tokenizer,
+        }
+    }
 }
 
-impl<'a> Token<'a> {
-    pub fn new(text: &'a str) -> Token<'a> {
-        Token { text }
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TokenFilterConfig {
+    Lowercase(LowercaseFilter),
+    Alphanumeric(AlphanumericFilter),
+    AsciiFolding(AsciiFoldingFilter),
+    CjkBigram(CjkBigramFilter),
+    NGram(NGramFilter),
+    Stemmer(StemmerFilter),
+    Stopword(StopwordFilter),
+}
+
+impl TokenFilterConfig {
+    fn filter(&self) -> &dyn TokenFilter {
+        match self {
+            Self::Lowercase(filter) => filter,
+            Self::Alphanumeric(filter) => filter,
+            Self::AsciiFolding(filter) => filter,
+            Self::CjkBigram(filter) => filter,
+            Self::NGram(filter) => filter,
+            Self::Stemmer(filter) => filter,
+            Self::Stopword(filter) => filter,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Analyzer {
+    tokenizer: TokenizerConfig,
+    filters: Vec<TokenFilterConfig>,
+}
+
+impl Analyzer {
+    pub fn new(tokenizer: TokenizerConfig, filters: Vec<TokenFilterConfig>) -> Self {
+        Self { tokenizer, filters }
+    }
+
+    pub fn analyze(&self, text: &str) -> Vec<Token> {
+        self.filters.iter().fold(self.tokenizer.tokenizer().tokenize(text), |tokens, filter| {
+            filter.filter().filter(tokens)
+        })
     }
 
-    pub fn clean(&self) -> String {
-        return self.text
-            .to_lowercase()
-            .chars()
-            .filter(|c| c.is_alphabetic() || c.is_alphanumeric())
+    /// Text of the tokens of an analyzed text, e.g. for building queries
+    pub fn analyze_texts(&self, text: &str) -> Vec<String> {
+        self.analyze(text)
+            .into_iter()
+            .map(|token| token.text)
             .collect()
-        ;
     }
 }
 
-pub fn tokenize(input_str: &str) -> Vec<Token> {
-    input_str
-        .split_whitespace()
-        .map(Token::new)
-        .collect()
-}
\ No newline at end of file
+// Fields without a configured analyzer, and the ones of indexes written before analyzers were recorded, use the
+// original fixed pipeline
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new(
+            TokenizerConfig::Whitespace(WhitespaceTokenizer),
+            vec![
+                TokenFilterConfig::Lowercase(LowercaseFilter),
+                TokenFilterConfig::Alphanumeric(AlphanumericFilter),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index_directory::Manifest;
+    use crate::test_index::{body_docs, TestIndex};
+    use std::collections::HashMap;
+
+    fn every_filter_analyzer() -> Analyzer {
+        Analyzer::new(
+            TokenizerConfig::Unicode(UnicodeTokenizer),
+            vec![
+                TokenFilterConfig::Lowercase(LowercaseFilter),
+                TokenFilterConfig::Alphanumeric(AlphanumericFilter),
+                TokenFilterConfig::AsciiFolding(AsciiFoldingFilter),
+                TokenFilterConfig::CjkBigram(CjkBigramFilter),
+                TokenFilterConfig::NGram(NGramFilter::new(2, 3)),
+                TokenFilterConfig::Stemmer(StemmerFilter::new(Language::French)),
+                TokenFilterConfig::Stopword(StopwordFilter::new(["le".to_string(), "la".to_string()])),
+            ],
+        )
+    }
+
+    // The tokenizer of the indexes written before the analyzers were configurable
+    fn legacy_clean_tokens(text: &str) -> Vec<String> {
+        text.split_whitespace()
+            .map(|word| word.to_lowercase().chars().filter(|c| c.is_alphabetic() || c.is_alphanumeric()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn analyzers_are_serialized_with_their_configuration() {
+        let analyzer = every_filter_analyzer();
+        let json: serde_json::Value = serde_json::to_value(&analyzer).unwrap();
+
+        assert_eq!(json["tokenizer"], serde_json::json!({"type": "unicode"}));
+        assert_eq!(json["filters"][0], serde_json::json!({"type": "lowercase"}));
+        assert_eq!(json["filters"][6]["type"], "stopword");
+        assert_eq!(serde_json::from_value::<Analyzer>(json).unwrap(), analyzer);
+        assert_eq!(serde_json::from_str::<Analyzer>(&serde_json::to_string(&Analyzer::default()).unwrap()).unwrap(), Analyzer::default());
+    }
+
+    #[test]
+    fn analyzers_are_recorded_in_the_manifest() {
+        let index = TestIndex::new("analyzers_manifest");
+        let mut manifest = Manifest::load(&index.directory).unwrap();
+        manifest.set_analyzers(&HashMap::from([("title".to_string(), every_filter_analyzer())])).unwrap();
+        manifest.write(&index.directory).unwrap();
+
+        let manifest = Manifest::load(&index.directory).unwrap();
+
+        assert_eq!(manifest.analyzer("title"), every_filter_analyzer());
+        assert_eq!(manifest.analyzer("body"), Analyzer::default());
+    }
+
+    #[test]
+    fn default_analyzer_matches_the_legacy_tokenizer() {
+        let texts = [
+            "The Quick  brown\tfox",
+            "Hello, World! It's 2024...",
+            "C++ -- e-mail foo_bar @home",
+            "Ünïcödé ΣΊΣΥΦΟΣ straße 東京",
+            "",
+            "   ",
+        ];
+
+        for text in texts {
+            let tokens = Analyzer::default().analyze(text);
+            let positions: Vec<Position> = tokens.iter().map(|token| token.position).collect();
+
+            assert_eq!(Analyzer::default().analyze_texts(text), legacy_clean_tokens(text), "{:?}", text);
+            // Tokens left empty by the filters are kept, so the positions are the ones of the words
+            assert_eq!(positions, (0..legacy_clean_tokens(text).len() as Position).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn analyzers_cannot_change_once_fields_are_indexed() {
+        let index = TestIndex::new("analyzers_mismatch");
+        let unicode_analyzer = Analyzer::new(TokenizerConfig::Unicode(UnicodeTokenizer), vec![TokenFilterConfig::Lowercase(LowercaseFilter)]);
+
+        // Any analyzer can be set before the first segment
+        let mut manifest = Manifest::load(&index.directory).unwrap();
+        manifest.set_analyzers(&HashMap::from([("body".to_string(), unicode_analyzer.clone())])).unwrap();
+        manifest.set_analyzers(&HashMap::from([("body".to_string(), Analyzer::default())])).unwrap();
+        assert_eq!(manifest.analyzer("body"), Analyzer::default());
+
+        let mut indexer = index.indexer(&["body"]);
+        indexer.index(body_docs(&[("d1", "rust")])).unwrap();
+        // The same analyzer, explicitly
+        indexer.set_analyzer("body", Analyzer::default());
+        indexer.index(body_docs(&[("d2", "rust")])).unwrap();
+
+        indexer.set_analyzer("body", unicode_analyzer.clone());
+        let error = indexer.index(body_docs(&[("d3", "rust")])).unwrap_err();
+        let mut manifest = Manifest::load(&index.directory).unwrap();
+        let n_segment_directories = std::fs::read_dir(index.path()).unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().is_dir())
+            .count();
+
+        let expected_error = format!("Field body was indexed with the analyzer {:?}, and can't be indexed with {:?}", Analyzer::default(), unicode_analyzer);
+        assert_eq!(error.to_string(), expected_error);
+        assert_eq!(manifest.segments.len(), 2);
+        assert_eq!(n_segment_directories, 2);
+        assert_eq!(manifest.analyzer("body"), Analyzer::default());
+        assert!(manifest.set_analyzers(&HashMap::from([("body".to_string(), unicode_analyzer)])).is_err());
+    }
+}
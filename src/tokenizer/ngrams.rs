@@ -0,0 +1,229 @@
+// Token filters splitting the tokens into overlapping character n-grams, so that parts of words can be matched. Text in
+// Chinese, Japanese or Korean has no spaces between its words, so whole sentences would otherwise end up as single
+// tokens (or, after a Unicode tokenizer, as single ideographs), while their bigrams are a good approximation of words
+// that doesn't need a dictionary
+
+use crate::indexing::postings_codec::Position;
+use crate::tokenizer::{Token, TokenFilter};
+use serde::{Deserialize, Serialize};
+
+/// Splits the runs of CJK characters into overlapping bigrams (e.g. "東京都" into "東京" and "京都"), leaving the rest of
+/// the text as it is. Runs continue across single-character tokens at consecutive positions, since the Unicode tokenizer
+/// splits ideographs and kana into single characters, and a run of a single character is kept as a unigram. Positions
+/// are renumbered so that every bigram has its own, keeping the gaps between the original tokens
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CjkBigramFilter;
+
+impl CjkBigramFilter {
+    fn is_cjk(c: char) -> bool {
+        matches!(c,
+            '\u{1100}'..='\u{11FF}' // Hangul Jamo
+            | '\u{3040}'..='\u{309F}' // Hiragana
+            | '\u{30A0}'..='\u{30FF}' // Katakana
+            | '\u{3130}'..='\u{318F}' // Hangul Compatibility Jamo
+            | '\u{31F0}'..='\u{31FF}' // Katakana Phonetic Extensions
+            | '\u{3400}'..='\u{4DBF}' // CJK Unified Ideographs Extension A
+            | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+            | '\u{AC00}'..='\u{D7AF}' // Hangul Syllables
+            | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+            | '\u{FF66}'..='\u{FF9F}' // Halfwidth Katakana
+            | '\u{20000}'..='\u{2FA1F}' // CJK Unified Ideographs Extensions B onwards, and their supplement
+        )
+    }
+
+    /// Parts of a text, as runs of CJK or non-CJK characters along with whether they are CJK
+    fn segments(text: &str) -> Vec<(bool, String)> {
+        let mut segments: Vec<(bool, String)> = Vec::new();
+        for c in text.chars() {
+            let is_cjk = Self::is_cjk(c);
+            match segments.last_mut() {
+                Some((segment_is_cjk, segment)) if *segment_is_cjk == is_cjk => segment.push(c),
+                _ => segments.push((is_cjk, c.to_string())),
+            }
+        }
+
+        segments
+    }
+
+    /// Adds the bigrams of a run of CJK characters (or the run itself, if it has a single character), emptying it
+    fn flush_run(run: &mut Vec<char>, next_position: &mut Position, tokens: &mut Vec<Token>) {
+        let texts: Vec<String> = match run.len() {
+            0 => Vec::new(),
+            1 => vec![run.iter().collect()],
+            _ => run.windows(2).map(|bigram| bigram.iter().collect()).collect(),
+        };
+
+        for text in texts {
+            tokens.push(Token::new(text, *next_position));
+            *next_position += 1;
+        }
+        run.clear();
+    }
+}
+
+impl TokenFilter for CjkBigramFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        let mut filtered = Vec::with_capacity(tokens.len());
+        // Pending run of CJK characters, which may continue in the next token
+        let mut run = Vec::new();
+        let mut next_position = 0;
+        let mut last_position: Option<Position> = None;
+        let mut last_is_single_cjk = false;
+
+        for token in tokens {
+            let mut segments = Self::segments(&token.text);
+            if segments.is_empty() {
+                segments.push((false, String::new()));
+            }
+
+            // Tokens stacked at the same position (e.g. n-grams) are never adjacent
+            let is_adjacent = last_position.is_some_and(|last_position| token.position == last_position + 1);
+            let is_single_cjk = segments.len() == 1 && segments[0].0 && segments[0].1.chars().count() == 1;
+            if !(is_adjacent && last_is_single_cjk && is_single_cjk) {
+                Self::flush_run(&mut run, &mut next_position, &mut filtered);
+            }
+            // Keeps the gap from the previous token, if any
+            next_position = match last_position {
+                Some(last_position) => next_position + token.position.saturating_sub(last_position + 1),
+                None => token.position,
+            };
+
+            for (is_cjk, segment) in segments {
+                if is_cjk {
+                    run.extend(segment.chars());
+                } else {
+                    Self::flush_run(&mut run, &mut next_position, &mut filtered);
+                    filtered.push(Token::new(segment, next_position));
+                    next_position += 1;
+                }
+            }
+            last_position = Some(token.position);
+            last_is_single_cjk = is_single_cjk;
+        }
+        Self::flush_run(&mut run, &mut next_position, &mut filtered);
+
+        filtered
+    }
+}
+
+/// Replaces every token by its character n-grams, of every length from `min_n` to `max_n` (e.g. "rust" with 2 and 3 by
+/// "ru", "rus", "us", "ust" and "st"), so that queries match the tokens containing them. The n-grams keep the position
+/// of their token, and tokens shorter than `min_n` are kept as they are so that they can still be found
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NGramFilter {
+    min_n: usize,
+    max_n: usize,
+}
+
+impl NGramFilter {
+    pub fn new(min_n: usize, max_n: usize) -> Self {
+        let min_n = min_n.max(1);
+
+        Self { min_n, max_n: max_n.max(min_n) }
+    }
+
+    fn ngrams(&self, text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.chars().collect();
+        if chars.len() < self.min_n {
+            return vec![text.to_string()];
+        }
+
+        let n_chars = chars.len();
+        (0..n_chars)
+            .flat_map(|start| {
+                (self.min_n..=self.max_n)
+                    .take_while(move |n| start + n <= n_chars)
+                    .map(move |n| (start, n))
+            })
+            .map(|(start, n)| chars[start..start + n].iter().collect())
+            .collect()
+    }
+}
+
+impl TokenFilter for NGramFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens.into_iter()
+            .flat_map(|token| {
+                self.ngrams(&token.text)
+                    .into_iter()
+                    .map(move |ngram| Token::new(ngram, token.position))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(texts_positions: &[(&str, Position)]) -> Vec<Token> {
+        texts_positions.iter().map(|(text, position)| Token::new(text.to_string(), *position)).collect()
+    }
+
+    #[test]
+    fn cjk_bigrams_of_single_character_tokens() {
+        let filtered = CjkBigramFilter.filter(tokens(&[("東", 0), ("京", 1), ("都", 2)]));
+
+        assert_eq!(filtered, tokens(&[("東京", 0), ("京都", 1)]));
+    }
+
+    #[test]
+    fn cjk_single_character_run_is_kept() {
+        assert_eq!(CjkBigramFilter.filter(tokens(&[("日", 0)])), tokens(&[("日", 0)]));
+        assert_eq!(CjkBigramFilter.filter(tokens(&[("rust", 0), ("日", 1), ("go", 2)])), tokens(&[("rust", 0), ("日", 1), ("go", 2)]));
+    }
+
+    #[test]
+    fn cjk_bigrams_of_mixed_text() {
+        let filtered = CjkBigramFilter.filter(tokens(&[("東京tower", 0), ("rust", 1)]));
+
+        assert_eq!(filtered, tokens(&[("東京", 0), ("tower", 1), ("rust", 2)]));
+    }
+
+    #[test]
+    fn cjk_bigrams_keep_gaps() {
+        // The gap splits the run, and is kept after renumbering
+        let filtered = CjkBigramFilter.filter(tokens(&[("東", 0), ("京", 1), ("京", 3), ("都", 4), ("rust", 7)]));
+
+        assert_eq!(filtered, tokens(&[("東京", 0), ("京都", 2), ("rust", 5)]));
+    }
+
+    #[test]
+    fn cjk_bigrams_of_stacked_tokens() {
+        // Stacked tokens don't continue the run, nor move the positions back
+        let filtered = CjkBigramFilter.filter(tokens(&[("東", 0), ("京", 0), ("ab", 1), ("bc", 1)]));
+
+        assert_eq!(filtered, tokens(&[("東", 0), ("京", 1), ("ab", 2), ("bc", 3)]));
+
+        // Like the n-grams of a CJK token, where only the last one is followed by the next token
+        let ngrams = NGramFilter::new(1, 2).filter(tokens(&[("東京", 0), ("都", 1)]));
+        assert_eq!(CjkBigramFilter.filter(ngrams), tokens(&[("東", 0), ("東京", 1), ("京都", 2)]));
+    }
+
+    #[test]
+    fn ngrams_are_stacked_at_their_token_position() {
+        let filtered = NGramFilter::new(2, 3).filter(tokens(&[("rust", 0), ("go", 3)]));
+
+        assert_eq!(filtered, tokens(&[("ru", 0), ("rus", 0), ("us", 0), ("ust", 0), ("st", 0), ("go", 3)]));
+    }
+
+    #[test]
+    fn ngrams_keep_short_tokens() {
+        let filtered = NGramFilter::new(3, 3).filter(tokens(&[("a", 0), ("東京", 1), ("東京都", 2)]));
+
+        assert_eq!(filtered, tokens(&[("a", 0), ("東京", 1), ("東京都", 2)]));
+    }
+
+    #[test]
+    fn ngrams_of_mixed_text() {
+        let filtered = NGramFilter::new(2, 2).filter(tokens(&[("東京x", 5)]));
+
+        assert_eq!(filtered, tokens(&[("東京", 5), ("京x", 5)]));
+    }
+
+    #[test]
+    fn ngram_lengths_are_clamped() {
+        assert_eq!(NGramFilter::new(0, 0), NGramFilter::new(1, 1));
+        assert_eq!(NGramFilter::new(3, 2), NGramFilter::new(3, 3));
+    }
+}
@@ -0,0 +1,256 @@
+// Porter2, the Snowball English stemmer (https://snowballstem.org/algorithms/english/stemmer.html). A "y" starting the
+// word or following a vowel is a consonant, and is marked as "Y" while the word is stemmed
+
+use crate::tokenizer::stemmers::Word;
+
+// Words with irregular stems, or which would be overstemmed
+const EXCEPTIONS: [(&str, &str); 18] = [
+    ("skis", "ski"),
+    ("skies", "sky"),
+    ("dying", "die"),
+    ("lying", "lie"),
+    ("tying", "tie"),
+    ("idly", "idl"),
+    ("gently", "gentl"),
+    ("ugly", "ugli"),
+    ("early", "earli"),
+    ("only", "onli"),
+    ("singly", "singl"),
+    ("sky", "sky"),
+    ("news", "news"),
+    ("howe", "howe"),
+    ("atlas", "atlas"),
+    ("cosmos", "cosmos"),
+    ("bias", "bias"),
+    ("andes", "andes"),
+];
+
+// Words left as they are after step 1a
+const STEP_1A_INVARIANTS: [&str; 8] = [
+    "inning", "outing", "canning", "herring", "earring", "proceed", "exceed", "succeed",
+];
+
+// Prefixes after which R1 starts, since the standard definition would make it start too late for their words
+const R1_PREFIXES: [&str; 3] = ["gener", "commun", "arsen"];
+
+const STEP_2_SUFFIXES: [(&str, &str); 24] = [
+    ("tional", "tion"),
+    ("enci", "ence"),
+    ("anci", "ance"),
+    ("abli", "able"),
+    ("entli", "ent"),
+    ("izer", "ize"),
+    ("ization", "ize"),
+    ("ational", "ate"),
+    ("ation", "ate"),
+    ("ator", "ate"),
+    ("alism", "al"),
+    ("aliti", "al"),
+    ("alli", "al"),
+    ("fulness", "ful"),
+    ("ousli", "ous"),
+    ("ousness", "ous"),
+    ("iveness", "ive"),
+    ("iviti", "ive"),
+    ("biliti", "ble"),
+    ("bli", "ble"),
+    ("ogi", "og"),
+    ("fulli", "ful"),
+    ("lessli", "less"),
+    ("li", ""),
+];
+
+const STEP_3_SUFFIXES: [(&str, &str); 9] = [
+    ("tional", "tion"),
+    ("ational", "ate"),
+    ("alize", "al"),
+    ("icate", "ic"),
+    ("iciti", "ic"),
+    ("ical", "ic"),
+    ("ful", ""),
+    ("ness", ""),
+    ("ative", ""),
+];
+
+const STEP_4_SUFFIXES: [&str; 18] = [
+    "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent", "ism", "ate", "iti", "ous", "ive",
+    "ize", "ion",
+];
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y')
+}
+
+fn is_valid_li_ending(c: char) -> bool {
+    matches!(c, 'c' | 'd' | 'e' | 'g' | 'h' | 'k' | 'm' | 'n' | 'r' | 't')
+}
+
+fn ends_with_double(chars: &[char]) -> bool {
+    matches!(chars, [.., a, b] if a == b && matches!(*a, 'b' | 'd' | 'f' | 'g' | 'm' | 'n' | 'p' | 'r' | 't'))
+}
+
+/// Whether the characters end with a short syllable: a vowel followed by a non-vowel other than w, x or Y and preceded
+/// by a non-vowel, or a vowel followed by a non-vowel at the beginning of the word
+fn ends_with_short_syllable(chars: &[char]) -> bool {
+    match *chars {
+        [.., a, b, c] if !is_vowel(a) && is_vowel(b) && !is_vowel(c) && !matches!(c, 'w' | 'x' | 'Y') => true,
+        [a, b] => is_vowel(a) && !is_vowel(b),
+        _ => false,
+    }
+}
+
+pub fn stem(text: &str) -> String {
+    if let Some((_, stem)) = EXCEPTIONS.iter().find(|(word, _)| *word == text) {
+        return stem.to_string();
+    }
+
+    let mut word = Word::new(text);
+    if word.len() < 3 {
+        return text.to_string();
+    }
+
+    if word.chars[0] == '\'' {
+        word.chars.remove(0);
+    }
+    for position in 0..word.len() {
+        if word.chars[position] == 'y' && (position == 0 || is_vowel(word.chars[position - 1])) {
+            word.chars[position] = 'Y';
+        }
+    }
+
+    let r1 = R1_PREFIXES.iter()
+        .find(|prefix| word.chars.iter().copied().take(prefix.len()).eq(prefix.chars()))
+        .map(|prefix| prefix.len())
+        .unwrap_or_else(|| word.region_start(0, is_vowel));
+    let r2 = word.region_start(r1, is_vowel);
+
+    step_1a(&mut word);
+    if !STEP_1A_INVARIANTS.contains(&word.to_string().as_str()) {
+        step_1b(&mut word, r1);
+        step_1c(&mut word);
+        step_2(&mut word, r1);
+        step_3(&mut word, r1, r2);
+        step_4(&mut word, r2);
+        step_5(&mut word, r1, r2);
+    }
+
+    word.to_string().replace('Y', "y")
+}
+
+// Possessives and plurals
+fn step_1a(word: &mut Word) {
+    if let Some(suffix) = word.longest_suffix(&["'", "'s", "'s'"]) {
+        word.remove_suffix(suffix);
+    }
+
+    let Some(suffix) = word.longest_suffix(&["sses", "ied", "ies", "s", "us", "ss"]) else {
+        return;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    match suffix {
+        "sses" => word.replace_suffix(suffix, "ss"),
+        "ied" | "ies" if suffix_start > 1 => word.replace_suffix(suffix, "i"),
+        "ied" | "ies" => word.replace_suffix(suffix, "ie"),
+        // Removed if there is a vowel before the letter preceding it, e.g. "gaps" but not "gas"
+        "s" if suffix_start > 1 && word.chars[..suffix_start - 1].iter().any(|&c| is_vowel(c)) => {
+            word.remove_suffix(suffix)
+        }
+        _ => {}
+    }
+}
+
+// Past participles and gerunds
+fn step_1b(word: &mut Word, r1: usize) {
+    let Some(suffix) = word.longest_suffix(&["eed", "eedly", "ed", "edly", "ing", "ingly"]) else {
+        return;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    if suffix == "eed" || suffix == "eedly" {
+        if suffix_start >= r1 {
+            word.replace_suffix(suffix, "ee");
+        }
+        return;
+    }
+    if !word.chars[..suffix_start].iter().any(|&c| is_vowel(c)) {
+        return;
+    }
+
+    word.remove_suffix(suffix);
+    if word.ends_with("at") || word.ends_with("bl") || word.ends_with("iz") {
+        word.chars.push('e');
+    } else if ends_with_double(&word.chars) {
+        word.chars.pop();
+    } else if word.len() == r1 && ends_with_short_syllable(&word.chars) {
+        word.chars.push('e');
+    }
+}
+
+fn step_1c(word: &mut Word) {
+    let len = word.len();
+    if len > 2 && matches!(word.chars[len - 1], 'y' | 'Y') && !is_vowel(word.chars[len - 2]) {
+        word.chars[len - 1] = 'i';
+    }
+}
+
+fn step_2(word: &mut Word, r1: usize) {
+    let suffixes = STEP_2_SUFFIXES.map(|(suffix, _)| suffix);
+    let Some(suffix) = word.longest_suffix(&suffixes) else {
+        return;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    if suffix_start < r1 {
+        return;
+    }
+
+    let preceding = word.char_before(suffix_start);
+    let (_, replacement) = STEP_2_SUFFIXES.iter().find(|(step_suffix, _)| *step_suffix == suffix).unwrap();
+    match suffix {
+        "ogi" if preceding != Some('l') => {}
+        "li" if !preceding.is_some_and(is_valid_li_ending) => {}
+        _ => word.replace_suffix(suffix, replacement),
+    }
+}
+
+fn step_3(word: &mut Word, r1: usize, r2: usize) {
+    let suffixes = STEP_3_SUFFIXES.map(|(suffix, _)| suffix);
+    let Some(suffix) = word.longest_suffix(&suffixes) else {
+        return;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    if suffix_start < r1 || (suffix == "ative" && suffix_start < r2) {
+        return;
+    }
+
+    let (_, replacement) = STEP_3_SUFFIXES.iter().find(|(step_suffix, _)| *step_suffix == suffix).unwrap();
+    word.replace_suffix(suffix, replacement);
+}
+
+fn step_4(word: &mut Word, r2: usize) {
+    let Some(suffix) = word.longest_suffix(&STEP_4_SUFFIXES) else {
+        return;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    if suffix_start < r2 {
+        return;
+    }
+
+    if suffix != "ion" || matches!(word.char_before(suffix_start), Some('s' | 't')) {
+        word.remove_suffix(suffix);
+    }
+}
+
+fn step_5(word: &mut Word, r1: usize, r2: usize) {
+    let Some(&last) = word.chars.last() else {
+        return;
+    };
+    let last_position = word.len() - 1;
+    let remove = match last {
+        'e' => last_position >= r2 || (last_position >= r1 && !ends_with_short_syllable(&word.chars[..last_position])),
+        'l' => last_position >= r2 && word.char_before(last_position) == Some('l'),
+        _ => false,
+    };
+
+    if remove {
+        word.chars.pop();
+    }
+}
@@ -0,0 +1,290 @@
+// Snowball French stemmer (https://snowballstem.org/algorithms/french/stemmer.html). A "u" or "i" between vowels, a "y"
+// next to a vowel and a "u" after "q" are consonants, and are marked as "U", "I" and "Y" while the word is stemmed
+
+use crate::tokenizer::stemmers::Word;
+
+const STANDARD_SUFFIXES: [&str; 43] = [
+    "ance", "iqUe", "isme", "able", "iste", "eux", "ances", "iqUes", "ismes", "ables", "istes", "atrice", "ateur",
+    "ation", "atrices", "ateurs", "ations", "logie", "logies", "usion", "ution", "usions", "utions", "ence", "ences",
+    "ement", "ements", "ité", "ités", "if", "ive", "ifs", "ives", "eaux", "aux", "euse", "euses", "issement",
+    "issements", "amment", "emment", "ment", "ments",
+];
+
+const I_VERB_SUFFIXES: [&str; 35] = [
+    "îmes", "ît", "îtes", "i", "ie", "ies", "ir", "ira", "irai", "iraIent", "irais", "irait", "iras", "irent", "irez",
+    "iriez", "irions", "irons", "iront", "is", "issaIent", "issais", "issait", "issant", "issante", "issantes",
+    "issants", "isse", "issent", "isses", "issez", "issiez", "issions", "issons", "it",
+];
+
+const VERB_SUFFIXES: [&str; 38] = [
+    "ions", "é", "ée", "ées", "és", "èrent", "er", "era", "erai", "eraIent", "erais", "erait", "eras", "erez", "eriez",
+    "erions", "erons", "eront", "ez", "iez", "âmes", "ât", "âtes", "a", "ai", "aIent", "ais", "ait", "ant", "ante",
+    "antes", "ants", "as", "asse", "assent", "asses", "assiez", "assions",
+];
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'â' | 'à' | 'ë' | 'é' | 'ê' | 'è' | 'ï' | 'î' | 'ô' | 'û' | 'ù')
+}
+
+pub fn stem(text: &str) -> String {
+    let mut word = Word::new(text);
+    mark_consonants(&mut word);
+    let rv = rv_start(&word);
+    let r1 = word.region_start(0, is_vowel);
+    let r2 = word.region_start(r1, is_vowel);
+
+    if standard_suffix(&mut word, rv, r1, r2) || i_verb_suffix(&mut word, rv) || verb_suffix(&mut word, rv, r2) {
+        match word.chars.last() {
+            Some('Y') => word.replace_suffix("Y", "i"),
+            Some('ç') => word.replace_suffix("ç", "c"),
+            _ => {}
+        }
+    } else {
+        residual_suffix(&mut word, rv, r2);
+    }
+
+    // Undoubles the final consonant
+    if word.longest_suffix(&["enn", "onn", "ett", "ell", "eill"]).is_some() {
+        word.chars.pop();
+    }
+    // Removes the accent of an "é" or "è" followed by non-vowels at the end of the word
+    let final_non_vowels = word.chars.iter().rev().take_while(|&&c| !is_vowel(c)).count();
+    if final_non_vowels > 0 && final_non_vowels < word.len() {
+        let position = word.len() - final_non_vowels - 1;
+        if matches!(word.chars[position], 'é' | 'è') {
+            word.chars[position] = 'e';
+        }
+    }
+
+    word.chars
+        .iter()
+        .map(|&c| match c {
+            'I' => 'i',
+            'U' => 'u',
+            'Y' => 'y',
+            c => c,
+        })
+        .collect()
+}
+
+fn mark_consonants(word: &mut Word) {
+    let mut position = 0;
+    while position < word.len() {
+        match consonant_at(&word.chars, position) {
+            Some(consonant) => word.chars[consonant] = word.chars[consonant].to_ascii_uppercase(),
+            None => position += 1,
+        }
+    }
+}
+
+/// Position of the consonant to be marked at a position of the word, if there is one
+fn consonant_at(chars: &[char], position: usize) -> Option<usize> {
+    let is_vowel_at = |position: usize| chars.get(position).is_some_and(|&c| is_vowel(c));
+    let next = chars.get(position + 1).copied();
+
+    if is_vowel(chars[position]) {
+        match next {
+            Some('u' | 'i') if is_vowel_at(position + 2) => return Some(position + 1),
+            Some('y') => return Some(position + 1),
+            _ => {}
+        }
+    }
+    match (chars[position], next) {
+        ('y', _) if is_vowel_at(position + 1) => Some(position),
+        ('q', Some('u')) => Some(position + 1),
+        _ => None,
+    }
+}
+
+/// Start of RV: after the third letter if the word begins with two vowels or with "par", "col" or "tap", and after the
+/// first vowel not at the beginning of the word otherwise
+fn rv_start(word: &Word) -> usize {
+    let begins_with_vowels = word.len() >= 3 && is_vowel(word.chars[0]) && is_vowel(word.chars[1]);
+    let begins_with_prefix = ["par", "col", "tap"].iter()
+        .any(|prefix| word.chars.iter().take(3).copied().eq(prefix.chars()));
+    if begins_with_vowels || begins_with_prefix {
+        return 3;
+    }
+
+    word.position_after(1, is_vowel).unwrap_or(word.len())
+}
+
+// Step 1: standard suffixes. Returns whether the step succeeded, which it doesn't after replacing the adverb endings,
+// so that the verb suffixes they may follow are still removed
+fn standard_suffix(word: &mut Word, rv: usize, r1: usize, r2: usize) -> bool {
+    let Some(suffix) = word.longest_suffix(&STANDARD_SUFFIXES) else {
+        return false;
+    };
+    let suffix_start = word.suffix_start(suffix);
+
+    match suffix {
+        "atrice" | "ateur" | "ation" | "atrices" | "ateurs" | "ations" => {
+            if !word.remove_suffix_in(suffix, r2) {
+                return false;
+            }
+            if word.ends_with("ic") && !word.remove_suffix_in("ic", r2) {
+                word.replace_suffix("ic", "iqU");
+            }
+            true
+        }
+        "logie" | "logies" => word.replace_suffix_in(suffix, "log", r2),
+        "usion" | "ution" | "usions" | "utions" => word.replace_suffix_in(suffix, "u", r2),
+        "ence" | "ences" => word.replace_suffix_in(suffix, "ent", r2),
+        "ement" | "ements" => {
+            if !word.remove_suffix_in(suffix, rv) {
+                return false;
+            }
+            match word.longest_suffix(&["iv", "eus", "abl", "ic", "ièr", "Ièr"]) {
+                Some("iv") => {
+                    let removed = word.remove_suffix_in("iv", r2);
+                    if removed {
+                        word.remove_suffix_in("at", r2);
+                    }
+                }
+                Some("eus") => {
+                    let removed = word.remove_suffix_in("eus", r2);
+                    if !removed {
+                        word.replace_suffix_in("eus", "eux", r1);
+                    }
+                }
+                Some(suffix @ ("abl" | "ic")) => {
+                    word.remove_suffix_in(suffix, r2);
+                }
+                Some(suffix) => {
+                    word.replace_suffix_in(suffix, "i", rv);
+                }
+                None => {}
+            }
+            true
+        }
+        "ité" | "ités" => {
+            if !word.remove_suffix_in(suffix, r2) {
+                return false;
+            }
+            match word.longest_suffix(&["abil", "ic", "iv"]) {
+                Some("abil") => {
+                    let removed = word.remove_suffix_in("abil", r2);
+                    if !removed {
+                        word.replace_suffix("abil", "abl");
+                    }
+                }
+                Some("ic") => {
+                    let removed = word.remove_suffix_in("ic", r2);
+                    if !removed {
+                        word.replace_suffix("ic", "iqU");
+                    }
+                }
+                Some(suffix) => {
+                    word.remove_suffix_in(suffix, r2);
+                }
+                None => {}
+            }
+            true
+        }
+        "if" | "ive" | "ifs" | "ives" => {
+            if !word.remove_suffix_in(suffix, r2) {
+                return false;
+            }
+            if word.remove_suffix_in("at", r2) && word.ends_with("ic") && !word.remove_suffix_in("ic", r2) {
+                word.replace_suffix("ic", "iqU");
+            }
+            true
+        }
+        "eaux" => {
+            word.replace_suffix(suffix, "eau");
+            true
+        }
+        "aux" => word.replace_suffix_in(suffix, "al", r1),
+        "euse" | "euses" => word.remove_suffix_in(suffix, r2) || word.replace_suffix_in(suffix, "eux", r1),
+        "issement" | "issements" => {
+            let removed = suffix_start >= r1 && word.char_before(suffix_start).is_some_and(|c| !is_vowel(c));
+            if removed {
+                word.remove_suffix(suffix);
+            }
+            removed
+        }
+        "amment" => {
+            word.replace_suffix_in(suffix, "ant", rv);
+            false
+        }
+        "emment" => {
+            word.replace_suffix_in(suffix, "ent", rv);
+            false
+        }
+        "ment" | "ments" => {
+            // Removed after a vowel in RV
+            if suffix_start > rv && is_vowel(word.chars[suffix_start - 1]) {
+                word.remove_suffix(suffix);
+            }
+            false
+        }
+        _ => word.remove_suffix_in(suffix, r2),
+    }
+}
+
+// Step 2a: verb suffixes beginning with "i", removed after a non-vowel in RV
+fn i_verb_suffix(word: &mut Word, rv: usize) -> bool {
+    let Some(suffix) = word.longest_suffix_in(&I_VERB_SUFFIXES, rv) else {
+        return false;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    if suffix_start <= rv || is_vowel(word.chars[suffix_start - 1]) {
+        return false;
+    }
+
+    word.remove_suffix(suffix);
+    true
+}
+
+// Step 2b: other verb suffixes
+fn verb_suffix(word: &mut Word, rv: usize, r2: usize) -> bool {
+    let Some(suffix) = word.longest_suffix_in(&VERB_SUFFIXES, rv) else {
+        return false;
+    };
+
+    match suffix {
+        "ions" => word.remove_suffix_in(suffix, r2),
+        "âmes" | "ât" | "âtes" | "a" | "ai" | "aIent" | "ais" | "ait" | "ant" | "ante" | "antes" | "ants" | "as"
+        | "asse" | "assent" | "asses" | "assiez" | "assions" => {
+            word.remove_suffix(suffix);
+            word.remove_suffix_in("e", rv);
+            true
+        }
+        _ => {
+            word.remove_suffix(suffix);
+            true
+        }
+    }
+}
+
+// Step 4: residual suffixes, when no other suffix was removed
+fn residual_suffix(word: &mut Word, rv: usize, r2: usize) {
+    // A final "s" is removed, except after the letters which usually keep it
+    if word.ends_with("s") {
+        let before_s = word.char_before(word.len() - 1);
+        if before_s.is_some_and(|c| !matches!(c, 'a' | 'i' | 'o' | 'u' | 'è' | 's')) {
+            word.chars.pop();
+        }
+    }
+
+    let Some(suffix) = word.longest_suffix_in(&["ion", "ier", "ière", "Ier", "Ière", "e", "ë"], rv) else {
+        return;
+    };
+    let suffix_start = word.suffix_start(suffix);
+
+    match suffix {
+        "ion" => {
+            // The "s" or "t" must also be in RV
+            if suffix_start >= r2 && suffix_start > rv && matches!(word.chars[suffix_start - 1], 's' | 't') {
+                word.remove_suffix(suffix);
+            }
+        }
+        "e" => word.remove_suffix(suffix),
+        "ë" => {
+            if suffix_start >= rv + 2 && word.ends_with_at(suffix_start, "gu") {
+                word.remove_suffix(suffix);
+            }
+        }
+        _ => word.replace_suffix(suffix, "i"),
+    }
+}
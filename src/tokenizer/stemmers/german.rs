@@ -0,0 +1,133 @@
+// Snowball German stemmer (https://snowballstem.org/algorithms/german/stemmer.html). A "u" or "y" between vowels is a
+// consonant, and is marked as "U" or "Y" while the word is stemmed
+
+use crate::tokenizer::stemmers::Word;
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'ä' | 'ö' | 'ü')
+}
+
+fn is_s_ending(c: char) -> bool {
+    matches!(c, 'b' | 'd' | 'f' | 'g' | 'h' | 'k' | 'l' | 'm' | 'n' | 'r' | 't')
+}
+
+fn is_st_ending(c: char) -> bool {
+    c != 'r' && is_s_ending(c)
+}
+
+pub fn stem(text: &str) -> String {
+    let mut word = Word::new(&text.replace('ß', "ss"));
+    for position in 1..word.len().saturating_sub(1) {
+        if is_vowel(word.chars[position - 1]) && is_vowel(word.chars[position + 1]) {
+            match word.chars[position] {
+                'u' => word.chars[position] = 'U',
+                'y' => word.chars[position] = 'Y',
+                _ => {}
+            }
+        }
+    }
+
+    // R1 is adjusted so that at least 3 letters precede it, but R2 is still found from its unadjusted start
+    let r1 = word.region_start(0, is_vowel);
+    let r2 = word.region_start(r1, is_vowel);
+    let r1 = r1.max(3);
+
+    step_1(&mut word, r1);
+    step_2(&mut word, r1);
+    step_3(&mut word, r1, r2);
+
+    word.chars
+        .iter()
+        .map(|&c| match c {
+            'U' | 'ü' => 'u',
+            'Y' => 'y',
+            'ä' => 'a',
+            'ö' => 'o',
+            c => c,
+        })
+        .collect()
+}
+
+fn step_1(word: &mut Word, r1: usize) {
+    let Some(suffix) = word.longest_suffix(&["em", "ern", "er", "e", "en", "es", "s"]) else {
+        return;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    if suffix_start < r1 {
+        return;
+    }
+
+    match suffix {
+        "s" => {
+            if word.char_before(suffix_start).is_some_and(is_s_ending) {
+                word.remove_suffix(suffix);
+            }
+        }
+        "e" | "en" | "es" => {
+            word.remove_suffix(suffix);
+            if word.ends_with("niss") {
+                word.chars.pop();
+            }
+        }
+        _ => word.remove_suffix(suffix),
+    }
+}
+
+fn step_2(word: &mut Word, r1: usize) {
+    let Some(suffix) = word.longest_suffix(&["en", "er", "est", "st"]) else {
+        return;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    if suffix_start < r1 {
+        return;
+    }
+
+    // "st" must be preceded by a valid ending, itself preceded by at least 3 letters
+    if suffix != "st" || (suffix_start > 3 && word.char_before(suffix_start).is_some_and(is_st_ending)) {
+        word.remove_suffix(suffix);
+    }
+}
+
+// Derivational suffixes
+fn step_3(word: &mut Word, r1: usize, r2: usize) {
+    let Some(suffix) = word.longest_suffix(&["end", "ung", "ig", "ik", "isch", "lich", "heit", "keit"]) else {
+        return;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    if suffix_start < r2 {
+        return;
+    }
+
+    match suffix {
+        "end" | "ung" => {
+            word.remove_suffix(suffix);
+            if word.ends_with("ig") {
+                let ig_start = word.suffix_start("ig");
+                if ig_start >= r2 && word.char_before(ig_start) != Some('e') {
+                    word.remove_suffix("ig");
+                }
+            }
+        }
+        "ig" | "ik" | "isch" => {
+            if word.char_before(suffix_start) != Some('e') {
+                word.remove_suffix(suffix);
+            }
+        }
+        "lich" | "heit" => {
+            word.remove_suffix(suffix);
+            if let Some(suffix) = word.longest_suffix(&["er", "en"]) {
+                if word.suffix_start(suffix) >= r1 {
+                    word.remove_suffix(suffix);
+                }
+            }
+        }
+        _ => {
+            word.remove_suffix(suffix);
+            if let Some(suffix) = word.longest_suffix(&["lich", "ig"]) {
+                if word.suffix_start(suffix) >= r2 {
+                    word.remove_suffix(suffix);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// Snowball Italian stemmer (https://snowballstem.org/algorithms/italian/stemmer.html). Acute accents are replaced by
+// grave ones, and a "u" after "q" or a "u" or "i" between vowels are consonants, marked as "U" or "I" while the word is
+// stemmed
+
+use crate::tokenizer::stemmers::Word;
+
+const PRONOUNS: [&str; 37] = [
+    "ci", "gli", "la", "le", "li", "lo", "mi", "ne", "si", "ti", "vi", "sene", "gliela", "gliele", "glieli", "glielo",
+    "gliene", "mela", "mele", "meli", "melo", "mene", "tela", "tele", "teli", "telo", "tene", "cela", "cele", "celi",
+    "celo", "cene", "vela", "vele", "veli", "velo", "vene",
+];
+
+const STANDARD_SUFFIXES: [&str; 51] = [
+    "anza", "anze", "ico", "ici", "ica", "ice", "iche", "ichi", "ismo", "ismi", "abile", "abili", "ibile", "ibili",
+    "ista", "iste", "isti", "istà", "istè", "istì", "oso", "osi", "osa", "ose", "mente", "atrice", "atrici", "ante",
+    "anti", "azione", "azioni", "atore", "atori", "logia", "logie", "uzione", "uzioni", "usione", "usioni", "enza",
+    "enze", "amento", "amenti", "imento", "imenti", "amente", "ità", "ivo", "ivi", "iva", "ive",
+];
+
+const VERB_SUFFIXES: [&str; 86] = [
+    "ammo", "ando", "ano", "are", "arono", "asse", "assero", "assi", "assimo", "ata", "ate", "ati", "ato", "ava",
+    "avamo", "avano", "avate", "avi", "avo", "emmo", "enda", "ende", "endi", "endo", "erà", "erai", "eranno", "ere",
+    "erebbe", "erebbero", "erei", "eremmo", "eremo", "ereste", "eresti", "erete", "erò", "erono", "essero", "ete",
+    "eva", "evamo", "evano", "evate", "evi", "evo", "iamo", "immo", "irà", "irai", "iranno", "ire", "irebbe",
+    "irebbero", "irei", "iremmo", "iremo", "ireste", "iresti", "irete", "irò", "irono", "isca", "iscano", "isce",
+    "isci", "isco", "iscono", "issero", "ita", "ite", "iti", "ito", "iva", "ivamo", "ivano", "ivate", "ivi", "ivo",
+    "ono", "uta", "ute", "uti", "uto", "ar", "ir",
+];
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'à' | 'è' | 'ì' | 'ò' | 'ù')
+}
+
+pub fn stem(text: &str) -> String {
+    let chars = text.chars()
+        .map(|c| match c {
+            'á' => 'à',
+            'é' => 'è',
+            'í' => 'ì',
+            'ó' => 'ò',
+            'ú' => 'ù',
+            c => c,
+        })
+        .collect::<String>();
+    let mut word = Word::new(&chars.replace("qu", "qU"));
+    for position in 1..word.len().saturating_sub(1) {
+        if is_vowel(word.chars[position - 1]) && is_vowel(word.chars[position + 1]) {
+            match word.chars[position] {
+                'u' => word.chars[position] = 'U',
+                'i' => word.chars[position] = 'I',
+                _ => {}
+            }
+        }
+    }
+
+    let rv = word.rv_start(is_vowel);
+    let r1 = word.region_start(0, is_vowel);
+    let r2 = word.region_start(r1, is_vowel);
+
+    attached_pronoun(&mut word, rv);
+    if !standard_suffix(&mut word, rv, r1, r2) {
+        verb_suffix(&mut word, rv);
+    }
+
+    // Removes a final vowel in RV, and then an "i" in RV before it
+    if word.len() > rv && matches!(word.chars[word.len() - 1], 'a' | 'e' | 'i' | 'o' | 'à' | 'è' | 'ì' | 'ò') {
+        word.chars.pop();
+        word.remove_suffix_in("i", rv);
+    }
+    // Replaces a final "ch" or "gh" in RV by "c" or "g"
+    if word.ends_with("ch") || word.ends_with("gh") {
+        word.remove_suffix_in("h", rv + 1);
+    }
+
+    word.chars
+        .iter()
+        .map(|&c| match c {
+            'I' => 'i',
+            'U' => 'u',
+            c => c,
+        })
+        .collect()
+}
+
+// Step 0: pronouns attached to gerunds and infinitives
+fn attached_pronoun(word: &mut Word, rv: usize) {
+    let Some(pronoun) = word.longest_suffix(&PRONOUNS) else {
+        return;
+    };
+    let pronoun_start = word.suffix_start(pronoun);
+    let Some(verb_form) = word.longest_suffix_at(pronoun_start, &["ando", "endo", "ar", "er", "ir"]) else {
+        return;
+    };
+    if pronoun_start - verb_form.chars().count() < rv {
+        return;
+    }
+
+    match verb_form {
+        "ando" | "endo" => word.remove_suffix(pronoun),
+        _ => word.replace_suffix(pronoun, "e"),
+    }
+}
+
+// Step 1: standard suffixes. Returns whether one was removed
+fn standard_suffix(word: &mut Word, rv: usize, r1: usize, r2: usize) -> bool {
+    let Some(suffix) = word.longest_suffix(&STANDARD_SUFFIXES) else {
+        return false;
+    };
+
+    match suffix {
+        "azione" | "azioni" | "atore" | "atori" => {
+            if !word.remove_suffix_in(suffix, r2) {
+                return false;
+            }
+            word.remove_suffix_in("ic", r2);
+            true
+        }
+        "logia" | "logie" => word.replace_suffix_in(suffix, "log", r2),
+        "uzione" | "uzioni" | "usione" | "usioni" => word.replace_suffix_in(suffix, "u", r2),
+        "enza" | "enze" => word.replace_suffix_in(suffix, "ente", r2),
+        "amento" | "amenti" | "imento" | "imenti" => word.remove_suffix_in(suffix, rv),
+        "amente" => {
+            if !word.remove_suffix_in(suffix, r1) {
+                return false;
+            }
+            if let Some(suffix) = word.longest_suffix(&["iv", "os", "ic", "abil"]) {
+                if word.remove_suffix_in(suffix, r2) && suffix == "iv" {
+                    word.remove_suffix_in("at", r2);
+                }
+            }
+            true
+        }
+        "ità" => {
+            if !word.remove_suffix_in(suffix, r2) {
+                return false;
+            }
+            if let Some(suffix) = word.longest_suffix(&["abil", "ic", "iv"]) {
+                word.remove_suffix_in(suffix, r2);
+            }
+            true
+        }
+        "ivo" | "ivi" | "iva" | "ive" => {
+            if !word.remove_suffix_in(suffix, r2) {
+                return false;
+            }
+            if word.remove_suffix_in("at", r2) {
+                word.remove_suffix_in("ic", r2);
+            }
+            true
+        }
+        _ => word.remove_suffix_in(suffix, r2),
+    }
+}
+
+// Step 2: verb suffixes, when no standard suffix was removed
+fn verb_suffix(word: &mut Word, rv: usize) {
+    if let Some(suffix) = word.longest_suffix_in(&VERB_SUFFIXES, rv) {
+        word.remove_suffix(suffix);
+    }
+}
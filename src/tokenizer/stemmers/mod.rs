@@ -0,0 +1,252 @@
+// Stemming token filter, reducing the inflected forms of a word to a common stem (e.g. "running", "runs" and "run" are
+// all indexed as "run"), so that queries match every form of their words. The stemmers are in-crate implementations of
+// the Snowball algorithms (https://snowballstem.org/algorithms/), one module per language
+//
+// The algorithms are written over the characters of a lowercased word, in terms of regions of it: R1 and R2 (the parts
+// after the first and second non-vowel following a vowel) and, for the Romance languages, RV. Suffixes are only removed
+// when they are in the region their rule requires
+
+mod english;
+mod french;
+mod german;
+mod italian;
+mod spanish;
+mod swedish;
+
+use crate::tokenizer::{Language, Token, TokenFilter};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StemmerFilter {
+    language: Language,
+}
+
+impl StemmerFilter {
+    pub fn new(language: Language) -> Self {
+        Self { language }
+    }
+
+    fn stem(&self, word: &str) -> String {
+        match self.language {
+            // Porter2
+            Language::English => english::stem(word),
+            Language::French => french::stem(word),
+            Language::German => german::stem(word),
+            Language::Italian => italian::stem(word),
+            Language::Spanish => spanish::stem(word),
+            Language::Swedish => swedish::stem(word),
+        }
+    }
+}
+
+impl TokenFilter for StemmerFilter {
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens.into_iter()
+            .map(|token| Token::new(self.stem(&token.text), token.position))
+            .collect()
+    }
+}
+
+/// Word being stemmed, as characters so that the regions and suffixes of non-ASCII words are counted in letters
+struct Word {
+    chars: Vec<char>,
+}
+
+impl Word {
+    fn new(word: &str) -> Self {
+        Self { chars: word.chars().collect() }
+    }
+
+    fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    fn ends_with(&self, suffix: &str) -> bool {
+        self.ends_with_at(self.len(), suffix)
+    }
+
+    /// Whether the first `end` characters of the word end with the suffix
+    fn ends_with_at(&self, end: usize, suffix: &str) -> bool {
+        let suffix_len = suffix.chars().count();
+        suffix_len <= end && self.chars[end - suffix_len..end].iter().copied().eq(suffix.chars())
+    }
+
+    /// Position where a suffix of the word starts
+    fn suffix_start(&self, suffix: &str) -> usize {
+        self.len() - suffix.chars().count()
+    }
+
+    /// Longest of the suffixes the word ends with
+    fn longest_suffix<'a>(&self, suffixes: &[&'a str]) -> Option<&'a str> {
+        self.longest_suffix_at(self.len(), suffixes)
+    }
+
+    /// Longest of the suffixes the first `end` characters of the word end with
+    fn longest_suffix_at<'a>(&self, end: usize, suffixes: &[&'a str]) -> Option<&'a str> {
+        suffixes.iter()
+            .copied()
+            .filter(|suffix| self.ends_with_at(end, suffix))
+            .max_by_key(|suffix| suffix.chars().count())
+    }
+
+    /// Longest of the suffixes the word ends with which lie entirely in the region starting at `region_start`
+    fn longest_suffix_in<'a>(&self, suffixes: &[&'a str], region_start: usize) -> Option<&'a str> {
+        suffixes.iter()
+            .copied()
+            .filter(|suffix| self.ends_with(suffix) && self.suffix_start(suffix) >= region_start)
+            .max_by_key(|suffix| suffix.chars().count())
+    }
+
+    fn replace_suffix(&mut self, suffix: &str, replacement: &str) {
+        self.chars.truncate(self.suffix_start(suffix));
+        self.chars.extend(replacement.chars());
+    }
+
+    fn remove_suffix(&mut self, suffix: &str) {
+        self.replace_suffix(suffix, "");
+    }
+
+    /// Replaces the suffix if the word ends with it in the region starting at `region_start`. Returns whether it did
+    fn replace_suffix_in(&mut self, suffix: &str, replacement: &str, region_start: usize) -> bool {
+        let replaced = self.ends_with(suffix) && self.suffix_start(suffix) >= region_start;
+        if replaced {
+            self.replace_suffix(suffix, replacement);
+        }
+
+        replaced
+    }
+
+    fn remove_suffix_in(&mut self, suffix: &str, region_start: usize) -> bool {
+        self.replace_suffix_in(suffix, "", region_start)
+    }
+
+    /// Character right before a position of the word
+    fn char_before(&self, position: usize) -> Option<char> {
+        position.checked_sub(1).map(|position| self.chars[position])
+    }
+
+    /// Position right after the first character from `start` matching the predicate
+    fn position_after(&self, start: usize, predicate: impl Fn(char) -> bool) -> Option<usize> {
+        (start..self.len())
+            .find(|&position| predicate(self.chars[position]))
+            .map(|position| position + 1)
+    }
+
+    /// Start of the region after the first non-vowel following a vowel from `start` (R1 from the beginning of the word,
+    /// and R2 from R1), or the end of the word if there is none
+    fn region_start(&self, start: usize, is_vowel: fn(char) -> bool) -> usize {
+        self.position_after(start, is_vowel)
+            .and_then(|position| self.position_after(position, |c| !is_vowel(c)))
+            .unwrap_or(self.len())
+    }
+
+    /// Start of RV in Spanish and Italian: after the next vowel if the second letter is a consonant, after the next
+    /// consonant if the first two letters are vowels, and after the third letter otherwise
+    fn rv_start(&self, is_vowel: fn(char) -> bool) -> usize {
+        if self.len() < 2 {
+            return self.len();
+        }
+
+        let rv_start = match (is_vowel(self.chars[0]), is_vowel(self.chars[1])) {
+            (_, false) => self.position_after(2, is_vowel),
+            (true, true) => self.position_after(2, |c| !is_vowel(c)),
+            (false, true) => Some(3).filter(|&position| position <= self.len()),
+        };
+
+        rv_start.unwrap_or(self.len())
+    }
+}
+
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.chars.iter().collect::<String>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Samples of the vocabularies of every algorithm (https://snowballstem.org/algorithms/), along with their stems
+    fn assert_stems(language: Language, samples: &[(&str, &str)]) {
+        let stemmer = StemmerFilter::new(language);
+        for (word, expected_stem) in samples {
+            assert_eq!(stemmer.stem(word), *expected_stem, "stem of {:?}", word);
+        }
+    }
+
+    #[test]
+    fn english_stems() {
+        assert_stems(Language::English, &[
+            // Exceptional forms
+            ("skies", "sky"), ("dying", "die"), ("lying", "lie"), ("news", "news"), ("skis", "ski"), ("idly", "idl"),
+            ("gently", "gentl"), ("early", "earli"), ("only", "onli"), ("proceed", "proceed"), ("inning", "inning"),
+            // R1 starting after the "gener", "commun" and "arsen" prefixes, and R2 bounding step 4
+            ("generate", "generat"), ("generates", "generat"), ("generously", "generous"), ("communication", "communic"),
+            ("communism", "communism"), ("arsenal", "arsenal"),
+            ("consign", "consign"), ("consigned", "consign"), ("consignment", "consign"), ("consistency", "consist"),
+            ("consistently", "consist"), ("consolation", "consol"), ("consolatory", "consolatori"),
+            ("consolidate", "consolid"), ("consolingly", "consol"), ("conspicuously", "conspicu"),
+            ("conspiracy", "conspiraci"), ("conspirators", "conspir"), ("constables", "constabl"), ("constancy", "constanc"),
+            ("knackeries", "knackeri"), ("knavish", "knavish"), ("kneeling", "kneel"), ("knightly", "knight"),
+            ("knitting", "knit"), ("knives", "knive"), ("knocker", "knocker"), ("knocking", "knock"),
+            ("caresses", "caress"), ("ponies", "poni"), ("agreed", "agre"), ("hopping", "hop"), ("hoping", "hope"),
+            ("filing", "file"), ("happy", "happi"), ("relational", "relat"), ("conditional", "condit"),
+        ]);
+    }
+
+    #[test]
+    fn french_stems() {
+        assert_stems(Language::French, &[
+            ("continu", "continu"), ("continua", "continu"), ("continuait", "continu"), ("continuant", "continu"),
+            ("continuation", "continu"), ("continuel", "continuel"), ("continuellement", "continuel"),
+            ("continuer", "continu"), ("continuez", "continu"), ("continuité", "continu"),
+            ("contorsions", "contors"), ("contournait", "contourn"), ("contractée", "contract"),
+            ("contradictoirement", "contradictoir"), ("contraindre", "contraindr"), ("contraintes", "contraint"),
+            ("contraria", "contrari"), ("maintenant", "mainten"), ("maintenue", "maintenu"), ("mairie", "mair"),
+            ("maîtresses", "maîtress"), ("majestueusement", "majestu"), ("majorité", "major"), ("maladies", "malad"),
+        ]);
+    }
+
+    #[test]
+    fn german_stems() {
+        assert_stems(Language::German, &[
+            ("aufeinander", "aufeinand"), ("aufeinanderfolgen", "aufeinanderfolg"),
+            ("aufeinanderfolgenden", "aufeinanderfolg"), ("aufeinanderfolgten", "aufeinanderfolgt"),
+            ("aufeinanderschlügen", "aufeinanderschlug"), ("aufenthaltes", "aufenthalt"), ("auferlegten", "auferlegt"),
+            ("auferstandene", "auferstand"), ("auferstehung", "aufersteh"), ("auferstünde", "auferstund"),
+            ("auffallenden", "auffall"), ("auffällig", "auffall"), ("auffälliges", "auffall"), ("häuser", "haus"),
+        ]);
+    }
+
+    #[test]
+    fn italian_stems() {
+        assert_stems(Language::Italian, &[
+            ("abbandonata", "abbandon"), ("abbandonerà", "abbandon"), ("abbandoneranno", "abbandon"),
+            ("abbassamento", "abbass"), ("abbassandola", "abbass"), ("abbassarono", "abbass"), ("abbassarsi", "abbass"),
+            ("abbassassero", "abbass"), ("abbastanza", "abbast"), ("abbattimento", "abbatt"), ("abbattuto", "abbatt"),
+            ("pronunciata", "pronunc"), ("pronunziarle", "pronunz"), ("propagazione", "propag"),
+            ("propensione", "propension"), ("proponendosi", "propon"), ("proponevano", "propon"),
+        ]);
+    }
+
+    #[test]
+    fn spanish_stems() {
+        assert_stems(Language::Spanish, &[
+            ("chica", "chic"), ("chicharrón", "chicharron"), ("chicle", "chicl"), ("chifladuras", "chifladur"),
+            ("chilenos", "chilen"), ("chillante", "chillant"), ("chillidos", "chill"), ("chimeneas", "chimen"),
+            ("tórax", "torax"), ("torbellino", "torbellin"), ("toreándolo", "tor"), ("tormentas", "torment"),
+            ("cantaríamos", "cant"),
+        ]);
+    }
+
+    #[test]
+    fn swedish_stems() {
+        assert_stems(Language::Swedish, &[
+            ("jaktbössa", "jaktböss"), ("jakten", "jakt"), ("jaktkarlarne", "jaktkarl"), ("jaktkarlens", "jaktkarl"),
+            ("jaktlöjtnantens", "jaktlöjtnant"), ("jalusierna", "jalusi"), ("jamaikanskt", "jamaikansk"),
+            ("klokaste", "klok"),
+        ]);
+    }
+}
@@ -0,0 +1,181 @@
+// Snowball Spanish stemmer (https://snowballstem.org/algorithms/spanish/stemmer.html)
+
+use crate::tokenizer::stemmers::Word;
+
+const PRONOUNS: [&str; 13] = [
+    "me", "se", "sela", "selo", "selas", "selos", "la", "le", "lo", "las", "les", "los", "nos",
+];
+
+// Verb forms which can be followed by an attached pronoun, with the accent they lose once it is removed
+const PRONOUN_VERB_FORMS: [(&str, &str); 11] = [
+    ("iéndo", "iendo"),
+    ("ándo", "ando"),
+    ("ár", "ar"),
+    ("ér", "er"),
+    ("ír", "ir"),
+    ("ando", "ando"),
+    ("iendo", "iendo"),
+    ("ar", "ar"),
+    ("er", "er"),
+    ("ir", "ir"),
+    ("yendo", "yendo"),
+];
+
+const STANDARD_SUFFIXES: [&str; 46] = [
+    "anza", "anzas", "ico", "ica", "icos", "icas", "ismo", "ismos", "able", "ables", "ible", "ibles", "ista", "istas",
+    "oso", "osa", "osos", "osas", "amiento", "amientos", "imiento", "imientos", "adora", "ador", "ación", "adoras",
+    "adores", "aciones", "ante", "antes", "ancia", "ancias", "logía", "logías", "ución", "uciones", "encia", "encias",
+    "amente", "mente", "idad", "idades", "iva", "ivo", "ivas", "ivos",
+];
+
+const Y_VERB_SUFFIXES: [&str; 12] = [
+    "ya", "ye", "yan", "yen", "yeron", "yendo", "yo", "yó", "yas", "yes", "yais", "yamos",
+];
+
+const VERB_SUFFIXES: [&str; 96] = [
+    "en", "es", "éis", "emos", "arían", "arías", "arán", "arás", "aríais", "aría", "aréis", "aríamos", "aremos", "ará",
+    "aré", "erían", "erías", "erán", "erás", "eríais", "ería", "eréis", "eríamos", "eremos", "erá", "eré", "irían",
+    "irías", "irán", "irás", "iríais", "iría", "iréis", "iríamos", "iremos", "irá", "iré", "aba", "ada", "ida", "ía",
+    "ara", "iera", "ad", "ed", "id", "ase", "iese", "aste", "iste", "an", "aban", "ían", "aran", "ieran", "asen",
+    "iesen", "aron", "ieron", "ado", "ido", "ando", "iendo", "ió", "ar", "er", "ir", "as", "abas", "adas", "idas",
+    "ías", "aras", "ieras", "ases", "ieses", "ís", "áis", "abais", "íais", "arais", "ierais", "aseis", "ieseis",
+    "asteis", "isteis", "ados", "idos", "amos", "ábamos", "íamos", "imos", "áramos", "iéramos", "iésemos", "ásemos",
+];
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'á' | 'é' | 'í' | 'ó' | 'ú' | 'ü')
+}
+
+pub fn stem(text: &str) -> String {
+    let mut word = Word::new(text);
+    let rv = word.rv_start(is_vowel);
+    let r1 = word.region_start(0, is_vowel);
+    let r2 = word.region_start(r1, is_vowel);
+
+    attached_pronoun(&mut word, rv);
+    if !standard_suffix(&mut word, r1, r2) && !y_verb_suffix(&mut word, rv) {
+        verb_suffix(&mut word, rv);
+    }
+    residual_suffix(&mut word, rv);
+
+    word.chars
+        .iter()
+        .map(|&c| match c {
+            'á' => 'a',
+            'é' => 'e',
+            'í' => 'i',
+            'ó' => 'o',
+            'ú' => 'u',
+            c => c,
+        })
+        .collect()
+}
+
+// Step 0: pronouns attached to gerunds and infinitives
+fn attached_pronoun(word: &mut Word, rv: usize) {
+    let Some(pronoun) = word.longest_suffix(&PRONOUNS) else {
+        return;
+    };
+    let pronoun_start = word.suffix_start(pronoun);
+    let verb_forms = PRONOUN_VERB_FORMS.map(|(verb_form, _)| verb_form);
+    let Some(verb_form) = word.longest_suffix_at(pronoun_start, &verb_forms) else {
+        return;
+    };
+    let verb_form_start = pronoun_start - verb_form.chars().count();
+    if verb_form_start < rv || (verb_form == "yendo" && word.char_before(verb_form_start) != Some('u')) {
+        return;
+    }
+
+    let (_, replacement) = PRONOUN_VERB_FORMS.iter().find(|(form, _)| *form == verb_form).unwrap();
+    word.chars.truncate(verb_form_start);
+    word.chars.extend(replacement.chars());
+}
+
+// Step 1: standard suffixes. Returns whether one was removed
+fn standard_suffix(word: &mut Word, r1: usize, r2: usize) -> bool {
+    let Some(suffix) = word.longest_suffix(&STANDARD_SUFFIXES) else {
+        return false;
+    };
+    let suffix_start = word.suffix_start(suffix);
+    let region_start = if suffix == "amente" { r1 } else { r2 };
+    if suffix_start < region_start {
+        return false;
+    }
+
+    match suffix {
+        "adora" | "ador" | "ación" | "adoras" | "adores" | "aciones" | "ante" | "antes" | "ancia" | "ancias" => {
+            word.remove_suffix(suffix);
+            word.remove_suffix_in("ic", r2);
+        }
+        "logía" | "logías" => word.replace_suffix(suffix, "log"),
+        "ución" | "uciones" => word.replace_suffix(suffix, "u"),
+        "encia" | "encias" => word.replace_suffix(suffix, "ente"),
+        "amente" => {
+            word.remove_suffix(suffix);
+            if let Some(suffix) = word.longest_suffix(&["iv", "os", "ic", "ad"]) {
+                if word.remove_suffix_in(suffix, r2) && suffix == "iv" {
+                    word.remove_suffix_in("at", r2);
+                }
+            }
+        }
+        "mente" => {
+            word.remove_suffix(suffix);
+            if let Some(suffix) = word.longest_suffix(&["ante", "able", "ible"]) {
+                word.remove_suffix_in(suffix, r2);
+            }
+        }
+        "idad" | "idades" => {
+            word.remove_suffix(suffix);
+            if let Some(suffix) = word.longest_suffix(&["abil", "ic", "iv"]) {
+                word.remove_suffix_in(suffix, r2);
+            }
+        }
+        "iva" | "ivo" | "ivas" | "ivos" => {
+            word.remove_suffix(suffix);
+            word.remove_suffix_in("at", r2);
+        }
+        _ => word.remove_suffix(suffix),
+    }
+
+    true
+}
+
+// Step 2a: verb suffixes beginning with "y", removed after a "u"
+fn y_verb_suffix(word: &mut Word, rv: usize) -> bool {
+    let Some(suffix) = word.longest_suffix_in(&Y_VERB_SUFFIXES, rv) else {
+        return false;
+    };
+    if word.char_before(word.suffix_start(suffix)) != Some('u') {
+        return false;
+    }
+
+    word.remove_suffix(suffix);
+    true
+}
+
+// Step 2b: other verb suffixes
+fn verb_suffix(word: &mut Word, rv: usize) {
+    let Some(suffix) = word.longest_suffix_in(&VERB_SUFFIXES, rv) else {
+        return;
+    };
+
+    word.remove_suffix(suffix);
+    if matches!(suffix, "en" | "es" | "éis" | "emos") && word.ends_with("gu") {
+        word.chars.pop();
+    }
+}
+
+// Step 3: residual suffixes
+fn residual_suffix(word: &mut Word, rv: usize) {
+    let Some(suffix) = word.longest_suffix(&["os", "a", "o", "á", "í", "ó", "e", "é"]) else {
+        return;
+    };
+    if word.suffix_start(suffix) < rv {
+        return;
+    }
+
+    word.remove_suffix(suffix);
+    if matches!(suffix, "e" | "é") && word.ends_with("gu") {
+        word.remove_suffix_in("u", rv);
+    }
+}
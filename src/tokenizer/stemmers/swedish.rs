@@ -0,0 +1,42 @@
+// Snowball Swedish stemmer (https://snowballstem.org/algorithms/swedish/stemmer.html)
+
+use crate::tokenizer::stemmers::Word;
+
+const MAIN_SUFFIXES: [&str; 37] = [
+    "a", "arna", "erna", "heterna", "orna", "ad", "e", "ade", "ande", "arne", "are", "aste", "en", "anden", "aren",
+    "heten", "ern", "ar", "er", "heter", "or", "as", "arnas", "ernas", "ornas", "es", "ades", "andes", "ens", "arens",
+    "hetens", "erns", "at", "andet", "het", "ast", "s",
+];
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'ä' | 'å' | 'ö')
+}
+
+fn is_s_ending(c: char) -> bool {
+    matches!(c, 'b' | 'c' | 'd' | 'f' | 'g' | 'h' | 'j' | 'k' | 'l' | 'm' | 'n' | 'o' | 'p' | 'r' | 't' | 'v' | 'y')
+}
+
+pub fn stem(text: &str) -> String {
+    let mut word = Word::new(text);
+    // R1 is adjusted so that at least 3 letters precede it
+    let r1 = word.region_start(0, is_vowel).max(3);
+
+    if let Some(suffix) = word.longest_suffix_in(&MAIN_SUFFIXES, r1) {
+        if suffix != "s" || word.char_before(word.suffix_start(suffix)).is_some_and(is_s_ending) {
+            word.remove_suffix(suffix);
+        }
+    }
+
+    if word.longest_suffix_in(&["dd", "gd", "nn", "dt", "gt", "kt", "tt"], r1).is_some() {
+        word.chars.pop();
+    }
+
+    match word.longest_suffix_in(&["lig", "ig", "els", "löst", "fullt"], r1) {
+        Some("löst") => word.replace_suffix("löst", "lös"),
+        Some("fullt") => word.replace_suffix("fullt", "full"),
+        Some(suffix) => word.remove_suffix(suffix),
+        None => {}
+    }
+
+    word.to_string()
+}
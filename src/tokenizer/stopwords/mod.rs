@@ -0,0 +1,130 @@
+// Stopword token filter, removing very common words (e.g. "the" or "of"), whose huge postings lists are costly to read
+// and barely affect the ranking. The built-in lists are the Snowball ones (https://snowballstem.org/), one file per
+// language, and custom lists can be loaded from files in the same format: a word per line, where everything after a
+// "|" is a comment
+//
+// The words are matched against the token texts as they reach the filter, so it usually goes after lowercasing. The
+// list itself is stored in the filter (and so in the index manifest), so that queries keep being analyzed like the
+// indexed fields even if the file changes
+
+use crate::tokenizer::{Language, Token, TokenFilter};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StopwordFilter {
+    stopwords: BTreeSet<String>,
+}
+
+impl StopwordFilter {
+    pub fn new(stopwords: impl IntoIterator<Item = String>) -> Self {
+        Self { stopwords: stopwords.into_iter().collect() }
+    }
+
+    /// Filter with the built-in list of a language
+    pub fn for_language(language: Language) -> Self {
+        let list = match language {
+            Language::English => include_str!("english.txt"),
+            Language::French => include_str!("french.txt"),
+            Language::German => include_str!("german.txt"),
+            Language::Italian => include_str!("italian.txt"),
+            Language::Spanish => include_str!("spanish.txt"),
+            Language::Swedish => include_str!("swedish.txt"),
+        };
+
+        Self::new(parse_list(list))
+    }
+
+    /// Filter with a custom list, read from a file
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let list = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read stopwords file {}", path.display()))?;
+
+        Ok(Self::new(parse_list(&list)))
+    }
+}
+
+impl TokenFilter for StopwordFilter {
+    // The remaining tokens keep their positions, so that phrases can't match across the removed ones
+    fn filter(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens.into_iter()
+            .filter(|token| !self.stopwords.contains(&token.text))
+            .collect()
+    }
+}
+
+/// Words of a stopwords list, ignoring comments and blank lines
+fn parse_list(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.lines()
+        .filter_map(|line| line.split('|').next())
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexing::postings_codec::Position;
+    use crate::test_index::{bm25f, body_docs, sorted_docids, TestIndex};
+    use crate::tokenizer::{Analyzer, LowercaseFilter, TokenFilterConfig, TokenizerConfig, UnicodeTokenizer};
+
+    fn tokens(text: &str) -> Vec<Token> {
+        text.split(' ').enumerate().map(|(position, text)| Token::new(text.to_string(), position as Position)).collect()
+    }
+
+    #[test]
+    fn stopwords_keep_position_gaps() {
+        let filtered = StopwordFilter::for_language(Language::English).filter(tokens("quick the fox of a dog"));
+
+        assert_eq!(filtered, vec![Token::new("quick".to_string(), 0), Token::new("fox".to_string(), 2), Token::new("dog".to_string(), 5)]);
+    }
+
+    #[test]
+    fn phrases_match_across_removed_stopwords() {
+        let index = TestIndex::new("stopword_phrases");
+        let mut indexer = index.indexer(&["body"]);
+        indexer.store_positions("body");
+        indexer.set_analyzer("body", Analyzer::new(
+            TokenizerConfig::Unicode(UnicodeTokenizer),
+            vec![
+                TokenFilterConfig::Lowercase(LowercaseFilter),
+                TokenFilterConfig::Stopword(StopwordFilter::for_language(Language::English)),
+            ],
+        ));
+        indexer.index(body_docs(&[("d1", "The quick the fox"), ("d2", "quick fox"), ("d3", "quick of fox"), ("d4", "quick the lazy fox")])).unwrap();
+
+        let retriever = index.retriever(&["body"]);
+        let phrase_tokens = retriever.analyzers()["body"].analyze("quick the fox");
+        let docids = sorted_docids(retriever.retrieval_phrase(&phrase_tokens, 0, &bm25f(&[("body", 0.75, 1.0)])).unwrap());
+
+        // The removed stopword still takes a position, so that neither "quick fox" nor "quick the lazy fox" match
+        assert_eq!(phrase_tokens, vec![Token::new("quick".to_string(), 0), Token::new("fox".to_string(), 2)]);
+        assert_eq!(docids, ["d1", "d3"]);
+    }
+
+    #[test]
+    fn lists_are_loaded_from_files() {
+        let lists_path = std::env::temp_dir().join(format!("stopword_lists_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&lists_path);
+        fs::create_dir_all(&lists_path).unwrap();
+
+        let list_path = lists_path.join("list.txt");
+        fs::write(&list_path, "foo   | a comment\n\n | a comment line\n  bar\nfoo bar\n").unwrap();
+        let empty_list_path = lists_path.join("empty.txt");
+        fs::write(&empty_list_path, "").unwrap();
+
+        let filter = StopwordFilter::from_file(&list_path).unwrap();
+        let empty_filter = StopwordFilter::from_file(&empty_list_path).unwrap();
+        let missing_filter = StopwordFilter::from_file(&lists_path.join("missing.txt"));
+        fs::remove_dir_all(&lists_path).unwrap();
+
+        assert_eq!(filter, StopwordFilter::new(["foo".to_string(), "bar".to_string(), "foo bar".to_string()]));
+        assert_eq!(filter.filter(tokens("foo baz bar")), vec![Token::new("baz".to_string(), 1)]);
+        assert_eq!(empty_filter.filter(tokens("foo baz")), tokens("foo baz"));
+        assert!(missing_filter.unwrap_err().to_string().starts_with("Failed to read stopwords file"));
+    }
+}
@@ -0,0 +1,69 @@
+// Tokenizer following the Unicode word boundaries (UAX #29, https://unicode.org/reports/tr29/), so that punctuation
+// splits words (e.g. "foo,bar" or "e-mail") and is dropped, while keeping together words like "can't" or "3.14". The
+// text is first normalized with NFKC, so that compatibility forms (e.g. "ﬁ" or full-width letters) become their
+// regular ones, and composed and decomposed accents are indexed alike
+
+use crate::indexing::postings_codec::Position;
+use crate::tokenizer::{Token, Tokenizer};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UnicodeTokenizer;
+
+impl Tokenizer for UnicodeTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        let text: String = text.nfkc().collect();
+
+        text.unicode_words()
+            .enumerate()
+            .map(|(position, word)| Token::new(word.to_string(), position as Position))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::{Analyzer, AsciiFoldingFilter, LowercaseFilter, TokenFilterConfig, TokenizerConfig};
+
+    fn texts(text: &str) -> Vec<String> {
+        UnicodeTokenizer.tokenize(text).into_iter().map(|token| token.text).collect()
+    }
+
+    #[test]
+    fn punctuation_splits_words() {
+        assert_eq!(texts("foo,bar"), ["foo", "bar"]);
+        assert_eq!(texts("e-mail"), ["e", "mail"]);
+        assert_eq!(texts("(Hello), world!"), ["Hello", "world"]);
+    }
+
+    #[test]
+    fn words_with_inner_punctuation_are_kept() {
+        assert_eq!(texts("can't stop 3.14"), ["can't", "stop", "3.14"]);
+    }
+
+    #[test]
+    fn positions_skip_punctuation() {
+        assert_eq!(UnicodeTokenizer.tokenize("foo, bar"), vec![Token::new("foo".to_string(), 0), Token::new("bar".to_string(), 1)]);
+    }
+
+    #[test]
+    fn compatibility_forms_are_normalized() {
+        assert_eq!(texts("ﬁle ＲＵＳＴ"), ["file", "RUST"]);
+    }
+
+    #[test]
+    fn folded_accents_match_unaccented_words() {
+        let analyzer = Analyzer::new(
+            TokenizerConfig::Unicode(UnicodeTokenizer),
+            vec![TokenFilterConfig::Lowercase(LowercaseFilter), TokenFilterConfig::AsciiFolding(AsciiFoldingFilter)],
+        );
+
+        // Composed and decomposed accents alike
+        assert_eq!(analyzer.analyze_texts("Café"), ["cafe"]);
+        assert_eq!(analyzer.analyze_texts("Cafe\u{301}"), ["cafe"]);
+        assert_eq!(analyzer.analyze_texts("cafe"), ["cafe"]);
+    }
+}
@@ -0,0 +1,17 @@
+// Tokenizer splitting the text by whitespace, keeping everything else (including punctuation) in the tokens
+
+use crate::indexing::postings_codec::Position;
+use crate::tokenizer::{Token, Tokenizer};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WhitespaceTokenizer;
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<Token> {
+        text.split_whitespace()
+            .enumerate()
+            .map(|(position, word)| Token::new(word.to_string(), position as Position))
+            .collect()
+    }
+}